@@ -0,0 +1,226 @@
+//! Software automatic gain control
+//!
+//! Watches the raw I/Q stream for clipping (samples pinned at full scale)
+//! and the demodulator noise floor for an under-driven front end, and
+//! steps the tuner gain up or down within configured limits.
+
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+use serde::Serialize;
+
+use crate::config::{Config, DeviceType};
+use crate::sample_format::SampleFormat;
+
+/// Gain steps supported by the R820T/R820T2 tuner in RTL-SDR dongles (dB)
+pub const RTLSDR_GAINS_DB: [f64; 29] = [
+    0.0, 0.9, 1.4, 2.7, 3.7, 7.7, 8.7, 12.5, 14.4, 15.7, 16.6, 19.7, 20.7, 22.9, 25.4, 28.0, 29.7,
+    32.8, 33.8, 36.4, 37.2, 38.6, 40.2, 42.1, 43.4, 43.9, 44.5, 48.0, 49.6,
+];
+
+/// HackRF LNA gain range and step (dB)
+pub const HACKRF_LNA_MAX_DB: u32 = 40;
+pub const HACKRF_LNA_STEP_DB: u32 = 8;
+/// HackRF VGA gain range and step (dB)
+pub const HACKRF_VGA_MAX_DB: u32 = 62;
+pub const HACKRF_VGA_STEP_DB: u32 = 2;
+
+/// I/Q components per AGC evaluation window (~1 s at 2 Msps)
+const AGC_WINDOW_SAMPLES: usize = 2_000_000 * 2;
+/// Clipped fraction above which gain is reduced
+const CLIP_RATIO_HIGH: f64 = 1e-4;
+/// Clipped fraction below which gain may be raised
+const CLIP_RATIO_LOW: f64 = 1e-6;
+/// Noise floor (magnitude units) below which the input is under-driven (~2 LSB)
+const NOISE_FLOOR_LOW: u16 = 720;
+/// Noise floor (magnitude units) above which the input is over-driven (~20 LSB)
+const NOISE_FLOOR_HIGH: u16 = 7200;
+/// Windows to ignore after a gain change while the capture restarts
+const SETTLE_WINDOWS: u32 = 1;
+
+/// AGC state shared with the display and stats consumers
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AgcStats {
+    /// Current gain in dB
+    pub gain_db: f64,
+    /// Number of gain changes made
+    pub changes: u64,
+    /// Clipped fraction in the last evaluation window
+    pub clip_ratio: f64,
+    /// Noise floor seen in the last evaluation window
+    pub noise_floor: u16,
+    /// Total clipped samples seen
+    pub clipped_samples: u64,
+    /// Total samples seen
+    pub samples: u64,
+}
+
+pub type SharedAgcStats = Arc<RwLock<AgcStats>>;
+
+/// Software AGC over a discrete set of gain steps
+pub struct Agc {
+    gains: Vec<f64>,
+    index: usize,
+    min_index: usize,
+    max_index: usize,
+    window_samples: usize,
+    window_clipped: usize,
+    settle: u32,
+    stats: SharedAgcStats,
+}
+
+impl Agc {
+    /// Create an AGC over `gains` (ascending), limited to `min_db..=max_db`,
+    /// starting at the step nearest to `initial_db`.
+    pub fn new(gains: Vec<f64>, initial_db: f64, min_db: f64, max_db: f64, stats: SharedAgcStats) -> Self {
+        let nearest = |db: f64| {
+            (0..gains.len())
+                .min_by(|&a, &b| (gains[a] - db).abs().total_cmp(&(gains[b] - db).abs()))
+                .unwrap_or(0)
+        };
+        let min_index = nearest(min_db);
+        let max_index = nearest(max_db).max(min_index);
+        let index = nearest(initial_db).clamp(min_index, max_index);
+
+        stats.write().gain_db = gains[index];
+
+        Self {
+            gains,
+            index,
+            min_index,
+            max_index,
+            window_samples: 0,
+            window_clipped: 0,
+            settle: 0,
+            stats,
+        }
+    }
+
+    /// Create an AGC with the gain steps and limits for the configured device
+    pub fn for_config(config: &Config, stats: SharedAgcStats) -> Self {
+        let (gains, initial) = match config.device_type {
+            DeviceType::RtlSdr | DeviceType::RtlTcp => (RTLSDR_GAINS_DB.to_vec(), config.gain as f64 / 10.0),
+            DeviceType::HackRf => {
+                let max = HACKRF_LNA_MAX_DB + HACKRF_VGA_MAX_DB;
+                let gains = (0..=max).step_by(HACKRF_VGA_STEP_DB as usize).map(f64::from).collect();
+                (gains, config.hackrf.total_gain_db())
+            }
+        };
+        Self::new(gains, initial, config.agc_min_gain, config.agc_max_gain, stats)
+    }
+
+    /// Current gain in dB
+    pub fn gain_db(&self) -> f64 {
+        self.gains[self.index]
+    }
+
+    /// Feed raw I/Q bytes in `format` and the current noise floor.
+    ///
+    /// Returns the new gain when the AGC decides to change it; the caller
+    /// is responsible for applying it to the device.
+    pub fn observe(&mut self, data: &[u8], format: SampleFormat, noise_floor: u16) -> Option<f64> {
+        let (clipped, samples) = format.count_clipped(data);
+        self.window_samples += samples;
+        self.window_clipped += clipped;
+
+        {
+            let mut stats = self.stats.write();
+            stats.samples += samples as u64;
+            stats.clipped_samples += clipped as u64;
+        }
+
+        if self.window_samples < AGC_WINDOW_SAMPLES {
+            return None;
+        }
+
+        let clip_ratio = self.window_clipped as f64 / self.window_samples as f64;
+        self.window_samples = 0;
+        self.window_clipped = 0;
+
+        {
+            let mut stats = self.stats.write();
+            stats.clip_ratio = clip_ratio;
+            stats.noise_floor = noise_floor;
+        }
+
+        if self.settle > 0 {
+            self.settle -= 1;
+            return None;
+        }
+
+        let new_index = if (clip_ratio > CLIP_RATIO_HIGH || noise_floor > NOISE_FLOOR_HIGH)
+            && self.index > self.min_index
+        {
+            self.index - 1
+        } else if clip_ratio < CLIP_RATIO_LOW
+            && noise_floor < NOISE_FLOOR_LOW
+            && self.index < self.max_index
+        {
+            self.index + 1
+        } else {
+            return None;
+        };
+
+        self.index = new_index;
+        self.settle = SETTLE_WINDOWS;
+
+        let mut stats = self.stats.write();
+        stats.gain_db = self.gain_db();
+        stats.changes += 1;
+        Some(self.gain_db())
+    }
+}
+
+/// Split a HackRF total gain into (LNA, VGA) settings, filling the LNA first
+pub fn hackrf_gain_split(total_db: f64) -> (u32, u32) {
+    let total = total_db.max(0.0).round() as u32;
+    let lna = (total.min(HACKRF_LNA_MAX_DB) / HACKRF_LNA_STEP_DB) * HACKRF_LNA_STEP_DB;
+    let vga = ((total - lna).min(HACKRF_VGA_MAX_DB) / HACKRF_VGA_STEP_DB) * HACKRF_VGA_STEP_DB;
+    (lna, vga)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_agc(initial: f64) -> Agc {
+        Agc::new(
+            RTLSDR_GAINS_DB.to_vec(),
+            initial,
+            0.0,
+            49.6,
+            Arc::new(RwLock::new(AgcStats::default())),
+        )
+    }
+
+    #[test]
+    fn test_agc_steps_down_on_clipping() {
+        let mut agc = test_agc(49.6);
+        let mut samples = vec![127u8; AGC_WINDOW_SAMPLES];
+        samples.iter_mut().step_by(100).for_each(|s| *s = 255);
+
+        assert_eq!(agc.observe(&samples, SampleFormat::Cu8, 1000), Some(48.0));
+        // Settling window after a change
+        assert_eq!(agc.observe(&samples, SampleFormat::Cu8, 1000), None);
+        assert_eq!(agc.observe(&samples, SampleFormat::Cu8, 1000), Some(44.5));
+        assert_eq!(agc.stats.read().changes, 2);
+    }
+
+    #[test]
+    fn test_agc_steps_up_when_underdriven() {
+        let mut agc = test_agc(20.0);
+        let samples = vec![127u8; AGC_WINDOW_SAMPLES];
+
+        assert_eq!(agc.observe(&samples, SampleFormat::Cu8, 100), Some(20.7));
+        // A healthy noise floor leaves the gain alone
+        agc.settle = 0;
+        assert_eq!(agc.observe(&samples, SampleFormat::Cu8, 2000), None);
+    }
+
+    #[test]
+    fn test_hackrf_gain_split() {
+        assert_eq!(hackrf_gain_split(52.0), (40, 12));
+        assert_eq!(hackrf_gain_split(20.0), (16, 4));
+        assert_eq!(hackrf_gain_split(200.0), (40, 62));
+    }
+}
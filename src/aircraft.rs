@@ -5,7 +5,51 @@
 use std::collections::HashMap;
 use std::time::{Duration, Instant};
 
-use crate::decoder::{BdsData, ModesMessage};
+use crate::decoder::{BdsData, ModesMessage, NavModes};
+use crate::sbs::{SbsKind, SbsRecord};
+
+/// How long Mode S data takes precedence over SBS data for the same field
+const MODES_PRECEDENCE: Duration = Duration::from_secs(30);
+
+/// Origin of a tracked value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataSource {
+    /// Decoded from Mode S frames (our own, or a raw/Beast feed)
+    ModeS,
+    /// Another receiver's SBS/BaseStation feed
+    Sbs,
+}
+
+/// Which source last set a field, and when
+#[derive(Debug, Clone, Copy)]
+pub struct FieldUpdate {
+    pub source: DataSource,
+    pub at: Instant,
+}
+
+/// Last update of each field that several sources can provide
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FieldSources {
+    pub flight: Option<FieldUpdate>,
+    pub altitude: Option<FieldUpdate>,
+    pub velocity: Option<FieldUpdate>,
+    pub position: Option<FieldUpdate>,
+    pub squawk: Option<FieldUpdate>,
+}
+
+/// Record an update of a field from `source`; returns false, leaving the
+/// field alone, for SBS data while there is recent Mode S data
+fn claim(slot: &mut Option<FieldUpdate>, source: DataSource, now: Instant) -> bool {
+    if source == DataSource::Sbs
+        && let Some(last) = slot
+        && last.source == DataSource::ModeS
+        && now.duration_since(last.at) < MODES_PRECEDENCE
+    {
+        return false;
+    }
+    *slot = Some(FieldUpdate { source, at: now });
+    true
+}
 
 /// Tracked aircraft data
 #[derive(Debug, Clone)]
@@ -19,6 +63,9 @@ pub struct Aircraft {
     pub flight: String,
     /// Altitude in feet
     pub altitude: i32,
+    /// Geometric (GNSS) altitude in feet (from ES position, or ES velocity
+    /// relative to the barometric altitude)
+    pub geom_altitude: Option<i32>,
     /// Ground speed in knots
     pub speed: u16,
     /// Track/heading in degrees
@@ -57,14 +104,30 @@ pub struct Aircraft {
     pub baro_altitude_rate: Option<i16>,
     /// MCP/FCU selected altitude (from BDS 4,0)
     pub selected_altitude: Option<u16>,
+    /// FMS selected altitude (from BDS 4,0)
+    pub fms_altitude: Option<u16>,
+    /// Active autopilot modes (from BDS 4,0)
+    pub nav_modes: Option<NavModes>,
     /// Barometric pressure setting (from BDS 4,0)
     pub baro_setting: Option<f32>,
+    /// Geometric (GNSS) altitude rate in ft/min (from ES velocity)
+    pub geom_altitude_rate: Option<i16>,
+    /// Emitter category, e.g. 0xA3 (from ES identification)
+    pub category: Option<u8>,
     /// Squawk code (identity) from DF5/DF21
     pub squawk: u16,
     /// Average signal level (magnitude)
     pub signal_level: u16,
     /// Count of phase-corrected messages
     pub phase_corrections: u32,
+    /// Mode A replies attributed to this aircraft by squawk
+    pub mode_ac_messages: u64,
+    /// Source and age of the shared fields
+    pub sources: FieldSources,
+    /// Last SBS record for this aircraft
+    pub sbs_seen: Option<Instant>,
+    /// SBS records merged
+    pub sbs_messages: u64,
 }
 
 impl Aircraft {
@@ -75,6 +138,7 @@ impl Aircraft {
             hex_addr: format!("{:06X}", addr),
             flight: String::new(),
             altitude: 0,
+            geom_altitude: None,
             speed: 0,
             track: 0,
             seen: now,
@@ -94,20 +158,47 @@ impl Aircraft {
             magnetic_heading: None,
             baro_altitude_rate: None,
             selected_altitude: None,
+            fms_altitude: None,
+            nav_modes: None,
             baro_setting: None,
+            geom_altitude_rate: None,
+            category: None,
             squawk: 0,
             signal_level: 0,
             phase_corrections: 0,
+            mode_ac_messages: 0,
+            sources: FieldSources::default(),
+            sbs_seen: None,
+            sbs_messages: 0,
         }
     }
 }
 
+/// A Mode A/C code heard recently
+#[derive(Debug, Clone)]
+pub struct ModeAcCode {
+    /// Squawk (Mode A) reading of the code
+    pub squawk: u16,
+    /// Altitude (Mode C) reading of the code, 0 if not a valid Gillham code
+    pub altitude: i32,
+    /// Number of replies received
+    pub messages: u64,
+    /// Last seen timestamp
+    pub seen: Instant,
+    /// Mode S aircraft currently squawking this code, if exactly one
+    pub addr: Option<u32>,
+}
+
 /// Store for tracking multiple aircraft
 pub struct AircraftStore {
     aircraft: HashMap<u32, Aircraft>,
+    /// Recent Mode A/C replies, keyed by squawk
+    mode_ac: HashMap<u16, ModeAcCode>,
     ttl: Duration,
     /// Minimum messages required before aircraft is considered confirmed
     min_messages: u64,
+    /// Mode S messages processed
+    messages: u64,
 }
 
 impl AircraftStore {
@@ -120,20 +211,34 @@ impl AircraftStore {
     pub fn with_min_messages(ttl_secs: u64, min_messages: u64) -> Self {
         Self {
             aircraft: HashMap::new(),
+            mode_ac: HashMap::new(),
             ttl: Duration::from_secs(ttl_secs),
             min_messages,
+            messages: 0,
         }
     }
 
     /// Update aircraft from a decoded message
     pub fn update_from_message(&mut self, mm: &ModesMessage) -> Option<&Aircraft> {
+        if mm.is_mode_ac() {
+            return self.update_from_mode_ac(mm);
+        }
+
+        self.messages += 1;
         let addr = mm.icao_address();
 
+        // A repaired address/parity message only ever updates an aircraft we
+        // already track; it must never be the first sighting (ghost aircraft)
+        if mm.is_address_parity() && mm.error_bit.is_some() && !self.aircraft.contains_key(&addr) {
+            return None;
+        }
+
         let aircraft = self
             .aircraft
             .entry(addr)
             .or_insert_with(|| Aircraft::new(addr));
-        aircraft.seen = Instant::now();
+        let now = Instant::now();
+        aircraft.seen = now;
         aircraft.messages += 1;
 
         // Track signal quality
@@ -152,32 +257,38 @@ impl AircraftStore {
         match mm.msg_type {
             0 | 4 | 16 | 20 => {
                 aircraft.altitude = mm.altitude;
+                claim(&mut aircraft.sources.altitude, DataSource::ModeS, now);
 
                 // Extract BDS data if present (DF20)
-                if mm.msg_type == 20 {
-                    if let Some(ref bds) = mm.bds_data {
-                        self.update_from_bds(addr, bds);
-                    }
+                if mm.msg_type == 20
+                    && let Some(ref bds) = mm.bds_data
+                {
+                    self.update_from_bds(addr, bds);
                 }
             }
             5 | 21 => {
                 // Store squawk (identity) code
                 if mm.identity != 0 {
                     aircraft.squawk = mm.identity;
+                    claim(&mut aircraft.sources.squawk, DataSource::ModeS, now);
                 }
                 
                 // Extract BDS data if present (DF21)
-                if mm.msg_type == 21 {
-                    if let Some(ref bds) = mm.bds_data {
-                        self.update_from_bds(addr, bds);
-                    }
+                if mm.msg_type == 21
+                    && let Some(ref bds) = mm.bds_data
+                {
+                    self.update_from_bds(addr, bds);
                 }
             }
             17 => {
                 if (1..=4).contains(&mm.me_type) {
+                    // Category set A-D for type codes 4-1, then the sub type
+                    aircraft.category = Some(((14 - mm.me_type) << 4) | mm.me_sub);
                     aircraft.flight = mm.flight.clone();
+                    claim(&mut aircraft.sources.flight, DataSource::ModeS, now);
                 } else if (9..=18).contains(&mm.me_type) {
                     aircraft.altitude = mm.altitude;
+                    claim(&mut aircraft.sources.altitude, DataSource::ModeS, now);
 
                     if mm.fflag {
                         aircraft.odd_cprlat = mm.raw_latitude;
@@ -198,10 +309,28 @@ impl AircraftStore {
                     if time_diff <= Duration::from_secs(10) {
                         self.decode_cpr(addr);
                     }
-                } else if mm.me_type == 19 {
+                } else if (20..=22).contains(&mm.me_type) {
+                    if mm.geom_altitude.is_some() {
+                        aircraft.geom_altitude = mm.geom_altitude;
+                    }
+                } else if mm.me_type == 19 && (1..=4).contains(&mm.me_sub) {
+                    if let Some(diff) = mm.gnss_baro_diff
+                        && aircraft.sources.altitude.is_some()
+                    {
+                        aircraft.geom_altitude = Some(aircraft.altitude + diff);
+                    }
                     if mm.me_sub == 1 || mm.me_sub == 2 {
                         aircraft.speed = mm.velocity;
                         aircraft.track = mm.heading as u16;
+                        claim(&mut aircraft.sources.velocity, DataSource::ModeS, now);
+                    }
+                    if let Some(rate) = mm.vertical_rate() {
+                        let rate = rate.clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+                        if mm.vert_rate_source == 0 {
+                            aircraft.geom_altitude_rate = Some(rate);
+                        } else {
+                            aircraft.baro_altitude_rate = Some(rate);
+                        }
                     }
                 }
             }
@@ -211,6 +340,96 @@ impl AircraftStore {
         self.aircraft.get(&addr)
     }
 
+    /// Merge a record from another receiver's SBS feed. Each field is taken
+    /// unless we decoded it ourselves within the last `MODES_PRECEDENCE`.
+    pub fn update_from_sbs(&mut self, rec: &SbsRecord) -> Option<&Aircraft> {
+        let now = Instant::now();
+
+        // Status lines only keep an aircraft we already know alive
+        if rec.kind == SbsKind::Sta {
+            if rec.status.as_deref() != Some("RM")
+                && let Some(aircraft) = self.aircraft.get_mut(&rec.hex)
+            {
+                aircraft.seen = now;
+                aircraft.sbs_seen = Some(now);
+            }
+            return self.aircraft.get(&rec.hex);
+        }
+
+        let aircraft = self
+            .aircraft
+            .entry(rec.hex)
+            .or_insert_with(|| Aircraft::new(rec.hex));
+        aircraft.seen = now;
+        aircraft.sbs_seen = Some(now);
+        aircraft.sbs_messages += 1;
+
+        let sources = &mut aircraft.sources;
+        if let Some(flight) = rec.callsign.as_deref().map(str::trim).filter(|f| !f.is_empty())
+            && claim(&mut sources.flight, DataSource::Sbs, now)
+        {
+            aircraft.flight = flight.to_string();
+        }
+        if let Some(altitude) = rec.altitude
+            && claim(&mut sources.altitude, DataSource::Sbs, now)
+        {
+            aircraft.altitude = altitude;
+        }
+        if (rec.ground_speed.is_some() || rec.track.is_some())
+            && claim(&mut sources.velocity, DataSource::Sbs, now)
+        {
+            aircraft.speed = rec.ground_speed.unwrap_or(aircraft.speed);
+            aircraft.track = rec.track.unwrap_or(aircraft.track);
+        }
+        if let Some((lat, lon)) = rec.position
+            && claim(&mut sources.position, DataSource::Sbs, now)
+        {
+            aircraft.lat = lat;
+            aircraft.lon = lon;
+        }
+        if let Some(squawk) = rec.squawk
+            && claim(&mut sources.squawk, DataSource::Sbs, now)
+        {
+            aircraft.squawk = squawk;
+        }
+
+        self.aircraft.get(&rec.hex)
+    }
+
+    /// Record a Mode A/C reply and attribute it to the Mode S aircraft
+    /// squawking the same code, if there is exactly one.
+    fn update_from_mode_ac(&mut self, mm: &ModesMessage) -> Option<&Aircraft> {
+        let mut matches = self
+            .aircraft
+            .values()
+            .filter(|a| a.squawk != 0 && a.squawk == mm.identity)
+            .map(|a| a.addr);
+        let addr = match (matches.next(), matches.next()) {
+            (Some(addr), None) => Some(addr),
+            _ => None,
+        };
+
+        let code = self.mode_ac.entry(mm.identity).or_insert_with(|| ModeAcCode {
+            squawk: mm.identity,
+            altitude: mm.altitude,
+            messages: 0,
+            seen: Instant::now(),
+            addr: None,
+        });
+        code.messages += 1;
+        code.seen = Instant::now();
+        code.addr = addr;
+
+        let aircraft = self.aircraft.get_mut(&addr?)?;
+        aircraft.mode_ac_messages += 1;
+        Some(aircraft)
+    }
+
+    /// Get recently heard Mode A/C codes
+    pub fn mode_ac_codes(&self) -> impl Iterator<Item = &ModeAcCode> {
+        self.mode_ac.values()
+    }
+
     /// Update aircraft with BDS data
     fn update_from_bds(&mut self, addr: u32, bds: &BdsData) {
         let aircraft = match self.aircraft.get_mut(&addr) {
@@ -219,19 +438,24 @@ impl AircraftStore {
         };
 
         match bds {
-            BdsData::AircraftIdentification { callsign } => {
-                if aircraft.flight.is_empty() {
-                    aircraft.flight = callsign.clone();
-                }
+            BdsData::AircraftIdentification { callsign } if aircraft.flight.is_empty() => {
+                aircraft.flight = callsign.clone();
             }
             BdsData::SelectedVerticalIntention {
                 mcp_altitude,
+                fms_altitude,
                 baro_setting,
-                ..
+                nav_modes,
             } => {
                 if let Some(alt) = mcp_altitude {
                     aircraft.selected_altitude = Some(*alt);
                 }
+                if let Some(alt) = fms_altitude {
+                    aircraft.fms_altitude = Some(*alt);
+                }
+                if let Some(modes) = nav_modes {
+                    aircraft.nav_modes = Some(*modes);
+                }
                 if let Some(baro) = baro_setting {
                     aircraft.baro_setting = Some(*baro);
                 }
@@ -281,15 +505,15 @@ impl AircraftStore {
     }
 
     /// Get aircraft by ICAO address
-    #[allow(dead_code)]
     pub fn get(&self, addr: u32) -> Option<&Aircraft> {
         self.aircraft.get(&addr)
     }
 
-    /// Get all aircraft that meet the minimum message threshold
+    /// Get all aircraft that meet the minimum message threshold. Aircraft
+    /// from SBS feeds were already confirmed by the sending receiver.
     pub fn all(&self) -> impl Iterator<Item = &Aircraft> {
         let min_msg = self.min_messages;
-        self.aircraft.values().filter(move |a| a.messages >= min_msg)
+        self.aircraft.values().filter(move |a| a.messages >= min_msg || a.sbs_messages > 0)
     }
 
     /// Get all aircraft including those below message threshold (for internal use)
@@ -303,6 +527,8 @@ impl AircraftStore {
         let now = Instant::now();
         self.aircraft
             .retain(|_, a| now.duration_since(a.seen) <= self.ttl);
+        self.mode_ac
+            .retain(|_, c| now.duration_since(c.seen) <= self.ttl);
     }
 
     /// Number of tracked aircraft (meeting minimum message threshold)
@@ -311,6 +537,11 @@ impl AircraftStore {
         self.all().count()
     }
 
+    /// Mode S messages processed since start
+    pub fn messages(&self) -> u64 {
+        self.messages
+    }
+
     /// Number of all tracked aircraft including below threshold
     #[allow(dead_code)]
     pub fn len_total(&self) -> usize {
@@ -349,6 +580,11 @@ impl AircraftStore {
             rlat1 -= 360.0;
         }
 
+        // An unpaired or corrupt frame gives an impossible latitude
+        if !(-90.0..=90.0).contains(&rlat0) || !(-90.0..=90.0).contains(&rlat1) {
+            return;
+        }
+
         if cpr_nl(rlat0) != cpr_nl(rlat1) {
             return;
         }
@@ -372,67 +608,7 @@ impl AircraftStore {
         if aircraft.lon > 180.0 {
             aircraft.lon -= 360.0;
         }
-    }
-
-    /// Generate JSON representation of all aircraft
-    #[allow(dead_code)]
-    pub fn to_json(&self) -> String {
-        let mut json = String::from("[\n");
-        let mut first = true;
-
-        for aircraft in self.aircraft.values() {
-            if aircraft.lat == 0.0 && aircraft.lon == 0.0 {
-                continue;
-            }
-
-            if !first {
-                json.push_str(",\n");
-            }
-            first = false;
-
-            // Build extended JSON with BDS data
-            let mut extra = String::new();
-
-            if let Some(ias) = aircraft.indicated_airspeed {
-                extra.push_str(&format!(r#","ias":{}"#, ias));
-            }
-            if let Some(tas) = aircraft.true_airspeed {
-                extra.push_str(&format!(r#","tas":{}"#, tas));
-            }
-            if let Some(mach) = aircraft.mach {
-                extra.push_str(&format!(r#","mach":{:.3}"#, mach));
-            }
-            if let Some(roll) = aircraft.roll_angle {
-                extra.push_str(&format!(r#","roll":{:.1}"#, roll));
-            }
-            if let Some(hdg) = aircraft.magnetic_heading {
-                extra.push_str(&format!(r#","mag_hdg":{:.1}"#, hdg));
-            }
-            if let Some(rate) = aircraft.baro_altitude_rate {
-                extra.push_str(&format!(r#","vert_rate":{}"#, rate));
-            }
-            if let Some(sel_alt) = aircraft.selected_altitude {
-                extra.push_str(&format!(r#","sel_alt":{}"#, sel_alt));
-            }
-            if let Some(baro) = aircraft.baro_setting {
-                extra.push_str(&format!(r#","baro":{:.1}"#, baro));
-            }
-
-            json.push_str(&format!(
-                r#"{{"hex":"{}","flight":"{}","lat": {},"lon":{},"altitude": {},"track":{},"speed":{}{}}}"#,
-                aircraft. hex_addr,
-                aircraft.flight,
-                aircraft.lat,
-                aircraft. lon,
-                aircraft.altitude,
-                aircraft.track,
-                aircraft.speed,
-                extra
-            ));
-        }
-
-        json.push_str("\n]");
-        json
+        claim(&mut aircraft.sources.position, DataSource::ModeS, Instant::now());
     }
 }
 
@@ -612,4 +788,45 @@ mod tests {
         assert!(store.is_empty());
         assert_eq!(store.len(), 0);
     }
+
+    #[test]
+    fn test_mode_ac_attribution() {
+        let mut store = AircraftStore::new(60);
+        store.update_from_message(&crate::decoder::decode_mode_ac(0x1200));
+        assert_eq!(store.len_total(), 0); // Never creates an aircraft
+
+        let mut ac = Aircraft::new(0x4840D6);
+        ac.squawk = 1200;
+        store.aircraft.insert(ac.addr, ac);
+
+        let attributed = store.update_from_message(&crate::decoder::decode_mode_ac(0x1200));
+        assert_eq!(attributed.map(|a| a.mode_ac_messages), Some(1));
+
+        let code = store.mode_ac_codes().next().unwrap();
+        assert_eq!(code.messages, 2);
+        assert_eq!(code.addr, Some(0x4840D6));
+    }
+
+    #[test]
+    fn test_sbs_merge_keeps_fresh_mode_s() {
+        let mut store = AircraftStore::new(60);
+        let position = crate::decoder::decode_hex_message("*8D40621D58C382D690C8AC2863A7;", true, true, false).unwrap();
+        store.update_from_message(&position);
+
+        let rec = SbsRecord::parse("MSG,3,1,1,40621D,1,,,,,EZY123,12000,,,51.5,-0.1,,7700,0,0,0,0").unwrap();
+        let ac = store.update_from_sbs(&rec).unwrap();
+        assert_eq!(ac.altitude, 38000); // Our own decode wins
+        assert_eq!(ac.flight, "EZY123"); // Fields we lack are taken
+        assert_eq!((ac.lat, ac.lon), (51.5, -0.1));
+        assert_eq!(ac.squawk, 7700);
+        assert_eq!(ac.sources.flight.map(|f| f.source), Some(DataSource::Sbs));
+        assert_eq!(ac.sources.altitude.map(|f| f.source), Some(DataSource::ModeS));
+
+        // SBS-only aircraft count as confirmed; status lines don't create any
+        store.update_from_sbs(&SbsRecord::parse("MSG,4,1,1,ABCDEF,1,,,,,,,420,90,,,,,,,,").unwrap());
+        store.update_from_sbs(&SbsRecord::parse("STA,,1,1,123456,1,,,,,OK").unwrap());
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.len_total(), 2);
+        assert_eq!(store.get(0xABCDEF).map(|a| a.speed), Some(420));
+    }
 }
@@ -0,0 +1,62 @@
+//! Retry delays
+//!
+//! Outbound connections and supervised capture tools retry after a
+//! failure, doubling the wait each time up to a limit and starting over
+//! once an attempt succeeds.
+
+use std::time::Duration;
+
+/// Wait after the first failure
+const BACKOFF_MIN: Duration = Duration::from_secs(1);
+/// Longest wait between attempts
+const BACKOFF_MAX: Duration = Duration::from_secs(60);
+
+/// Exponential backoff between attempts
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    next: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Backoff {
+    pub fn new() -> Self {
+        Self { next: BACKOFF_MIN }
+    }
+
+    /// Start over after a successful attempt
+    pub fn reset(&mut self) {
+        self.next = BACKOFF_MIN;
+    }
+
+    /// Delay before the next attempt; the one after it is twice as long
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.next;
+        self.next = (self.next * 2).min(BACKOFF_MAX);
+        delay
+    }
+
+    /// Wait out the delay before the next attempt
+    pub async fn wait(&mut self) {
+        tokio::time::sleep(self.next_delay()).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_doubles_up_to_max() {
+        let mut backoff = Backoff::new();
+        let delays: Vec<u64> = (0..8).map(|_| backoff.next_delay().as_secs()).collect();
+        assert_eq!(delays, vec![1, 2, 4, 8, 16, 32, 60, 60]);
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,280 @@
+//! Mode-S Beast binary format
+//!
+//! Each frame is `0x1A <type> <timestamp:6> <signal:1> <payload>`, where
+//! type '1' carries a Mode A/C reply (2 bytes), '2' a short Mode S message
+//! (7 bytes) and '3' a long one (14 bytes). The timestamp is a big-endian
+//! 12 MHz counter and the signal byte is the RSSI as amplitude scaled to
+//! 0-255. A 0x1A byte anywhere after the type byte is sent twice.
+
+use crate::config::Config;
+use crate::decoder::{self, MODES_TIMESTAMP_MASK, ModesMessage};
+use crate::sample_format::MAG_FULL_SCALE;
+
+/// Frame start marker and escape byte
+pub const BEAST_ESCAPE: u8 = 0x1A;
+
+/// Beast frame types carrying messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BeastFrameType {
+    /// Mode A/C reply
+    Ac,
+    /// 56-bit Mode S message
+    Short,
+    /// 112-bit Mode S message
+    Long,
+}
+
+impl BeastFrameType {
+    /// Parse a type byte; `None` for types we don't handle
+    pub fn from_byte(b: u8) -> Option<Self> {
+        match b {
+            b'1' => Some(Self::Ac),
+            b'2' => Some(Self::Short),
+            b'3' => Some(Self::Long),
+            _ => None,
+        }
+    }
+
+    pub fn type_byte(&self) -> u8 {
+        match self {
+            Self::Ac => b'1',
+            Self::Short => b'2',
+            Self::Long => b'3',
+        }
+    }
+
+    /// Payload length in bytes
+    pub fn payload_len(&self) -> usize {
+        match self {
+            Self::Ac => 2,
+            Self::Short => 7,
+            Self::Long => 14,
+        }
+    }
+}
+
+/// One Beast frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BeastFrame {
+    pub frame_type: BeastFrameType,
+    /// 48-bit 12 MHz timestamp
+    pub timestamp: u64,
+    /// RSSI, amplitude scaled to 0-255
+    pub signal: u8,
+    pub payload: Vec<u8>,
+}
+
+/// RSSI byte for a signal level in magnitude units
+pub fn signal_byte(level: u16) -> u8 {
+    (level as f32 / MAG_FULL_SCALE * 255.0).round().min(255.0) as u8
+}
+
+/// Signal level in magnitude units for an RSSI byte
+pub fn signal_level(byte: u8) -> u16 {
+    (byte as f32 / 255.0 * MAG_FULL_SCALE).round() as u16
+}
+
+impl BeastFrame {
+    /// Frame for a decoded message; `None` if its length has no Beast type
+    pub fn from_message(mm: &ModesMessage) -> Option<Self> {
+        let frame_type = if mm.is_mode_ac() {
+            BeastFrameType::Ac
+        } else {
+            match mm.msg_bits {
+                56 => BeastFrameType::Short,
+                112 => BeastFrameType::Long,
+                _ => return None,
+            }
+        };
+        Some(Self {
+            frame_type,
+            timestamp: mm.timestamp & MODES_TIMESTAMP_MASK,
+            signal: signal_byte(mm.signal_level),
+            payload: mm.msg[..frame_type.payload_len()].to_vec(),
+        })
+    }
+
+    /// Decode the frame payload, keeping its timestamp and signal level
+    pub fn to_message(&self, config: &Config) -> ModesMessage {
+        let mut mm = match self.frame_type {
+            BeastFrameType::Ac => decoder::decode_mode_ac(u16::from_be_bytes([self.payload[0], self.payload[1]])),
+            BeastFrameType::Short | BeastFrameType::Long => decoder::decode_modes_message(
+                &self.payload,
+                config.fix_errors,
+                config.aggressive,
+                config.fix_df,
+            ),
+        };
+        mm.timestamp = self.timestamp;
+        mm.signal_level = signal_level(self.signal);
+        mm
+    }
+
+    /// Append the escaped frame to `out`
+    pub fn encode_into(&self, out: &mut Vec<u8>) {
+        out.push(BEAST_ESCAPE);
+        out.push(self.frame_type.type_byte());
+        let ts = self.timestamp.to_be_bytes();
+        for &b in ts[2..].iter().chain([self.signal].iter()).chain(self.payload.iter()) {
+            out.push(b);
+            if b == BEAST_ESCAPE {
+                out.push(BEAST_ESCAPE);
+            }
+        }
+    }
+
+    /// The escaped frame
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(2 + 2 * (7 + self.payload.len()));
+        self.encode_into(&mut out);
+        out
+    }
+}
+
+/// Splits a Beast byte stream into frames, resynchronising on corrupt or
+/// unsupported frames
+#[derive(Debug, Default)]
+pub struct BeastDecoder {
+    buf: Vec<u8>,
+}
+
+impl BeastDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add received bytes
+    pub fn push(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+
+    /// Next complete frame, or `None` until more data arrives
+    pub fn next_frame(&mut self) -> Option<BeastFrame> {
+        loop {
+            // Find a frame start: 0x1A not followed by another 0x1A
+            let Some(start) = self.buf.iter().position(|&b| b == BEAST_ESCAPE) else {
+                self.buf.clear();
+                return None;
+            };
+            self.buf.drain(..start);
+            let &type_byte = self.buf.get(1)?;
+            if type_byte == BEAST_ESCAPE {
+                self.buf.drain(..2);
+                continue;
+            }
+            let Some(frame_type) = BeastFrameType::from_byte(type_byte) else {
+                self.buf.drain(..1);
+                continue;
+            };
+
+            // Unescape timestamp, signal and payload
+            let want = 7 + frame_type.payload_len();
+            let mut body = Vec::with_capacity(want);
+            let mut i = 2;
+            while body.len() < want {
+                let &b = self.buf.get(i)?;
+                if b == BEAST_ESCAPE {
+                    let &next = self.buf.get(i + 1)?;
+                    if next != BEAST_ESCAPE {
+                        break; // A new frame started: this one is truncated
+                    }
+                    i += 1;
+                }
+                body.push(b);
+                i += 1;
+            }
+            if body.len() < want {
+                self.buf.drain(..i);
+                continue;
+            }
+            self.buf.drain(..i);
+
+            let mut ts = [0u8; 8];
+            ts[2..].copy_from_slice(&body[..6]);
+            return Some(BeastFrame {
+                frame_type,
+                timestamp: u64::from_be_bytes(ts),
+                signal: body[6],
+                payload: body[7..].to_vec(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::{decode_hex_message, decode_mode_ac};
+
+    #[test]
+    fn test_encode_escapes() {
+        let frame = BeastFrame {
+            frame_type: BeastFrameType::Short,
+            timestamp: 0x1A_0000_0001,
+            signal: 0x1A,
+            payload: vec![0x5D, 0x48, 0x40, 0xD6, 0xF8, 0x74, 0x0F],
+        };
+        assert_eq!(
+            frame.encode(),
+            vec![
+                0x1A, b'2', 0x00, 0x1A, 0x1A, 0x00, 0x00, 0x00, 0x01, 0x1A, 0x1A, 0x5D, 0x48, 0x40, 0xD6, 0xF8,
+                0x74, 0x0F
+            ]
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut long = decode_hex_message("*8D4840D6202CC371C32CE0576098;", true, true, false).unwrap();
+        long.timestamp = 0xFFFF_1A1A_1A1A;
+        long.signal_level = 23040;
+        let short = decode_hex_message("*5D4840D6F8740F;", true, true, false).unwrap();
+        let mut mode_ac = decode_mode_ac(0x7700);
+        mode_ac.timestamp = 12_000_000;
+
+        let frames: Vec<BeastFrame> =
+            [&long, &short, &mode_ac].iter().map(|mm| BeastFrame::from_message(mm).unwrap()).collect();
+        assert_eq!(frames[0].signal, 128);
+        assert_eq!(frames[2].payload, vec![0x77, 0x00]);
+
+        // Junk and an unsupported status frame in front, fed a byte at a time
+        let mut stream = vec![0x00, 0x1A, b'4', 0x01];
+        for frame in &frames {
+            frame.encode_into(&mut stream);
+        }
+        let mut decoder = BeastDecoder::new();
+        let mut decoded = Vec::new();
+        for b in stream {
+            decoder.push(&[b]);
+            while let Some(frame) = decoder.next_frame() {
+                decoded.push(frame);
+            }
+        }
+        assert_eq!(decoded, frames);
+
+        // Timestamp and signal level survive decoding
+        let mm = decoded[0].to_message(&Config::default());
+        assert!(mm.crc_ok);
+        assert_eq!(mm.timestamp, 0xFFFF_1A1A_1A1A);
+        assert_eq!(signal_byte(mm.signal_level), 128);
+        assert_eq!(decoded[2].to_message(&Config::default()).identity, 7700);
+    }
+
+    #[test]
+    fn test_resync_after_truncated_frame() {
+        let frame = BeastFrame {
+            frame_type: BeastFrameType::Ac,
+            timestamp: 42,
+            signal: 9,
+            payload: vec![0x12, 0x34],
+        };
+        let mut stream = frame.encode();
+        stream.truncate(6);
+        frame.encode_into(&mut stream);
+
+        let mut decoder = BeastDecoder::new();
+        decoder.push(&stream);
+        assert_eq!(decoder.next_frame(), Some(frame));
+        assert_eq!(decoder.next_frame(), None);
+    }
+}
@@ -0,0 +1,157 @@
+//! Hot-loop benchmarks
+//!
+//! `adsb-rx bench` times the magnitude conversion and preamble search on a
+//! generated test signal, comparing the original per-block allocating LUT
+//! code with the buffer-reusing and vectorised versions. Run it on the
+//! target board with a release build to see the sample rate it can sustain.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use crate::generator::{self, DEFAULT_FRAMES, GeneratorConfig};
+use crate::magnitude::{MagnitudeLut, compute_magnitude_into, compute_magnitude_vector};
+use crate::sample_format::SampleFormat;
+use crate::simd;
+
+/// Bytes per block, as read from the SDR
+const BLOCK_BYTES: usize = 16 * 16384;
+
+/// Time `f` over `rounds` runs and return the fastest
+fn best_of(rounds: usize, mut f: impl FnMut()) -> Duration {
+    (0..rounds)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .min()
+        .unwrap_or_default()
+}
+
+/// Format one result line: time per run, ns per sample and throughput
+fn report(name: &str, elapsed: Duration, samples: usize, baseline: Duration) -> String {
+    let ns = elapsed.as_nanos() as f64 / samples as f64;
+    format!(
+        "  {:<28} {:>9.2} ms  {:>6.2} ns/sample  {:>8.1} Msps  {:>5.2}x",
+        name,
+        elapsed.as_secs_f64() * 1e3,
+        ns,
+        1e3 / ns,
+        baseline.as_secs_f64() / elapsed.as_secs_f64()
+    )
+}
+
+/// Entry point for `adsb-rx bench ...`
+pub fn run_cli(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut seconds = 1.0f64;
+    let mut rounds = 10;
+
+    let mut i = 0;
+    while i < args.len() {
+        let value = |i: usize| args.get(i).cloned().ok_or_else(|| format!("{} needs a value", args[i - 1]));
+        match args[i].as_str() {
+            "--seconds" => {
+                i += 1;
+                seconds = value(i)?.parse()?;
+            }
+            "--rounds" => {
+                i += 1;
+                rounds = value(i)?.parse()?;
+            }
+            "--help" => {
+                print_help();
+                return Ok(());
+            }
+            other => {
+                print_help();
+                return Err(format!("unknown option: {}", other).into());
+            }
+        }
+        i += 1;
+    }
+
+    // A busy channel: the default frames back to back at 12 dB SNR
+    let messages: Vec<_> =
+        DEFAULT_FRAMES.iter().map(|h| (generator::message_from_hex(h, None).unwrap(), 0.0)).collect();
+    let repeat = (seconds * 1e6 / 3.0 / 200.0).ceil() as usize;
+    let (emissions, duration_us) = generator::schedule(&messages, repeat, 200.0);
+    let config = GeneratorConfig { snr_db: Some(12.0), ..GeneratorConfig::default() };
+    let data = SampleFormat::Cu8.encode(&generator::generate(&config, &emissions, duration_us));
+    let samples = data.len() / 2;
+    let lut = MagnitudeLut::new();
+
+    println!(
+        "{} samples ({:.2} s at 2 Msps) in {} KiB blocks, best of {} rounds",
+        samples,
+        samples as f64 / 2e6,
+        BLOCK_BYTES / 1024,
+        rounds
+    );
+
+    println!("Magnitude conversion (cu8):");
+    let baseline = best_of(rounds, || {
+        for block in data.chunks(BLOCK_BYTES) {
+            black_box(compute_magnitude_vector(black_box(block), &lut));
+        }
+    });
+    println!("{}", report("LUT, new Vec per block", baseline, samples, baseline));
+    let mut out = Vec::new();
+    let reuse = best_of(rounds, || {
+        for block in data.chunks(BLOCK_BYTES) {
+            compute_magnitude_into(black_box(block), &lut, &mut out);
+            black_box(&out);
+        }
+    });
+    println!("{}", report("LUT, reused buffer", reuse, samples, baseline));
+    let mut lanes = vec![0u16; BLOCK_BYTES / 2];
+    let portable = best_of(rounds, || {
+        for block in data.chunks(BLOCK_BYTES) {
+            black_box(simd::magnitude_cu8_lanes(black_box(block), &mut lanes));
+        }
+    });
+    println!("{}", report("portable lanes", portable, samples, baseline));
+    let vectorised = best_of(rounds, || {
+        for block in data.chunks(BLOCK_BYTES) {
+            simd::magnitude_cu8(black_box(block), &lut, &mut out);
+            black_box(&out);
+        }
+    });
+    println!("{}", report("vectorised (used)", vectorised, samples, baseline));
+
+    println!("Preamble search:");
+    let magnitude = compute_magnitude_vector(&data, &lut);
+    let end = magnitude.len() - simd::PREAMBLE_SPAN + 1;
+    let mut candidates = Vec::new();
+    let baseline = best_of(rounds, || {
+        simd::preamble_candidates_scalar(black_box(&magnitude), end, &mut candidates);
+        black_box(&candidates);
+    });
+    let found = candidates.len();
+    println!("{}", report("scalar", baseline, samples, baseline));
+    let portable = best_of(rounds, || {
+        candidates.clear();
+        black_box(simd::preamble_candidates_lanes(black_box(&magnitude), end, &mut candidates));
+    });
+    println!("{}", report("portable lanes", portable, samples, baseline));
+    let vectorised = best_of(rounds, || {
+        simd::preamble_candidates(black_box(&magnitude), end, &mut candidates);
+        black_box(&candidates);
+    });
+    println!("{}", report("vectorised (used)", vectorised, samples, baseline));
+    println!("  {} candidates", found);
+
+    Ok(())
+}
+
+fn print_help() {
+    println!(
+        r#"Usage: adsb bench [options]
+
+Benchmark magnitude conversion and preamble search on a generated signal.
+Build with --release for meaningful numbers.
+
+OPTIONS:
+  --seconds <s>          Length of the test signal (default: 1)
+  --rounds <N>           Runs per measurement; the fastest is reported (default: 10)"#
+    );
+}
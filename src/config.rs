@@ -1,6 +1,16 @@
 //! Configuration and command-line argument parsing
 
 use std::env;
+use std::io;
+use std::path::Path;
+
+use crate::connector::ConnectorSpec;
+use crate::decoder::AvrFormat;
+use crate::hackrf::HackRfSettings;
+use crate::mqtt::MqttSettings;
+use crate::recorder::RecordTrigger;
+use crate::sample_format::SampleFormat;
+use crate::sigmf::{self, SigmfMeta};
 
 /// Supported SDR device types
 #[derive(Debug, Clone, Copy, PartialEq, Default)]
@@ -8,6 +18,8 @@ pub enum DeviceType {
     #[default]
     RtlSdr,
     HackRf,
+    /// Remote RTL-SDR served by rtl_tcp
+    RtlTcp,
 }
 
 #[derive(Debug, Clone)]
@@ -15,18 +27,48 @@ pub struct Config {
     // Device settings
     pub device_type: DeviceType,
     pub dev_index: u32,
+    /// Select the device by serial number instead of index
+    pub device_serial: Option<String>,
+    /// Restart the capture tool after this many seconds without samples
+    pub stall_timeout: u64,
+    /// rtl_tcp server address (host:port)
+    pub rtltcp_addr: String,
     pub gain: i32,
     pub enable_agc: bool,
+    /// Lowest gain the software AGC may select (dB)
+    pub agc_min_gain: f64,
+    /// Highest gain the software AGC may select (dB)
+    pub agc_max_gain: f64,
+    /// HackRF amp/LNA/VGA/filter settings
+    pub hackrf: HackRfSettings,
+    /// Sweep the gain range, dwelling this many seconds per step, and exit
+    pub gain_sweep: Option<u64>,
     pub freq: u32,
+    /// RTL-SDR crystal frequency correction (ppm)
+    pub ppm: i32,
 
     // Input
     pub filename: Option<String>,
     pub loop_file: bool,
+    /// I/Q encoding of the input file
+    pub sample_format: SampleFormat,
+    /// Input sample rate in Hz
+    pub sample_rate: u32,
+    /// Input options given on the command line
+    pub explicit: ExplicitOptions,
+    /// Track and remove the DC offset before computing magnitudes
+    pub dc_filter: bool,
+    /// Estimate and correct I/Q gain and phase imbalance
+    pub iq_balance: bool,
 
     // Processing
     pub fix_errors: bool,
     pub check_crc: bool,
     pub aggressive: bool,
+    /// Allow error correction to flip bits in the DF field
+    pub fix_df: bool,
+    /// Also detect Mode A/C replies
+    pub mode_ac: bool,
 
     // Output
     pub raw: bool,
@@ -44,17 +86,57 @@ pub struct Config {
     /// Receiver longitude (optional)
     pub receiver_lon: Option<f64>,
 
+    // I/Q recording
+    /// Directory to record raw I/Q to (live input only)
+    pub record_dir: Option<String>,
+    /// Rotate recordings after this many MB (0 = never)
+    pub record_max_size_mb: u64,
+    /// Rotate recordings after this many seconds (0 = never)
+    pub record_max_time: u64,
+    /// Write SigMF metadata alongside recordings
+    pub record_sigmf: bool,
+    /// Only record around these events
+    pub record_trigger: RecordTrigger,
+    /// Seconds kept from before a trigger
+    pub record_pre: u64,
+    /// Seconds recorded after the last trigger
+    pub record_post: u64,
+
     // Networking
     pub net: bool,
     pub net_only: bool,
     pub net_ro_port: u16,
+    /// AVR variant written on the raw output port
+    pub net_ro_format: AvrFormat,
     pub net_ri_port: u16,
     pub net_http_port: u16,
+    /// Directory of static files served over HTTP, ahead of the built-in map
+    pub net_http_dir: Option<String>,
     pub net_sbs_port: u16,
+    /// TCP port for Beast binary output
+    pub net_bo_port: u16,
+    /// TCP port for Beast binary input
+    pub net_bi_port: u16,
+    /// Remote Beast feeds (host:port) to connect to
+    pub net_beast_connect: Vec<String>,
+    /// TCP port for SBS/BaseStation input (0 = off)
+    pub net_sbs_in_port: u16,
+    /// Remote SBS/BaseStation feeds (host:port) to connect to
+    pub net_sbs_connect: Vec<String>,
+    /// Outbound feeds we push our messages to
+    pub net_connectors: Vec<ConnectorSpec>,
+
+    // MQTT
+    pub mqtt: MqttSettings,
 
     // Debug
     pub debug: DebugFlags,
     pub stats: bool,
+    /// Seconds between interval reports in live mode
+    pub stats_every: Option<u64>,
+    /// JSON lines file every report is appended to
+    pub stats_history: Option<String>,
+    pub stats_history_max_size_mb: u64,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -68,19 +150,41 @@ pub struct DebugFlags {
     pub js: bool,
 }
 
+/// Options set on the command line, which SigMF metadata must not override
+#[derive(Debug, Clone, Default)]
+pub struct ExplicitOptions {
+    pub sample_format: bool,
+    pub freq: bool,
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
             device_type: DeviceType::default(),
             dev_index: 0,
+            device_serial: None,
+            stall_timeout: 5,
+            rtltcp_addr: "127.0.0.1:1234".to_string(),
             gain: 999999, // Max gain
             enable_agc: false,
+            agc_min_gain: 0.0,
+            agc_max_gain: 1000.0, // Device maximum
+            hackrf: HackRfSettings::default(),
+            gain_sweep: None,
             freq: 1_090_000_000,
+            ppm: 0,
             filename: None,
             loop_file: false,
+            sample_format: SampleFormat::default(),
+            sample_rate: 2_000_000,
+            explicit: ExplicitOptions::default(),
+            dc_filter: false,
+            iq_balance: false,
             fix_errors: true,
             check_crc: true,
-            aggressive: false,
+            aggressive: true,
+            fix_df: false,
+            mode_ac: false,
             raw: false,
             onlyaddr: false,
             metric: true,
@@ -90,14 +194,33 @@ impl Default for Config {
             min_messages: 2,
             receiver_lat: None,
             receiver_lon: None,
+            record_dir: None,
+            record_max_size_mb: 1024,
+            record_max_time: 0,
+            record_sigmf: false,
+            record_trigger: RecordTrigger::default(),
+            record_pre: 10,
+            record_post: 10,
             net: false,
             net_only: false,
             net_ro_port: 30002,
+            net_ro_format: AvrFormat::Plain,
             net_ri_port: 30001,
             net_http_port: 8080,
+            net_http_dir: None,
             net_sbs_port: 30003,
+            net_bo_port: 30005,
+            net_bi_port: 30004,
+            net_beast_connect: Vec::new(),
+            net_sbs_in_port: 0,
+            net_sbs_connect: Vec::new(),
+            net_connectors: Vec::new(),
+            mqtt: MqttSettings::default(),
             debug: DebugFlags::default(),
             stats: false,
+            stats_every: None,
+            stats_history: None,
+            stats_history_max_size_mb: 10,
         }
     }
 }
@@ -107,6 +230,12 @@ impl Config {
         let args: Vec<String> = env::args().collect();
         let mut config = Config::default();
 
+        // HackRF options are resolved against --gain once all are known
+        let mut hackrf_lna = None;
+        let mut hackrf_vga = None;
+        let mut hackrf_amp = true;
+        let mut hackrf_bb_filter = None;
+
         let mut i = 1;
         while i < args.len() {
             match args[i].as_str() {
@@ -114,6 +243,14 @@ impl Config {
                     i += 1;
                     config.dev_index = args.get(i).and_then(|s| s.parse().ok()).unwrap_or(0);
                 }
+                "--device-serial" => {
+                    i += 1;
+                    config.device_serial = args.get(i).cloned();
+                }
+                "--stall-timeout" => {
+                    i += 1;
+                    config.stall_timeout = args.get(i).and_then(|s| s.parse().ok()).unwrap_or(5).max(1);
+                }
                 "--gain" => {
                     i += 1;
                     config.gain = args
@@ -123,23 +260,104 @@ impl Config {
                         .unwrap_or(999999);
                 }
                 "--enable-agc" => config.enable_agc = true,
+                "--agc-min-gain" => {
+                    i += 1;
+                    config.agc_min_gain = args.get(i).and_then(|s| s.parse().ok()).unwrap_or(0.0);
+                }
+                "--agc-max-gain" => {
+                    i += 1;
+                    config.agc_max_gain =
+                        args.get(i).and_then(|s| s.parse().ok()).unwrap_or(1000.0);
+                }
                 "--hackrf" => config.device_type = DeviceType::HackRf,
+                "--hackrf-lna" => {
+                    i += 1;
+                    hackrf_lna = args.get(i).and_then(|s| s.parse().ok());
+                }
+                "--hackrf-vga" => {
+                    i += 1;
+                    hackrf_vga = args.get(i).and_then(|s| s.parse().ok());
+                }
+                "--hackrf-amp" => {
+                    i += 1;
+                    hackrf_amp = !matches!(args.get(i).map(String::as_str), Some("off" | "0"));
+                }
+                "--hackrf-bb-filter" => {
+                    i += 1;
+                    hackrf_bb_filter = args.get(i).and_then(|s| s.parse().ok());
+                }
+                "--gain-sweep" => {
+                    i += 1;
+                    config.gain_sweep = Some(args.get(i).and_then(|s| s.parse().ok()).unwrap_or(10));
+                }
                 "--rtlsdr" => config.device_type = DeviceType::RtlSdr,
+                "--rtltcp" => {
+                    i += 1;
+                    config.device_type = DeviceType::RtlTcp;
+                    if let Some(addr) = args.get(i) {
+                        config.rtltcp_addr = addr.clone();
+                    }
+                }
+                "--ppm" => {
+                    i += 1;
+                    config.ppm = args.get(i).and_then(|s| s.parse().ok()).unwrap_or(0);
+                }
                 "--freq" => {
                     i += 1;
                     config.freq = args
                         .get(i)
                         .and_then(|s| s.parse().ok())
                         .unwrap_or(1_090_000_000);
+                    config.explicit.freq = true;
                 }
                 "--ifile" => {
                     i += 1;
                     config.filename = args.get(i).cloned();
                 }
                 "--loop" => config.loop_file = true,
+                "--iformat" => {
+                    i += 1;
+                    let name = args.get(i).map(String::as_str).unwrap_or("");
+                    config.sample_format = SampleFormat::from_name(name).unwrap_or_else(|| {
+                        eprintln!("Unknown sample format: {}", name);
+                        std::process::exit(1);
+                    });
+                    config.explicit.sample_format = true;
+                }
+                "--dc-filter" => config.dc_filter = true,
+                "--iq-balance" => config.iq_balance = true,
                 "--no-fix" => config.fix_errors = false,
                 "--no-crc-check" => config.check_crc = false,
                 "--raw" => config.raw = true,
+                "--record" => {
+                    i += 1;
+                    config.record_dir = args.get(i).cloned();
+                }
+                "--record-max-size" => {
+                    i += 1;
+                    config.record_max_size_mb = args.get(i).and_then(|s| s.parse().ok()).unwrap_or(1024);
+                }
+                "--record-max-time" => {
+                    i += 1;
+                    config.record_max_time = args.get(i).and_then(|s| s.parse().ok()).unwrap_or(0);
+                }
+                "--record-sigmf" => config.record_sigmf = true,
+                "--record-trigger" => {
+                    i += 1;
+                    let spec = args.get(i).map(String::as_str).unwrap_or("");
+                    if let Err(e) = config.record_trigger.add(spec) {
+                        eprintln!("{}", e);
+                        std::process::exit(1);
+                    }
+                }
+                "--record-pre" => {
+                    i += 1;
+                    config.record_pre = args.get(i).and_then(|s| s.parse().ok()).unwrap_or(10);
+                }
+                "--record-post" => {
+                    i += 1;
+                    config.record_post = args.get(i).and_then(|s| s.parse().ok()).unwrap_or(10);
+                }
                 "--net" => config.net = true,
                 "--net-only" => {
                     config.net = true;
@@ -149,6 +367,14 @@ impl Config {
                     i += 1;
                     config.net_ro_port = args.get(i).and_then(|s| s.parse().ok()).unwrap_or(30002);
                 }
+                "--net-ro-format" => {
+                    i += 1;
+                    let name = args.get(i).map(String::as_str).unwrap_or("");
+                    config.net_ro_format = AvrFormat::from_name(name).unwrap_or_else(|| {
+                        eprintln!("Unknown raw output format: {}", name);
+                        std::process::exit(1);
+                    });
+                }
                 "--net-ri-port" => {
                     i += 1;
                     config.net_ri_port = args.get(i).and_then(|s| s.parse().ok()).unwrap_or(30001);
@@ -157,14 +383,93 @@ impl Config {
                     i += 1;
                     config.net_http_port = args.get(i).and_then(|s| s.parse().ok()).unwrap_or(8080);
                 }
+                "--net-http-dir" => {
+                    i += 1;
+                    config.net_http_dir = args.get(i).cloned();
+                }
                 "--net-sbs-port" => {
                     i += 1;
                     config.net_sbs_port = args.get(i).and_then(|s| s.parse().ok()).unwrap_or(30003);
                 }
+                "--net-bo-port" => {
+                    i += 1;
+                    config.net_bo_port = args.get(i).and_then(|s| s.parse().ok()).unwrap_or(30005);
+                }
+                "--net-bi-port" => {
+                    i += 1;
+                    config.net_bi_port = args.get(i).and_then(|s| s.parse().ok()).unwrap_or(30004);
+                }
+                "--net-beast-connect" => {
+                    i += 1;
+                    config.net = true;
+                    if let Some(addr) = args.get(i) {
+                        config.net_beast_connect.push(addr.clone());
+                    }
+                }
+                "--net-sbs-in-port" => {
+                    i += 1;
+                    config.net_sbs_in_port = args.get(i).and_then(|s| s.parse().ok()).unwrap_or(0);
+                    if config.net_sbs_in_port != 0 {
+                        config.net = true;
+                    }
+                }
+                "--net-sbs-connect" => {
+                    i += 1;
+                    config.net = true;
+                    if let Some(addr) = args.get(i) {
+                        config.net_sbs_connect.push(addr.clone());
+                    }
+                }
+                "--net-connector" => {
+                    i += 1;
+                    config.net = true;
+                    match ConnectorSpec::parse(args.get(i).map(String::as_str).unwrap_or("")) {
+                        Ok(spec) => config.net_connectors.push(spec),
+                        Err(e) => {
+                            eprintln!("Invalid --net-connector: {}", e);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                "--mqtt" => {
+                    i += 1;
+                    config.mqtt.broker = args.get(i).cloned();
+                }
+                "--mqtt-prefix" => {
+                    i += 1;
+                    if let Some(prefix) = args.get(i) {
+                        config.mqtt.prefix = prefix.trim_end_matches('/').to_string();
+                    }
+                }
+                "--mqtt-qos" => {
+                    i += 1;
+                    config.mqtt.qos = args.get(i).and_then(|s| s.parse().ok()).filter(|&q| q <= 2).unwrap_or(0);
+                }
+                "--mqtt-user" => {
+                    i += 1;
+                    config.mqtt.username = args.get(i).cloned();
+                }
+                "--mqtt-password" => {
+                    i += 1;
+                    config.mqtt.password = args.get(i).cloned();
+                }
+                "--mqtt-client-id" => {
+                    i += 1;
+                    if let Some(id) = args.get(i) {
+                        config.mqtt.client_id = id.clone();
+                    }
+                }
+                "--mqtt-interval" => {
+                    i += 1;
+                    config.mqtt.interval = args.get(i).and_then(|s| s.parse().ok()).unwrap_or(5);
+                }
                 "--onlyaddr" => config.onlyaddr = true,
                 "--metric" => config.metric = true,
                 "--imperial" => config.metric = false,
                 "--aggressive" => config.aggressive = true,
+                "--no-aggressive" => config.aggressive = false,
+                "--fix-df" => config.fix_df = true,
+                "--modeac" => config.mode_ac = true,
                 "--interactive" => config.interactive = true,
                 "--interactive-rows" => {
                     i += 1;
@@ -188,6 +493,18 @@ impl Config {
                     config.receiver_lon = args.get(i).and_then(|s| s.parse().ok());
                 }
                 "--stats" => config.stats = true,
+                "--stats-every" => {
+                    i += 1;
+                    config.stats_every = args.get(i).and_then(|s| s.parse().ok()).filter(|&n| n > 0);
+                }
+                "--stats-history" => {
+                    i += 1;
+                    config.stats_history = args.get(i).cloned();
+                }
+                "--stats-history-max-size" => {
+                    i += 1;
+                    config.stats_history_max_size_mb = args.get(i).and_then(|s| s.parse().ok()).unwrap_or(10);
+                }
                 "--debug" => {
                     i += 1;
                     if let Some(flags) = args.get(i) {
@@ -218,8 +535,67 @@ impl Config {
             i += 1;
         }
 
+        if config.device_type == DeviceType::HackRf {
+            match HackRfSettings::resolve(hackrf_lna, hackrf_vga, hackrf_amp, hackrf_bb_filter, config.gain) {
+                Ok(settings) => config.hackrf = settings,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+
         config
     }
+
+    /// Pick up format, sample rate and frequency from the SigMF metadata
+    /// next to the input file, if there is any. `--iformat` and `--freq`
+    /// given on the command line take precedence.
+    ///
+    /// Accepts either half of a SigMF pair as `--ifile`.
+    pub fn apply_sigmf_meta(&mut self) -> io::Result<Option<SigmfMeta>> {
+        let Some(filename) = self.filename.clone() else {
+            return Ok(None);
+        };
+        let Some(meta_path) = sigmf::meta_path_for(&filename) else {
+            return Ok(None);
+        };
+
+        let meta = sigmf::read_meta(&meta_path)?;
+        if Path::new(&filename).extension().is_some_and(|e| e == "sigmf-meta") {
+            self.filename = Some(sigmf::data_path_for(&meta_path).to_string_lossy().into_owned());
+        }
+        if !self.explicit.sample_format {
+            self.sample_format = meta.format;
+        }
+        if let Some(rate) = meta.sample_rate {
+            self.sample_rate = rate;
+        }
+        if let Some(freq) = meta.frequency
+            && !self.explicit.freq
+        {
+            self.freq = freq;
+        }
+        Ok(Some(meta))
+    }
+
+    /// Command line options that disagree with the SigMF metadata
+    pub fn sigmf_conflicts(&self, meta: &SigmfMeta) -> Vec<String> {
+        let mut conflicts = Vec::new();
+        if self.explicit.sample_format && self.sample_format != meta.format {
+            conflicts.push(format!(
+                "--iformat {} overrides the recorded format {}",
+                self.sample_format, meta.format
+            ));
+        }
+        if let Some(freq) = meta.frequency
+            && self.explicit.freq
+            && self.freq != freq
+        {
+            conflicts.push(format!("--freq {} overrides the recorded frequency {}", self.freq, freq));
+        }
+        conflicts
+    }
 }
 
 fn print_help() {
@@ -231,14 +607,45 @@ Usage: adsb [OPTIONS]
 DEVICE OPTIONS:
   --rtlsdr               Use RTL-SDR device (default)
   --hackrf               Use HackRF One device
+  --rtltcp <host:port>   Read from an rtl_tcp server (e.g. 192.168.1.20:1234)
   --device-index <N>     Select device by index (default: 0)
+  --device-serial <S>    Select device by serial number (required to pick a HackRF)
+  --stall-timeout <s>    Restart the SDR tool after <s> seconds without samples (default: 5)
   --gain <db>            Set gain (default: max. Use -10 for auto-gain)
-  --enable-agc           Enable Automatic Gain Control
+  --enable-agc           Enable software Automatic Gain Control
+  --agc-min-gain <db>    Lowest gain the AGC may select (default: 0)
+  --agc-max-gain <db>    Highest gain the AGC may select (default: device max)
   --freq <hz>            Set frequency (default: 1090 MHz)
+  --ppm <N>              RTL-SDR/rtl_tcp frequency correction in ppm (default: 0)
+  --gain-sweep <s>       Step through the gain range, <s> seconds per step,
+                         print the message rate at each and exit
+
+HACKRF OPTIONS:
+  --gain <db>            Total LNA+VGA gain, 0-102 (split LNA first)
+  --hackrf-lna <db>      LNA (IF) gain, 0-40 in 8 dB steps (default: 32)
+  --hackrf-vga <db>      VGA (baseband) gain, 0-62 in 2 dB steps (default: 20)
+  --hackrf-amp <on|off>  RF amplifier, ~11 dB (default: on)
+  --hackrf-bb-filter <hz> Baseband filter bandwidth, e.g. 1750000 (default: auto)
 
 INPUT OPTIONS:
   --ifile <filename>     Read data from file (use '-' for stdin)
   --loop                 With --ifile, read the same file in a loop
+  --iformat <format>     Input sample format: cu8 (default), cs8/sc8, cs16, cf32.
+                         SigMF recordings (.sigmf-meta next to the data file)
+                         set format, sample rate and frequency automatically
+                         unless --iformat/--freq are given
+  --dc-filter            Track and remove the I/Q DC offset
+  --iq-balance           Also correct I/Q gain and phase imbalance
+
+RECORDING OPTIONS:
+  --record <dir>         Record raw I/Q from the SDR to files in <dir>
+  --record-max-size <MB> Start a new file after <MB> megabytes (default: 1024, 0 = never)
+  --record-max-time <s>  Start a new file after <s> seconds (default: never)
+  --record-sigmf         Write SigMF metadata (frequency, rate, gain) with each file
+  --record-trigger <ev>  Only record around events: 'emergency' (squawk 7500/7600/7700)
+                         and/or hex ICAO addresses, comma separated; may repeat
+  --record-pre <s>       With --record-trigger, seconds kept before the event (default: 10)
+  --record-post <s>      With --record-trigger, seconds recorded after it (default: 10)
 
 DISPLAY OPTIONS:
   --interactive          Interactive mode refreshing data on screen
@@ -253,30 +660,105 @@ NETWORKING:
   --net                  Enable networking
   --net-only             Enable just networking, no SDR device or file
   --net-ro-port <port>   TCP port for raw output (default: 30002)
+  --net-ro-format <fmt>  Raw output format: avr (default, '*'), avr-colon (':'),
+                         avr-mlat ('@' with 12 MHz timestamp), avr-tilde ('~' with
+                         timestamp) or avr-signal ('<' with timestamp and signal level)
   --net-ri-port <port>   TCP port for raw input (default: 30001)
+                         Accepts all five formats; '%' is read as '@'
   --net-http-port <port> HTTP server port (default: 8080)
+  --net-http-dir <dir>   Serve static files (e.g. tar1090) from <dir>, ahead of
+                         the built-in map
   --net-sbs-port <port>  TCP port for SBS output (default: 30003)
+  --net-bo-port <port>   TCP port for Beast binary output (default: 30005)
+  --net-bi-port <port>   TCP port for Beast binary input (default: 30004)
+  --net-beast-connect <host:port>
+                         Read a remote Beast feed, reconnecting as needed; may repeat
+  --net-sbs-in-port <port> TCP port for SBS/BaseStation input (default: off)
+  --net-sbs-connect <host:port>
+                         Read a remote SBS port 30003 feed, reconnecting as needed; may repeat
+  --net-connector <host:port,format>
+                         Push messages to a remote host as raw (or an avr-* format),
+                         beast, sbs or json (lines); reconnects and buffers; may repeat
+
+MQTT:
+  --mqtt <host[:port]>   Publish aircraft state and events to an MQTT broker (port 1883)
+  --mqtt-prefix <topic>  Topic prefix (default: adsb)
+  --mqtt-qos <0|1|2>     QoS of published messages (default: 0)
+  --mqtt-user <name>     User name for the broker
+  --mqtt-password <pw>   Password for the broker (with --mqtt-user)
+  --mqtt-client-id <id>  Client identifier (default: adsb-rx-<pid>)
+  --mqtt-interval <s>    Minimum seconds between updates of one aircraft (default: 5)
 
 FILTERING:
   --min-messages <N>     Min messages before showing aircraft (default: 2)
   --no-fix               Disable single-bit error correction
   --no-crc-check         Disable CRC check (discouraged)
-  --aggressive           More CPU for more messages
+  --aggressive           Enable two-bit error correction (default)
+  --no-aggressive        Disable two-bit error correction
+  --fix-df               Allow error correction to change DF bits
+  --modeac               Also decode Mode A/C replies
 
 POSITION:
   --lat <degrees>        Receiver latitude for distance calculation
   --lon <degrees>        Receiver longitude for distance calculation
 
 OTHER:
-  --stats                With --ifile print stats at exit
+  --stats                Print a statistics summary at exit (end of --ifile)
+  --stats-every <s>      Print interval statistics every <s> seconds in live mode
+  --stats-history <file> Append every statistics report to <file> as JSON lines
+  --stats-history-max-size <MB>
+                         Rotate the history file to <file>.1 .. .5 after <MB>
+                         megabytes (default: 10, 0 = never)
   --debug <flags>        Debug mode (d/D/c/C/p/n/j)
   --help                 Show this help
 
 EXAMPLES:
   adsb --interactive                    # RTL-SDR with interactive display
   adsb --hackrf --interactive           # HackRF One with interactive display
+  adsb --rtltcp pi.local:1234           # Remote dongle via rtl_tcp
   adsb --net --interactive              # With network output
   adsb --ifile recording.bin            # From a file
+  adsb --ifile rec.bin --iformat cs16   # From a 16-bit recording
+  adsb --ifile capture.sigmf-data       # From a SigMF recording
+  adsb generate --help                  # Synthetic test data generator
 "#
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_explicit_options_win() {
+        let dir = std::env::temp_dir().join(format!("adsb-config-sigmf-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let meta = dir.join("rec.sigmf-meta");
+        sigmf::write_meta(&meta, SampleFormat::Cs8, 2_400_000, 1_090_000_000, "HackRF", "2024-01-01T00:00:00Z").unwrap();
+        let data = dir.join("rec.sigmf-data").to_string_lossy().into_owned();
+
+        // Nothing given: everything comes from the metadata
+        let mut config = Config { filename: Some(data.clone()), freq: 0, ..Config::default() };
+        let read = config.apply_sigmf_meta().unwrap().unwrap();
+        assert_eq!(
+            (config.sample_format, config.sample_rate, config.freq),
+            (SampleFormat::Cs8, 2_400_000, 1_090_000_000)
+        );
+        assert!(config.sigmf_conflicts(&read).is_empty());
+
+        // --iformat cs16 --freq 1089000000 are kept and reported
+        let mut config =
+            Config { filename: Some(data), sample_format: SampleFormat::Cs16, freq: 1_089_000_000, ..Config::default() };
+        config.explicit.sample_format = true;
+        config.explicit.freq = true;
+        let read = config.apply_sigmf_meta().unwrap().unwrap();
+        assert_eq!(
+            (config.sample_format, config.sample_rate, config.freq),
+            (SampleFormat::Cs16, 2_400_000, 1_089_000_000)
+        );
+        assert_eq!(config.sigmf_conflicts(&read).len(), 2);
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
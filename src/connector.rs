@@ -0,0 +1,479 @@
+//! Outbound feed connectors
+//!
+//! Pushes our messages to a remote aggregator instead of waiting for it to
+//! connect, so a receiver behind NAT can feed out. Each connector dials
+//! `host:port`, sends every accepted message in its format, and reconnects
+//! with exponential backoff. While disconnected, encoded messages are kept
+//! in a bounded replay buffer and sent first after the next connect.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Instant, SystemTime};
+
+use parking_lot::RwLock;
+use serde_json::{Value, json};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+
+use crate::aircraft::AircraftStore;
+use crate::backoff::Backoff;
+use crate::beast::{BeastFrame, signal_byte};
+use crate::decoder::{AvrFormat, ModesMessage};
+use crate::sbs;
+
+/// Messages kept while disconnected; the oldest are dropped beyond this
+pub const REPLAY_CAPACITY: usize = 10_000;
+
+/// Wire format of a connector
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectorFormat {
+    /// AVR text frames, one per line
+    Raw(AvrFormat),
+    /// Mode-S Beast binary
+    Beast,
+    /// SBS/BaseStation MSG lines
+    Sbs,
+    /// One JSON object per line
+    Json,
+}
+
+impl ConnectorFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "raw" | "avr" => Some(Self::Raw(AvrFormat::Plain)),
+            "avr-colon" => Some(Self::Raw(AvrFormat::Colon)),
+            "avr-mlat" => Some(Self::Raw(AvrFormat::Mlat)),
+            "avr-tilde" => Some(Self::Raw(AvrFormat::Tilde)),
+            "avr-signal" => Some(Self::Raw(AvrFormat::Signal)),
+            "beast" => Some(Self::Beast),
+            "sbs" => Some(Self::Sbs),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Raw(AvrFormat::Plain) => "raw",
+            Self::Raw(AvrFormat::Colon) => "avr-colon",
+            Self::Raw(AvrFormat::Mlat) => "avr-mlat",
+            Self::Raw(AvrFormat::Tilde) => "avr-tilde",
+            Self::Raw(AvrFormat::Signal) => "avr-signal",
+            Self::Beast => "beast",
+            Self::Sbs => "sbs",
+            Self::Json => "json",
+        }
+    }
+}
+
+/// A configured connector: `host:port,format`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectorSpec {
+    pub addr: String,
+    pub format: ConnectorFormat,
+}
+
+impl ConnectorSpec {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let (addr, format) = s.rsplit_once(',').ok_or_else(|| format!("expected host:port,format: {}", s))?;
+        if !addr.contains(':') {
+            return Err(format!("expected host:port: {}", addr));
+        }
+        let format = ConnectorFormat::from_name(format).ok_or_else(|| format!("unknown connector format: {}", format))?;
+        Ok(Self { addr: addr.to_string(), format })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConnectorState {
+    #[default]
+    Connecting,
+    Connected,
+    /// Waiting before the next attempt
+    Backoff,
+}
+
+impl fmt::Display for ConnectorState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Connecting => "connecting",
+            Self::Connected => "connected",
+            Self::Backoff => "backoff",
+        })
+    }
+}
+
+/// Per-connector status for the stats consumers
+#[derive(Debug, Clone)]
+pub struct ConnectorStatus {
+    pub addr: String,
+    pub format: ConnectorFormat,
+    pub state: ConnectorState,
+    /// Successful connects
+    pub connects: u64,
+    /// Messages written to the socket
+    pub messages_sent: u64,
+    pub bytes_sent: u64,
+    /// Messages lost to a full replay buffer or a lagging channel
+    pub dropped: u64,
+    /// Messages waiting in the replay buffer
+    pub buffered: usize,
+    pub last_error: Option<String>,
+    /// When the current connection was made
+    pub connected_since: Option<Instant>,
+}
+
+impl ConnectorStatus {
+    pub fn shared(spec: &ConnectorSpec) -> SharedConnectorStatus {
+        Arc::new(RwLock::new(Self {
+            addr: spec.addr.clone(),
+            format: spec.format,
+            state: ConnectorState::default(),
+            connects: 0,
+            messages_sent: 0,
+            bytes_sent: 0,
+            dropped: 0,
+            buffered: 0,
+            last_error: None,
+            connected_since: None,
+        }))
+    }
+}
+
+impl fmt::Display for ConnectorStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}): {}, {} sent, {} buffered, {} dropped, {} connects",
+            self.addr,
+            self.format.name(),
+            self.state,
+            self.messages_sent,
+            self.buffered,
+            self.dropped,
+            self.connects
+        )?;
+        if self.state != ConnectorState::Connected
+            && let Some(e) = &self.last_error
+        {
+            write!(f, ", last error: {}", e)?;
+        }
+        Ok(())
+    }
+}
+
+pub type SharedConnectorStatus = Arc<RwLock<ConnectorStatus>>;
+
+/// One message as a JSON object
+pub fn message_json(mm: &ModesMessage) -> Value {
+    let raw: String = mm.msg[..mm.msg_bits / 8].iter().map(|b| format!("{:02X}", b)).collect();
+    if mm.is_mode_ac() {
+        return json!({
+            "mode_ac": true,
+            "squawk": format!("{:04}", mm.identity),
+            "raw": raw,
+            "timestamp": mm.timestamp,
+            "signal": signal_byte(mm.signal_level),
+        });
+    }
+
+    let mut obj = json!({
+        "hex": format!("{:06X}", mm.icao_address()),
+        "df": mm.msg_type,
+        "raw": raw,
+        "crc_ok": mm.crc_ok,
+        "timestamp": mm.timestamp,
+        "signal": signal_byte(mm.signal_level),
+    });
+    let map = obj.as_object_mut().expect("object literal");
+    match mm.msg_type {
+        0 | 4 | 16 | 20 => {
+            map.insert("altitude".into(), json!(mm.altitude));
+        }
+        5 | 21 => {
+            map.insert("squawk".into(), json!(format!("{:04}", mm.identity)));
+        }
+        17 => {
+            map.insert("me_type".into(), json!(mm.me_type));
+            if (1..=4).contains(&mm.me_type) {
+                map.insert("flight".into(), json!(mm.flight));
+            } else if (9..=18).contains(&mm.me_type) {
+                map.insert("altitude".into(), json!(mm.altitude));
+            } else if mm.me_type == 19 && (1..=4).contains(&mm.me_sub) {
+                map.insert("speed".into(), json!(mm.velocity));
+                map.insert("track".into(), json!((mm.heading * 10.0).round() / 10.0));
+                if let Some(rate) = mm.vertical_rate() {
+                    map.insert("vert_rate".into(), json!(rate));
+                }
+            }
+        }
+        _ => {}
+    }
+    obj
+}
+
+/// Encode a message in `format`; `None` if the format can't carry it
+pub fn encode(format: ConnectorFormat, mm: &ModesMessage, store: &RwLock<AircraftStore>) -> Option<Vec<u8>> {
+    match format {
+        ConnectorFormat::Raw(avr) => Some((mm.to_avr_string(avr) + "\n").into_bytes()),
+        ConnectorFormat::Beast => BeastFrame::from_message(mm).map(|frame| frame.encode()),
+        ConnectorFormat::Sbs => {
+            if mm.is_mode_ac() {
+                return None;
+            }
+            let store = store.read();
+            sbs::format_message(mm, store.get(mm.icao_address()), SystemTime::now()).map(|l| (l + "\r\n").into_bytes())
+        }
+        ConnectorFormat::Json => Some((message_json(mm).to_string() + "\n").into_bytes()),
+    }
+}
+
+/// Bounded FIFO of encoded messages awaiting a connection
+#[derive(Debug, Default)]
+pub struct ReplayBuffer {
+    frames: VecDeque<Vec<u8>>,
+    capacity: usize,
+}
+
+impl ReplayBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self { frames: VecDeque::new(), capacity }
+    }
+
+    /// Queue a frame; returns true if the oldest had to be dropped
+    pub fn push(&mut self, frame: Vec<u8>) -> bool {
+        let full = self.frames.len() >= self.capacity;
+        if full {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(frame);
+        full
+    }
+
+    pub fn front(&self) -> Option<&Vec<u8>> {
+        self.frames.front()
+    }
+
+    pub fn pop_front(&mut self) -> Option<Vec<u8>> {
+        self.frames.pop_front()
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+}
+
+/// Encodes messages for one connector, buffering them while offline
+struct Outbox {
+    format: ConnectorFormat,
+    store: Arc<RwLock<AircraftStore>>,
+    buffer: ReplayBuffer,
+    status: SharedConnectorStatus,
+}
+
+impl Outbox {
+    /// Handle a broadcast result while not connected
+    fn offline(&mut self, msg: Result<ModesMessage, broadcast::error::RecvError>) -> bool {
+        match msg {
+            Ok(mm) => {
+                if let Some(frame) = encode(self.format, &mm, &self.store) {
+                    let dropped = self.buffer.push(frame);
+                    let mut status = self.status.write();
+                    status.dropped += dropped as u64;
+                    status.buffered = self.buffer.len();
+                }
+                true
+            }
+            Err(broadcast::error::RecvError::Lagged(n)) => {
+                self.status.write().dropped += n;
+                true
+            }
+            Err(broadcast::error::RecvError::Closed) => false,
+        }
+    }
+
+    fn sent(&self, bytes: usize) {
+        let mut status = self.status.write();
+        status.messages_sent += 1;
+        status.bytes_sent += bytes as u64;
+        status.buffered = self.buffer.len();
+    }
+
+    /// Replay the buffer, then stream live messages until the connection
+    /// fails. A frame that could not be written stays queued.
+    async fn serve(&mut self, socket: TcpStream, rx: &mut broadcast::Receiver<ModesMessage>) -> std::io::Result<()> {
+        let (mut reader, mut writer) = socket.into_split();
+
+        while let Some(frame) = self.buffer.front() {
+            writer.write_all(frame).await?;
+            let len = frame.len();
+            self.buffer.pop_front();
+            self.sent(len);
+        }
+
+        let mut discard = [0u8; 512];
+        loop {
+            tokio::select! {
+                msg = rx.recv() => match msg {
+                    Ok(mm) => {
+                        let Some(frame) = encode(self.format, &mm, &self.store) else {
+                            continue;
+                        };
+                        if let Err(e) = writer.write_all(&frame).await {
+                            self.buffer.push(frame);
+                            self.status.write().buffered = self.buffer.len();
+                            return Err(e);
+                        }
+                        self.sent(frame.len());
+                    }
+                    Err(broadcast::error::RecvError::Lagged(n)) => self.status.write().dropped += n,
+                    Err(broadcast::error::RecvError::Closed) => return Ok(()),
+                },
+                // Aggregators don't talk back; a read only ends with close or error
+                n = reader.read(&mut discard) => {
+                    if n? == 0 {
+                        return Err(std::io::Error::new(std::io::ErrorKind::ConnectionReset, "closed by peer"));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Run one connector until the message channel closes
+pub async fn run_connector(
+    spec: ConnectorSpec,
+    mut rx: broadcast::Receiver<ModesMessage>,
+    store: Arc<RwLock<AircraftStore>>,
+    status: SharedConnectorStatus,
+) {
+    let name = spec.format.name();
+    let mut outbox = Outbox {
+        format: spec.format,
+        store,
+        buffer: ReplayBuffer::new(REPLAY_CAPACITY),
+        status: Arc::clone(&status),
+    };
+    let mut backoff = Backoff::new();
+
+    loop {
+        status.write().state = ConnectorState::Connecting;
+        let connect = TcpStream::connect(&spec.addr);
+        tokio::pin!(connect);
+        let result = loop {
+            tokio::select! {
+                result = &mut connect => break result,
+                msg = rx.recv() => if !outbox.offline(msg) {
+                    return;
+                },
+            }
+        };
+
+        match result {
+            Ok(socket) => {
+                socket.set_nodelay(true).ok();
+                info!("Connector {} ({}) connected", spec.addr, name);
+                backoff.reset();
+                {
+                    let mut status = status.write();
+                    status.state = ConnectorState::Connected;
+                    status.connects += 1;
+                    status.connected_since = Some(Instant::now());
+                }
+                let error = match outbox.serve(socket, &mut rx).await {
+                    Ok(()) => return,
+                    Err(e) => e,
+                };
+                warn!("Connector {} ({}) disconnected: {}", spec.addr, name, error);
+                let mut status = status.write();
+                status.last_error = Some(error.to_string());
+                status.connected_since = None;
+            }
+            Err(e) => {
+                warn!("Connector {} ({}) cannot connect: {}", spec.addr, name, e);
+                status.write().last_error = Some(e.to_string());
+            }
+        }
+
+        status.write().state = ConnectorState::Backoff;
+        let sleep = tokio::time::sleep(backoff.next_delay());
+        tokio::pin!(sleep);
+        loop {
+            tokio::select! {
+                _ = &mut sleep => break,
+                msg = rx.recv() => if !outbox.offline(msg) {
+                    return;
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::decoder::decode_hex_message;
+    use tokio::io::AsyncBufReadExt;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_parse_spec() {
+        let spec = ConnectorSpec::parse("feed.example.net:30004,beast").unwrap();
+        assert_eq!(spec.addr, "feed.example.net:30004");
+        assert_eq!(spec.format, ConnectorFormat::Beast);
+        assert_eq!(ConnectorSpec::parse("[::1]:30001,avr-mlat").unwrap().format, ConnectorFormat::Raw(AvrFormat::Mlat));
+        assert_eq!(ConnectorSpec::parse("host:1,avr-tilde").unwrap().format, ConnectorFormat::Raw(AvrFormat::Tilde));
+        assert!(ConnectorSpec::parse("host:1,xml").is_err());
+        assert!(ConnectorSpec::parse("host,json").is_err());
+    }
+
+    #[test]
+    fn test_replay_buffer_drops_oldest() {
+        let mut buffer = ReplayBuffer::new(2);
+        assert!(!buffer.push(vec![1]));
+        assert!(!buffer.push(vec![2]));
+        assert!(buffer.push(vec![3]));
+        assert_eq!(buffer.pop_front(), Some(vec![2]));
+        assert_eq!(buffer.len(), 1);
+    }
+
+    #[test]
+    fn test_json_line() {
+        let mm = decode_hex_message("*8D4840D6202CC371C32CE0576098;", true, true, false).unwrap();
+        let v = message_json(&mm);
+        assert_eq!(v["hex"], "4840D6");
+        assert_eq!(v["df"], 17);
+        assert_eq!(v["flight"], "KLM1023");
+        assert_eq!(v["raw"], "8D4840D6202CC371C32CE0576098");
+    }
+
+    #[tokio::test]
+    async fn test_replays_after_connect() {
+        // Reserve a port, then leave it closed so the first attempt fails
+        let port = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().port();
+        let spec = ConnectorSpec::parse(&format!("127.0.0.1:{},raw", port)).unwrap();
+        let status = ConnectorStatus::shared(&spec);
+        let store = Arc::new(RwLock::new(AircraftStore::new(60)));
+        let (tx, rx) = broadcast::channel(16);
+        let task = tokio::spawn(run_connector(spec, rx, store, Arc::clone(&status)));
+
+        let mm = decode_hex_message("*5D4840D6F8740F;", true, true, false).unwrap();
+        while status.read().state != ConnectorState::Backoff {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        tx.send(mm.clone()).unwrap();
+        tx.send(mm).unwrap();
+
+        let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+        let (socket, _) = listener.accept().await.unwrap();
+        let mut lines = tokio::io::BufReader::new(socket).lines();
+        assert_eq!(lines.next_line().await.unwrap().as_deref(), Some("*5D4840D6F8740F;"));
+        assert_eq!(lines.next_line().await.unwrap().as_deref(), Some("*5D4840D6F8740F;"));
+        assert_eq!(status.read().connects, 1);
+        task.abort();
+    }
+}
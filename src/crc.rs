@@ -1,9 +1,14 @@
 //! CRC-24 implementation for Mode S messages
 //!
 //! This module ports the CRC calculation from the original C code.
-//! The CRC is computed by XORing precomputed values for each set bit.
+//! The CRC is computed a byte at a time from a 256-entry table; the
+//! original per-bit table is kept as a reference implementation.
+//! Error correction uses precomputed syndrome tables for 1- and 2-bit errors.
 
-/// Precomputed CRC table for Mode S messages.
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// Precomputed per-bit CRC table for Mode S messages (reference implementation).
 /// Each entry corresponds to a bit position in the message.
 /// For 112-bit messages, all entries are used.
 /// For 56-bit messages, only the last 56 entries are used.
@@ -26,6 +31,72 @@ pub const MODES_CHECKSUM_TABLE: [u32; 112] = [
     0x000000, 0x000000, 0x000000, 0x000000,
 ];
 
+/// Mode S CRC-24 generator polynomial (x^24 term implied)
+pub const MODES_GENERATOR_POLY: u32 = 0xfff409;
+
+/// Byte-wise CRC table: entry `i` is the CRC contribution of byte `i`
+/// entering the top of the 24-bit register.
+const MODES_CRC_BYTE_TABLE: [u32; 256] = build_byte_table();
+
+const fn build_byte_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u32) << 16;
+        let mut bit = 0;
+        while bit < 8 {
+            crc <<= 1;
+            if crc & 0x1000000 != 0 {
+                crc ^= MODES_GENERATOR_POLY;
+            }
+            bit += 1;
+        }
+        table[i] = crc & 0xffffff;
+        i += 1;
+    }
+    table
+}
+
+/// Incremental CRC-24 over whole bytes.
+///
+/// Feeding the data field of a message (everything but the last 3 bytes)
+/// yields the same value as [`modes_checksum`], but bytes can be pushed
+/// as soon as they are demodulated.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Crc24 {
+    crc: u32,
+}
+
+impl Crc24 {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed more bytes into the CRC
+    #[inline]
+    pub fn update(&mut self, data: &[u8]) {
+        let mut crc = self.crc;
+        for &byte in data {
+            let idx = ((crc >> 16) as u8 ^ byte) as usize;
+            crc = ((crc << 8) & 0xffffff) ^ MODES_CRC_BYTE_TABLE[idx];
+        }
+        self.crc = crc;
+    }
+
+    /// Current 24-bit CRC value
+    #[inline]
+    pub fn value(&self) -> u32 {
+        self.crc
+    }
+}
+
+/// Compute the Mode S CRC-24 over an arbitrary data field.
+pub fn crc24(data: &[u8]) -> u32 {
+    let mut crc = Crc24::new();
+    crc.update(data);
+    crc.value()
+}
+
 /// Calculate the Mode S checksum for a message.
 ///
 /// # Arguments
@@ -35,6 +106,17 @@ pub const MODES_CHECKSUM_TABLE: [u32; 112] = [
 /// # Returns
 /// The 24-bit CRC value
 ///
+/// The CRC covers every byte except the trailing 3-byte parity field and
+/// is computed a byte at a time from [`MODES_CRC_BYTE_TABLE`].
+pub fn modes_checksum(msg: &[u8], bits: usize) -> u32 {
+    debug_assert!(bits == 56 || bits == 112);
+    debug_assert!(msg.len() >= bits / 8);
+
+    crc24(&msg[..bits / 8 - 3])
+}
+
+/// Bit-by-bit reference implementation of [`modes_checksum`].
+///
 /// # C Pointer Arithmetic Conversion
 /// The original C code uses:
 /// ```c
@@ -47,7 +129,8 @@ pub const MODES_CHECKSUM_TABLE: [u32; 112] = [
 /// In Rust, we use safe slice indexing with bounds checking:
 /// - `msg[byte]` becomes `msg[j / 8]` with automatic bounds check
 /// - The bitmask calculation is identical
-pub fn modes_checksum(msg: &[u8], bits: usize) -> u32 {
+#[allow(dead_code)]
+pub fn modes_checksum_bitwise(msg: &[u8], bits: usize) -> u32 {
     debug_assert!(bits == 56 || bits == 112);
     debug_assert!(msg.len() >= bits / 8);
 
@@ -70,6 +153,26 @@ pub fn modes_checksum(msg: &[u8], bits: usize) -> u32 {
     crc
 }
 
+/// Parity field value for a message with the ICAO address in the clear
+/// (DF11/DF17/DF18): the plain CRC of the data field.
+pub fn parity(msg: &[u8], bits: usize) -> u32 {
+    modes_checksum(msg, bits)
+}
+
+/// Address/parity field value for DF0/4/5/16/20/21: the CRC XORed with
+/// the 24-bit ICAO address.
+pub fn address_parity(msg: &[u8], bits: usize, icao: u32) -> u32 {
+    modes_checksum(msg, bits) ^ (icao & 0xffffff)
+}
+
+/// Write a 24-bit value into the trailing parity field of a message.
+pub fn write_parity_field(msg: &mut [u8], bits: usize, value: u32) {
+    let len = bits / 8;
+    msg[len - 3] = (value >> 16) as u8;
+    msg[len - 2] = (value >> 8) as u8;
+    msg[len - 1] = value as u8;
+}
+
 /// Extract the CRC from a message (last 3 bytes).
 ///
 /// # Arguments
@@ -94,97 +197,169 @@ fn is_valid_icao(icao: u32) -> bool {
     icao != 0 && icao < 0x1000000
 }
 
-/// Attempt to fix single-bit errors using the CRC.
-///
-/// # Algorithm
-/// For each bit position, flip it and check if the CRC matches.
-/// If found, the error is corrected in place.
-///
-/// # Returns
-/// * `Some(bit_position)` if an error was fixed
-/// * `None` if no single-bit fix was possible
-///
-/// # C Pointer Arithmetic Conversion
-/// Original C:
-/// ```c
-/// memcpy(aux, msg, bits/8);
-/// aux[byte] ^= bitmask;
-/// ```
-///
-/// In Rust, we use a stack-allocated array and safe indexing:
-/// ```rust
-/// let mut aux = [0u8; 14]; // Max message size
-/// aux[..len].copy_from_slice(&msg[..len]);
-/// aux[byte_idx] ^= bitmask;
-/// ```
-pub fn fix_single_bit_errors(msg: &mut [u8], bits: usize) -> Option<usize> {
-    let len = bits / 8;
+/// Number of Downlink Format bits at the start of every message.
+pub const MODES_DF_BITS: usize = 5;
 
-    // Work on a copy to avoid modifying original until we find a fix
-    let mut aux = [0u8; 14]; // MODES_LONG_MSG_BYTES
-    aux[..len].copy_from_slice(&msg[..len]);
+/// Bit error pattern that produces a given syndrome.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BitError {
+    Single(usize),
+    Double(usize, usize),
+}
 
-    for j in 0..bits {
-        let byte_idx = j / 8;
-        let bitmask = 1u8 << (7 - (j % 8));
+impl BitError {
+    /// Whether any of the flipped bits lies in the DF field
+    fn touches_df(&self) -> bool {
+        match *self {
+            BitError::Single(j) => j < MODES_DF_BITS,
+            BitError::Double(j, _) => j < MODES_DF_BITS,
+        }
+    }
+}
 
-        // Flip bit j
-        aux[byte_idx] ^= bitmask;
+/// Precomputed syndrome -> error pattern table for one message length.
+///
+/// The CRC is linear, so the syndrome of a damaged message
+/// (`computed_crc ^ received_crc`) depends only on which bits were flipped.
+/// Building the table once turns error correction into a single lookup
+/// instead of re-running the checksum for every candidate bit flip.
+pub struct SyndromeTable {
+    /// Syndrome of each single flipped bit, indexed by bit position
+    single: Vec<u32>,
+    /// `None` marks a syndrome shared by several patterns (not correctable)
+    entries: HashMap<u32, Option<BitError>>,
+}
 
-        let crc_in_msg = extract_crc(&aux, bits);
-        let computed_crc = modes_checksum(&aux, bits);
+impl SyndromeTable {
+    /// Build the table for `bits`-long messages, covering up to two errors.
+    fn new(bits: usize) -> Self {
+        let single: Vec<u32> = (0..bits).map(|j| bit_syndrome(j, bits)).collect();
+        let mut entries = HashMap::with_capacity(bits * bits / 2 + bits);
 
-        if crc_in_msg == computed_crc {
-            // Found the error! Copy fixed message back
-            msg[..len].copy_from_slice(&aux[..len]);
-            return Some(j);
+        for (j, &syn) in single.iter().enumerate() {
+            insert_pattern(&mut entries, syn, BitError::Single(j));
         }
+        for j in 0..bits {
+            for i in (j + 1)..bits {
+                insert_pattern(&mut entries, single[j] ^ single[i], BitError::Double(j, i));
+            }
+        }
+
+        Self { single, entries }
+    }
 
-        // Flip bit back for next iteration
-        aux[byte_idx] ^= bitmask;
+    /// Look up the unique error pattern for a syndrome, if there is one.
+    pub fn lookup(&self, syndrome: u32) -> Option<BitError> {
+        self.entries.get(&syndrome).copied().flatten()
     }
 
-    None
+    /// Syndromes of all single-bit errors, indexed by bit position.
+    pub fn single_bit_syndromes(&self) -> &[u32] {
+        &self.single
+    }
 }
 
-/// Attempt to fix two-bit errors (aggressive mode).
-/// This is computationally expensive: O(n²) where n = bits.
-///
-/// # Returns
-/// * `Some((bit1, bit2))` if errors were fixed
-/// * `None` if no two-bit fix was possible
-pub fn fix_two_bit_errors(msg: &mut [u8], bits: usize) -> Option<(usize, usize)> {
-    let len = bits / 8;
-    let mut aux = [0u8; 14];
-    aux[..len].copy_from_slice(&msg[..len]);
+fn insert_pattern(entries: &mut HashMap<u32, Option<BitError>>, syndrome: u32, error: BitError) {
+    entries
+        .entry(syndrome)
+        .and_modify(|e| *e = None)
+        .or_insert(Some(error));
+}
 
-    for j in 0..bits {
-        let byte1 = j / 8;
-        let bitmask1 = 1u8 << (7 - (j % 8));
+/// Syndrome produced by flipping bit `j` of a `bits`-long message.
+fn bit_syndrome(j: usize, bits: usize) -> u32 {
+    let mut msg = [0u8; 14]; // MODES_LONG_MSG_BYTES
+    msg[j / 8] = 1 << (7 - (j % 8));
+    modes_checksum(&msg, bits) ^ extract_crc(&msg, bits)
+}
 
-        // Start from j+1 to avoid checking same pairs twice
-        for i in (j + 1)..bits {
-            let byte2 = i / 8;
-            let bitmask2 = 1u8 << (7 - (i % 8));
+/// Get the shared syndrome table for 56- or 112-bit messages.
+pub fn syndrome_table(bits: usize) -> &'static SyndromeTable {
+    static SHORT: OnceLock<SyndromeTable> = OnceLock::new();
+    static LONG: OnceLock<SyndromeTable> = OnceLock::new();
 
-            // Reset aux to original
-            aux[..len].copy_from_slice(&msg[..len]);
+    debug_assert!(bits == 56 || bits == 112);
+    if bits == 112 {
+        LONG.get_or_init(|| SyndromeTable::new(112))
+    } else {
+        SHORT.get_or_init(|| SyndromeTable::new(56))
+    }
+}
 
-            // Flip both bits
-            aux[byte1] ^= bitmask1;
-            aux[byte2] ^= bitmask2;
+/// Compute the syndrome of a message (zero when the CRC is valid).
+pub fn syndrome(msg: &[u8], bits: usize) -> u32 {
+    modes_checksum(msg, bits) ^ extract_crc(msg, bits)
+}
+
+/// Flip the bits described by `error` in place.
+pub fn apply_bit_error(msg: &mut [u8], error: BitError) {
+    let flip = |msg: &mut [u8], j: usize| msg[j / 8] ^= 1 << (7 - (j % 8));
+    match error {
+        BitError::Single(j) => flip(msg, j),
+        BitError::Double(j, i) => {
+            flip(msg, j);
+            flip(msg, i);
+        }
+    }
+}
+
+/// Find the correction for `syndrome`, honouring the error budget and DF protection.
+fn find_correction(syndrome: u32, bits: usize, max_errors: usize, fix_df: bool) -> Option<BitError> {
+    if syndrome == 0 {
+        return None;
+    }
 
-            let crc_in_msg = extract_crc(&aux, bits);
-            let computed_crc = modes_checksum(&aux, bits);
+    let error = syndrome_table(bits).lookup(syndrome)?;
+    let allowed = match error {
+        BitError::Single(_) => max_errors >= 1,
+        BitError::Double(..) => max_errors >= 2,
+    };
 
-            if crc_in_msg == computed_crc {
-                msg[..len].copy_from_slice(&aux[..len]);
-                return Some((j, i));
-            }
+    if !allowed || (!fix_df && error.touches_df()) {
+        return None;
+    }
+    Some(error)
+}
+
+/// Attempt to fix single-bit errors using the CRC.
+///
+/// # Algorithm
+/// Compute the message syndrome and look it up in the precomputed
+/// syndrome table. If it matches exactly one flipped bit, the error
+/// is corrected in place.
+///
+/// Unless `fix_df` is set, errors in the first five bits are left
+/// alone, so a damaged frame is never turned into a different DF.
+///
+/// # Returns
+/// * `Some(bit_position)` if an error was fixed
+/// * `None` if no single-bit fix was possible
+pub fn fix_single_bit_errors(msg: &mut [u8], bits: usize, fix_df: bool) -> Option<usize> {
+    match find_correction(syndrome(msg, bits), bits, 1, fix_df)? {
+        BitError::Single(j) => {
+            apply_bit_error(msg, BitError::Single(j));
+            Some(j)
         }
+        BitError::Double(..) => None,
     }
+}
 
-    None
+/// Attempt to fix two-bit errors (aggressive mode).
+///
+/// Uses the same syndrome table lookup as [`fix_single_bit_errors`],
+/// so the cost is constant rather than O(n²) checksum evaluations.
+///
+/// # Returns
+/// * `Some((bit1, bit2))` if errors were fixed
+/// * `None` if no two-bit fix was possible
+pub fn fix_two_bit_errors(msg: &mut [u8], bits: usize, fix_df: bool) -> Option<(usize, usize)> {
+    match find_correction(syndrome(msg, bits), bits, 2, fix_df)? {
+        BitError::Double(j, i) => {
+            apply_bit_error(msg, BitError::Double(j, i));
+            Some((j, i))
+        }
+        BitError::Single(_) => None,
+    }
 }
 
 
@@ -206,21 +381,101 @@ mod tests {
         assert_eq!(crc, expected);
     }
 
+    #[test]
+    fn test_bytewise_matches_bitwise() {
+        // xorshift32 - deterministic pseudo-random messages
+        let mut state = 0x12345678u32;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        };
+
+        for _ in 0..1000 {
+            let mut msg = [0u8; 14];
+            msg.iter_mut().for_each(|b| *b = next());
+            for bits in [56, 112] {
+                assert_eq!(modes_checksum(&msg, bits), modes_checksum_bitwise(&msg, bits));
+            }
+        }
+    }
+
+    #[test]
+    fn test_build_valid_frames() {
+        // DF17: parity is the plain CRC
+        let mut msg = [
+            0x8D, 0x48, 0x40, 0xD6, 0x20, 0x2C, 0xC3, 0x71, 0xC3, 0x2C, 0xE0, 0x00, 0x00, 0x00,
+        ];
+        let pi = parity(&msg, 112);
+        write_parity_field(&mut msg, 112, pi);
+        assert_eq!(&msg[11..], &[0x57, 0x60, 0x98]);
+
+        // DF4: the address must be recoverable from the parity field
+        let mut msg = [0x20, 0x00, 0x0f, 0x1f, 0x00, 0x00, 0x00];
+        let ap = address_parity(&msg, 56, 0xABCDEF);
+        write_parity_field(&mut msg, 56, ap);
+        assert_eq!(syndrome(&msg, 56), 0xABCDEF);
+    }
+
     #[test]
     fn test_single_bit_error_correction() {
         // Start with a valid message
         let mut msg = [
             0x8D, 0x48, 0x40, 0xD6, 0x20, 0x2C, 0xC3, 0x71, 0xC3, 0x2C, 0xE0, 0x57, 0x60, 0x98,
         ];
-        let original = msg.clone();
+        let original = msg;
 
         // Introduce a single-bit error
         msg[5] ^= 0x04;
 
         // Attempt to fix
-        if let Some(bit_pos) = fix_single_bit_errors(&mut msg, 112) {
+        if let Some(bit_pos) = fix_single_bit_errors(&mut msg, 112, false) {
             assert_eq!(msg, original);
             assert!(bit_pos > 0);
         }
     }
+
+    #[test]
+    fn test_two_bit_error_correction() {
+        let original = [
+            0x8D, 0x48, 0x40, 0xD6, 0x20, 0x2C, 0xC3, 0x71, 0xC3, 0x2C, 0xE0, 0x57, 0x60, 0x98,
+        ];
+
+        let mut msg = original;
+        msg[3] ^= 0x10;
+        msg[9] ^= 0x01;
+
+        // Not a single-bit error
+        assert_eq!(fix_single_bit_errors(&mut msg, 112, false), None);
+        assert_eq!(fix_two_bit_errors(&mut msg, 112, false), Some((27, 79)));
+        assert_eq!(msg, original);
+    }
+
+    #[test]
+    fn test_df_bits_protected() {
+        let original = [
+            0x8D, 0x48, 0x40, 0xD6, 0x20, 0x2C, 0xC3, 0x71, 0xC3, 0x2C, 0xE0, 0x57, 0x60, 0x98,
+        ];
+
+        // Flip a bit inside the DF field
+        let mut msg = original;
+        msg[0] ^= 0x40;
+        assert_eq!(fix_single_bit_errors(&mut msg, 112, false), None);
+        assert_eq!(fix_single_bit_errors(&mut msg, 112, true), Some(1));
+        assert_eq!(msg, original);
+    }
+
+    #[test]
+    fn test_syndrome_table_matches_bitwise_search() {
+        // Every single-bit error must map back to exactly the flipped bit
+        for bits in [56, 112] {
+            let table = syndrome_table(bits);
+            for j in 0..bits {
+                let mut msg = [0u8; 14];
+                msg[j / 8] ^= 1 << (7 - (j % 8));
+                assert_eq!(table.lookup(syndrome(&msg, bits)), Some(BitError::Single(j)));
+            }
+        }
+    }
 }
@@ -4,6 +4,7 @@
 
 use std::fmt;
 
+use crate::beast::{signal_byte, signal_level};
 use crate::crc::{self, extract_crc, modes_checksum};
 
 /// Constants for message sizes
@@ -12,6 +13,12 @@ pub const MODES_SHORT_MSG_BITS: usize = 56;
 pub const MODES_LONG_MSG_BYTES: usize = 14;
 #[allow(dead_code)]
 pub const MODES_SHORT_MSG_BYTES: usize = 7;
+/// Mode S timestamps are 48 bits of a 12 MHz clock
+pub const MODES_TIMESTAMP_MASK: u64 = (1 << 48) - 1;
+/// Pseudo downlink format used for Mode A/C replies (as in dump1090)
+pub const MODEAC_MSG_TYPE: u8 = 32;
+/// Mode A/C replies are carried as a 2-byte code
+pub const MODEAC_MSG_BITS: usize = 16;
 
 /// Unit for altitude measurements
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -20,6 +27,15 @@ pub enum AltitudeUnit {
     Meters,
 }
 
+/// Autopilot modes from BDS 4,0, valid when the MCP/FCU mode status bit
+/// is set
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NavModes {
+    pub vnav: bool,
+    pub alt_hold: bool,
+    pub approach: bool,
+}
+
 /// BDS (Comm-B Data Selector) register types
 #[derive(Debug, Clone, PartialEq)]
 pub enum BdsData {
@@ -42,9 +58,7 @@ pub enum BdsData {
         mcp_altitude: Option<u16>,
         fms_altitude: Option<u16>,
         baro_setting: Option<f32>,
-        vnav_mode: bool,
-        alt_hold_mode: bool,
-        approach_mode: bool,
+        nav_modes: Option<NavModes>,
     },
     /// BDS 5,0 - Track and turn report
     TrackAndTurnReport {
@@ -103,6 +117,10 @@ pub struct ModesMessage {
     pub altitude: i32,
     /// Altitude unit
     pub unit: AltitudeUnit,
+    /// GNSS height from an ES airborne position (TC 20-22), in feet
+    pub geom_altitude: Option<i32>,
+    /// GNSS height minus barometric altitude from ES velocity, in feet
+    pub gnss_baro_diff: Option<i32>,
     /// Flight callsign
     pub flight: String,
     /// Aircraft type category
@@ -139,6 +157,8 @@ pub struct ModesMessage {
     pub phase_corrected: bool,
     /// Signal level (preamble peak magnitude)
     pub signal_level: u16,
+    /// Reception time on the 48-bit 12 MHz sample clock (0 if unknown)
+    pub timestamp: u64,
     /// BDS data from DF20/DF21 MB field
     pub bds_data: Option<BdsData>,
 }
@@ -163,6 +183,8 @@ impl Default for ModesMessage {
             identity: 0,
             altitude: 0,
             unit: AltitudeUnit::Feet,
+            geom_altitude: None,
+            gnss_baro_diff: None,
             flight: String::new(),
             aircraft_type: 0,
             fflag: false,
@@ -181,6 +203,7 @@ impl Default for ModesMessage {
             velocity: 0,
             phase_corrected: false,
             signal_level: 0,
+            timestamp: 0,
             bds_data: None,
         }
     }
@@ -192,96 +215,107 @@ impl ModesMessage {
         ((self.aa[0] as u32) << 16) | ((self.aa[1] as u32) << 8) | (self.aa[2] as u32)
     }
 
+    /// Whether the ICAO address is XORed into the parity field
+    /// (DF0/4/5/16/20/21) instead of being sent in the clear
+    pub fn is_address_parity(&self) -> bool {
+        matches!(self.msg_type, 0 | 4 | 5 | 16 | 20 | 21)
+    }
+
+    /// Whether this is a Mode A/C reply rather than a Mode S message
+    pub fn is_mode_ac(&self) -> bool {
+        self.msg_type == MODEAC_MSG_TYPE
+    }
+
+    /// Vertical rate in ft/min from an airborne velocity message
+    pub fn vertical_rate(&self) -> Option<i32> {
+        if !matches!(self.msg_type, 17 | 18)
+            || self.me_type != 19
+            || !(1..=4).contains(&self.me_sub)
+            || self.vert_rate == 0
+        {
+            return None;
+        }
+        let rate = (self.vert_rate as i32 - 1) * 64;
+        Some(if self.vert_rate_sign != 0 { -rate } else { rate })
+    }
+
     /// Format as raw hex string for network output
     pub fn to_raw_string(&self) -> String {
+        self.to_avr_string(AvrFormat::Plain)
+    }
+
+    /// Format as an AVR line in the given variant
+    pub fn to_avr_string(&self, format: AvrFormat) -> String {
         let bytes = self.msg_bits / 8;
-        let mut s = String::with_capacity(bytes * 2 + 3);
-        s.push('*');
+        let mut s = String::with_capacity(bytes * 2 + 17);
+        s.push(format.marker());
+        if format.has_timestamp() {
+            s.push_str(&format!("{:012X}", self.timestamp & MODES_TIMESTAMP_MASK));
+        }
+        if format == AvrFormat::Signal {
+            s.push_str(&format!("{:02X}", signal_byte(self.signal_level)));
+        }
         for i in 0..bytes {
             s.push_str(&format!("{:02X}", self.msg[i]));
         }
         s.push(';');
         s
     }
+}
 
-    /// Format as SBS/BaseStation output
-    #[allow(dead_code)]
-    pub fn to_sbs_string(&self, lat: f64, lon: f64) -> Option<String> {
-        let icao = format!("{:02X}{:02X}{:02X}", self.aa[0], self.aa[1], self.aa[2]);
+/// AVR text frame variants: `*frame;`, `:frame;`, `@timestamp frame;`
+/// (AVR-MLAT), `~timestamp frame;` and `<timestamp signal frame;`. The
+/// timestamp is the 12 MHz counter as 12 hex digits and the signal the
+/// Beast RSSI byte. On input `%` is also accepted for `@`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AvrFormat {
+    #[default]
+    Plain,
+    Colon,
+    Mlat,
+    Tilde,
+    Signal,
+}
 
-        match self.msg_type {
-            0 => Some(format!(
-                "MSG,5,,,{},,,,,,,,{},,,,,,,,,,",
-                icao, self.altitude
-            )),
-            4 => {
-                let (alert, emergency, spi, ground) = self.decode_flight_status_flags();
-                Some(format!(
-                    "MSG,5,,,{},,,,,,,{},,,,,,,,{},{},{},{}",
-                    icao, self.altitude, alert, emergency, spi, ground
-                ))
-            }
-            5 => {
-                let (alert, emergency, spi, ground) = self.decode_flight_status_flags();
-                Some(format!(
-                    "MSG,6,,,{},,,,,,,,,,,,,,{},{},{},{},{}",
-                    icao, self.identity, alert, emergency, spi, ground
-                ))
-            }
-            11 => Some(format!("MSG,8,,,{},,,,,,,,,,,,,,,,,", icao)),
-            17 if self.me_type == 4 => Some(format!(
-                "MSG,1,,,{},,,,,,,{},,,,,,,,0,0,0,0",
-                icao, self.flight
-            )),
-            17 if (9..=18).contains(&self.me_type) => {
-                if lat == 0.0 && lon == 0.0 {
-                    Some(format!(
-                        "MSG,3,,,{},,,,,,,,{},,,,,,,,0,0,0,0",
-                        icao, self.altitude
-                    ))
-                } else {
-                    Some(format!(
-                        "MSG,3,,,{},,,,,,,{},,{:.5},{:.5},,,0,0,0,0",
-                        icao, self.altitude, lat, lon
-                    ))
-                }
-            }
-            17 if self.me_type == 19 && self.me_sub == 1 => {
-                let vr = if self.vert_rate_sign == 0 { 1 } else { -1 }
-                    * (self.vert_rate as i32 - 1)
-                    * 64;
-                Some(format!(
-                    "MSG,4,,,{},,,,,,,,{},{},,,,{},,0,0,0,0",
-                    icao, self.velocity, self.heading as i32, vr
-                ))
-            }
-            21 => {
-                let (alert, emergency, spi, ground) = self.decode_flight_status_flags();
-                Some(format!(
-                    "MSG,6,,,{},,,,,,,,,,,,,,{},{},{},{},{}",
-                    icao, self.identity, alert, emergency, spi, ground
-                ))
-            }
+impl AvrFormat {
+    /// Parse a format name as used on the command line
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "avr" => Some(Self::Plain),
+            "avr-colon" => Some(Self::Colon),
+            "avr-mlat" | "mlat" => Some(Self::Mlat),
+            "avr-tilde" => Some(Self::Tilde),
+            "avr-signal" | "signal" => Some(Self::Signal),
             _ => None,
         }
     }
 
-    /// Decode flight status flags for SBS output
-    #[allow(dead_code)]
-    fn decode_flight_status_flags(&self) -> (i32, i32, i32, i32) {
-        let emergency = if self.identity == 7500 || self.identity == 7600 || self.identity == 7700 {
-            -1
-        } else {
-            0
-        };
-        let ground = if self.fs == 1 || self.fs == 3 { -1 } else { 0 };
-        let alert = if self.fs == 2 || self.fs == 3 || self.fs == 4 {
-            -1
-        } else {
-            0
-        };
-        let spi = if self.fs == 4 || self.fs == 5 { -1 } else { 0 };
-        (alert, emergency, spi, ground)
+    /// Whether frames carry the 12 MHz timestamp
+    pub fn has_timestamp(&self) -> bool {
+        matches!(self, Self::Mlat | Self::Tilde | Self::Signal)
+    }
+
+    /// Line start marker written on output
+    pub fn marker(&self) -> char {
+        match self {
+            Self::Plain => '*',
+            Self::Colon => ':',
+            Self::Mlat => '@',
+            Self::Tilde => '~',
+            Self::Signal => '<',
+        }
+    }
+
+    /// Variant for an input line start marker
+    fn from_marker(c: char) -> Option<Self> {
+        match c {
+            '*' => Some(Self::Plain),
+            ':' => Some(Self::Colon),
+            '@' | '%' => Some(Self::Mlat),
+            '~' => Some(Self::Tilde),
+            '<' => Some(Self::Signal),
+            _ => None,
+        }
     }
 }
 
@@ -350,10 +384,10 @@ impl fmt::Display for ModesMessage {
                     self.aa[0], self.aa[1], self.aa[2]
                 )?;
 
-                if self.msg_type == 20 {
-                    if let Some(ref bds) = self.bds_data {
-                        writeln!(f, "  MB Field (BDS) : {}", format_bds_data(bds))?;
-                    }
+                if self.msg_type == 20
+                    && let Some(ref bds) = self.bds_data
+                {
+                    writeln!(f, "  MB Field (BDS) : {}", format_bds_data(bds))?;
                 }
             }
             5 | 21 => {
@@ -373,10 +407,10 @@ impl fmt::Display for ModesMessage {
                     self.aa[0], self.aa[1], self.aa[2]
                 )?;
 
-                if self.msg_type == 21 {
-                    if let Some(ref bds) = self.bds_data {
-                        writeln!(f, "  MB Field (BDS) : {}", format_bds_data(bds))?;
-                    }
+                if self.msg_type == 21
+                    && let Some(ref bds) = self.bds_data
+                {
+                    writeln!(f, "  MB Field (BDS) : {}", format_bds_data(bds))?;
                 }
             }
             11 => {
@@ -450,6 +484,8 @@ impl fmt::Display for ModesMessage {
                     } else {
                         writeln!(f, "    Heading status:  {}", self.heading_is_valid)?;
                         writeln!(f, "    Heading:  {:.1}", self.heading)?;
+                        writeln!(f, "    Airspeed:  {}", self.velocity)?;
+                        writeln!(f, "    Vertical rate     : {}", self.vert_rate)?;
                     }
                 } else {
                     writeln!(
@@ -477,6 +513,13 @@ impl fmt::Display for ModesMessage {
                     self.aa[0], self.aa[1], self.aa[2]
                 )?;
             }
+            MODEAC_MSG_TYPE => {
+                writeln!(f, "Mode A/C reply.")?;
+                writeln!(f, "  Mode A (squawk): {:04}", self.identity)?;
+                if self.altitude != 0 {
+                    writeln!(f, "  Mode C altitude: {} feet", self.altitude)?;
+                }
+            }
             _ => {
                 writeln!(f, "DF {} (decoding not fully implemented)", self.msg_type)?;
             }
@@ -511,9 +554,7 @@ fn format_bds_data(bds: &BdsData) -> String {
             mcp_altitude,
             fms_altitude,
             baro_setting,
-            vnav_mode,
-            alt_hold_mode,
-            approach_mode,
+            nav_modes,
         } => {
             let mcp = mcp_altitude
                 .map(|a| format!("{} ft", a))
@@ -524,9 +565,12 @@ fn format_bds_data(bds: &BdsData) -> String {
             let baro = baro_setting
                 .map(|b| format!("{:.1} mb", b))
                 .unwrap_or_else(|| "N/A".to_string());
+            let modes = nav_modes
+                .map(|m| format!("VNAV={}, ALT_HOLD={}, APP={}", m.vnav, m.alt_hold, m.approach))
+                .unwrap_or_else(|| "Modes: N/A".to_string());
             format!(
-                "BDS 4,0 - MCP Alt: {}, FMS Alt: {}, Baro: {}, VNAV={}, ALT_HOLD={}, APP={}",
-                mcp, fms, baro, vnav_mode, alt_hold_mode, approach_mode
+                "BDS 4,0 - MCP Alt: {}, FMS Alt: {}, Baro: {}, {}",
+                mcp, fms, baro, modes
             )
         }
         BdsData::TrackAndTurnReport {
@@ -682,7 +726,7 @@ fn decode_gillham_altitude(code: u16) -> Option<i32> {
     let altitude = (five_hundreds * 500) + (hundreds * 100) - 1300;
 
     // Validate range (-1200 to 126,700 feet for Mode C)
-    if altitude >= -1200 && altitude <= 126700 {
+    if (-1200..=126700).contains(&altitude) {
         Some(altitude)
     } else {
         None
@@ -793,62 +837,69 @@ fn try_decode_bds_30(mb: &[u8]) -> Option<BdsData> {
 }
 
 fn try_decode_bds_40(mb: &[u8]) -> Option<BdsData> {
+    // Bits 40-47 and 52-53 are reserved
+    if mb[4] & 0x01 != 0 || mb[5] & 0xFE != 0 || mb[6] & 0x18 != 0 {
+        return None;
+    }
+
+    // Status bits 1, 14 and 27, each followed by a 12-bit field
     let mcp_status = (mb[0] & 0x80) != 0;
-    let fms_status = (mb[2] & 0x80) != 0;
-    let baro_status = (mb[4] & 0x80) != 0;
+    let fms_status = (mb[1] & 0x04) != 0;
+    let baro_status = (mb[3] & 0x20) != 0;
 
     let mcp_altitude = if mcp_status {
         let raw = ((mb[0] as u16 & 0x7F) << 5) | ((mb[1] >> 3) as u16);
-        Some((raw * 16) as u16)
+        Some(raw * 16)
     } else {
         None
     };
 
     let fms_altitude = if fms_status {
-        let raw = ((mb[2] as u16 & 0x7F) << 5) | ((mb[3] >> 3) as u16);
-        Some((raw * 16) as u16)
+        let raw = ((mb[1] as u16 & 0x03) << 10) | ((mb[2] as u16) << 2) | ((mb[3] >> 6) as u16);
+        Some(raw * 16)
     } else {
         None
     };
 
     let baro_setting = if baro_status {
-        let raw = ((mb[4] as u16 & 0x7F) << 5) | ((mb[5] >> 3) as u16);
+        let raw = ((mb[3] as u16 & 0x1F) << 7) | ((mb[4] >> 1) as u16);
         Some(800.0 + (raw as f32) * 0.1)
     } else {
         None
     };
 
-    let vnav_mode = (mb[6] & 0x08) != 0;
-    let alt_hold_mode = (mb[6] & 0x04) != 0;
-    let approach_mode = (mb[6] & 0x02) != 0;
+    // Bit 48 is the mode status, bits 49-51 VNAV, altitude hold, approach
+    let nav_modes = (mb[5] & 0x01 != 0).then(|| NavModes {
+        vnav: mb[6] & 0x80 != 0,
+        alt_hold: mb[6] & 0x40 != 0,
+        approach: mb[6] & 0x20 != 0,
+    });
 
     if mcp_altitude.is_none() && fms_altitude.is_none() && baro_setting.is_none() {
         return None;
     }
 
-    if let Some(alt) = mcp_altitude {
-        if alt > 50000 {
-            return None;
-        }
+    if let Some(alt) = mcp_altitude
+        && alt > 50000
+    {
+        return None;
     }
-    if let Some(alt) = fms_altitude {
-        if alt > 50000 {
-            return None;
-        }
+    if let Some(alt) = fms_altitude
+        && alt > 50000
+    {
+        return None;
     }
-    if let Some(baro) = baro_setting {
-        if baro < 850.0 || baro > 1100.0 {
-            return None;
-        }
+    if let Some(baro) = baro_setting
+        && !(850.0..=1100.0).contains(&baro)
+    {
+        return None;
     }
 
     Some(BdsData::SelectedVerticalIntention {
         mcp_altitude,
         fms_altitude,
         baro_setting,
-        vnav_mode,
-        alt_hold_mode,
-        approach_mode,
+        nav_modes,
     })
 }
 
@@ -876,7 +927,7 @@ fn try_decode_bds_50(mb: &[u8]) -> Option<BdsData> {
 
     let ground_speed = if gs_status {
         let raw = ((mb[2] as u16 & 0x01) << 9) | ((mb[3] as u16) << 1) | ((mb[4] >> 7) as u16);
-        Some((raw * 2) as u16)
+        Some(raw * 2)
     } else {
         None
     };
@@ -891,7 +942,7 @@ fn try_decode_bds_50(mb: &[u8]) -> Option<BdsData> {
 
     let true_airspeed = if tas_status {
         let raw = ((mb[5] as u16 & 0x1F) << 5) | ((mb[6] >> 3) as u16);
-        Some((raw * 2) as u16)
+        Some(raw * 2)
     } else {
         None
     };
@@ -911,20 +962,20 @@ fn try_decode_bds_50(mb: &[u8]) -> Option<BdsData> {
         return None;
     }
 
-    if let Some(roll) = roll_angle {
-        if roll.abs() > 60.0 {
-            return None;
-        }
+    if let Some(roll) = roll_angle
+        && roll.abs() > 60.0
+    {
+        return None;
     }
-    if let Some(gs) = ground_speed {
-        if gs > 600 {
-            return None;
-        }
+    if let Some(gs) = ground_speed
+        && gs > 600
+    {
+        return None;
     }
-    if let Some(tas) = true_airspeed {
-        if tas > 600 {
-            return None;
-        }
+    if let Some(tas) = true_airspeed
+        && tas > 600
+    {
+        return None;
     }
 
     Some(BdsData::TrackAndTurnReport {
@@ -952,7 +1003,7 @@ fn try_decode_bds_60(mb: &[u8]) -> Option<BdsData> {
 
     let indicated_airspeed = if ias_status {
         let raw = ((mb[1] as u16 & 0x0F) << 6) | ((mb[2] >> 2) as u16);
-        Some(raw as u16)
+        Some(raw)
     } else {
         None
     };
@@ -967,7 +1018,7 @@ fn try_decode_bds_60(mb: &[u8]) -> Option<BdsData> {
     let baro_altitude_rate = if baro_rate_status {
         let raw = ((mb[4] as i16 & 0x3F) << 4) | ((mb[5] >> 4) as i16);
         let signed = if raw & 0x200 != 0 { raw - 0x400 } else { raw };
-        Some((signed * 32) as i16)
+        Some(signed * 32)
     } else {
         None
     };
@@ -975,7 +1026,7 @@ fn try_decode_bds_60(mb: &[u8]) -> Option<BdsData> {
     let inertial_altitude_rate = if inertial_rate_status {
         let raw = ((mb[5] as i16 & 0x0F) << 6) | ((mb[6] >> 2) as i16);
         let signed = if raw & 0x200 != 0 { raw - 0x400 } else { raw };
-        Some((signed * 32) as i16)
+        Some(signed * 32)
     } else {
         None
     };
@@ -995,15 +1046,15 @@ fn try_decode_bds_60(mb: &[u8]) -> Option<BdsData> {
         return None;
     }
 
-    if let Some(ias) = indicated_airspeed {
-        if ias > 500 {
-            return None;
-        }
+    if let Some(ias) = indicated_airspeed
+        && ias > 500
+    {
+        return None;
     }
-    if let Some(m) = mach {
-        if m > 1.0 {
-            return None;
-        }
+    if let Some(m) = mach
+        && m > 1.0
+    {
+        return None;
     }
 
     Some(BdsData::HeadingAndSpeedReport {
@@ -1019,7 +1070,14 @@ fn try_decode_bds_60(mb: &[u8]) -> Option<BdsData> {
 ///
 /// For DF4/5/20/21, we can only validate if we have a known ICAO to check against.
 /// Pass `known_icao` as Some(icao) to validate, or None to attempt recovery.
-pub fn decode_modes_message(raw_msg: &[u8], fix_errors: bool, aggressive: bool) -> ModesMessage {
+///
+/// `fix_df` allows error correction to flip bits in the DF field itself.
+pub fn decode_modes_message(
+    raw_msg: &[u8],
+    fix_errors: bool,
+    aggressive: bool,
+    fix_df: bool,
+) -> ModesMessage {
     let mut mm = ModesMessage::default();
 
     // Copy message to local buffer
@@ -1046,17 +1104,17 @@ pub fn decode_modes_message(raw_msg: &[u8], fix_errors: bool, aggressive: bool)
 
         // Attempt error correction for DF11 and DF17 messages
         if !mm.crc_ok && fix_errors && (mm.msg_type == 11 || mm.msg_type == 17) {
-            if let Some(bit) = crc::fix_single_bit_errors(&mut mm.msg, mm.msg_bits) {
+            if let Some(bit) = crc::fix_single_bit_errors(&mut mm.msg, mm.msg_bits, fix_df) {
                 mm.error_bit = Some(bit);
                 mm.crc = extract_crc(&mm.msg, mm.msg_bits);
                 mm.crc_ok = true;
-            } else if aggressive && mm.msg_type == 17 {
-                if let Some((bit1, bit2)) = crc::fix_two_bit_errors(&mut mm.msg, mm.msg_bits) {
-                    mm.error_bit = Some(bit1);
-                    mm.error_bit2 = Some(bit2);
-                    mm.crc = extract_crc(&mm.msg, mm.msg_bits);
-                    mm.crc_ok = true;
-                }
+            } else if aggressive && mm.msg_type == 17
+                && let Some((bit1, bit2)) = crc::fix_two_bit_errors(&mut mm.msg, mm.msg_bits, fix_df)
+            {
+                mm.error_bit = Some(bit1);
+                mm.error_bit2 = Some(bit2);
+                mm.crc = extract_crc(&mm.msg, mm.msg_bits);
+                mm.crc_ok = true;
             }
         }
     } else {
@@ -1105,8 +1163,8 @@ pub fn decode_modes_message(raw_msg: &[u8], fix_errors: bool, aggressive: bool)
         mm.altitude = decode_ac13_field(&mm.msg, &mut mm.unit);
     }
 
-    // === Decode extended squitter (DF17) ===
-    if mm.msg_type == 17 {
+    // === Decode extended squitter (DF17, and DF18 from non-transponder devices) ===
+    if matches!(mm.msg_type, 17 | 18) {
         decode_extended_squitter(&mut mm);
     }
 
@@ -1131,6 +1189,110 @@ pub fn validate_icao(mm: &mut ModesMessage, known_icao: u32) {
         mm.crc_ok = true;
     }
 }
+
+/// Outcome of trying to repair an address/parity message
+#[derive(Debug)]
+pub enum ApCorrection {
+    /// Exactly one candidate ICAO explains the residual as a single-bit error
+    Fixed(ModesMessage),
+    /// More than one candidate ICAO matched; the message can't be attributed
+    Ambiguous,
+    /// No candidate ICAO is a single bit away
+    NotFound,
+}
+
+/// Try to repair a single-bit error in a DF0/4/5/16/20/21 message.
+///
+/// The recovered address is `AA ^ syndrome(error)`, so for every possible
+/// single-bit error we XOR its syndrome back out and ask `is_candidate`
+/// whether the result is an address we trust. Only a unique match is
+/// accepted, and the message is re-decoded with the bit flipped.
+pub fn fix_address_parity(
+    mm: &ModesMessage,
+    is_candidate: impl Fn(u32) -> bool,
+    fix_df: bool,
+) -> ApCorrection {
+    let recovered = mm.icao_address();
+    let first_bit = if fix_df { 0 } else { crc::MODES_DF_BITS };
+    let syndromes = crc::syndrome_table(mm.msg_bits).single_bit_syndromes();
+
+    let mut found: Option<(usize, u32)> = None;
+    for (j, &syn) in syndromes.iter().enumerate().skip(first_bit) {
+        let candidate = recovered ^ syn;
+        if !is_candidate(candidate) {
+            continue;
+        }
+        if found.is_some() {
+            return ApCorrection::Ambiguous;
+        }
+        found = Some((j, candidate));
+    }
+
+    let Some((bit, _)) = found else {
+        return ApCorrection::NotFound;
+    };
+
+    let len = mm.msg_bits / 8;
+    let mut msg = mm.msg;
+    crc::apply_bit_error(&mut msg[..len], crc::BitError::Single(bit));
+
+    // A flipped DF bit must not change the message length
+    if message_len_by_type(msg[0] >> 3) != mm.msg_bits {
+        return ApCorrection::NotFound;
+    }
+
+    let mut fixed = decode_modes_message(&msg[..len], false, false, false);
+    fixed.crc_ok = true;
+    fixed.error_bit = Some(bit);
+    fixed.phase_corrected = mm.phase_corrected;
+    fixed.signal_level = mm.signal_level;
+    ApCorrection::Fixed(fixed)
+}
+
+/// Build a message from a Mode A/C reply.
+///
+/// `code` holds the four octal digits ABCD packed as nibbles
+/// (squawk 7700 -> 0x7700). Replies to Mode A and Mode C interrogations
+/// look identical, so the code is decoded both as a squawk and, where it
+/// is a valid Gillham code, as an altitude.
+pub fn decode_mode_ac(code: u16) -> ModesMessage {
+    let mut mm = ModesMessage {
+        msg_type: MODEAC_MSG_TYPE,
+        msg_bits: MODEAC_MSG_BITS,
+        crc_ok: true, // No parity in Mode A/C
+        ..Default::default()
+    };
+    mm.msg[0] = (code >> 8) as u8;
+    mm.msg[1] = code as u8;
+
+    let a = (code >> 12) & 7;
+    let b = (code >> 8) & 7;
+    let c = (code >> 4) & 7;
+    let d = code & 7;
+    mm.identity = a * 1000 + b * 100 + c * 10 + d;
+
+    // Mode C never uses D1
+    if d & 1 == 0 {
+        let bit = |digit: u16, weight: u16| (digit >> weight) & 1;
+        let gillham = (bit(d, 2) << 10)
+            | (bit(d, 1) << 9)
+            | (bit(b, 2) << 8)
+            | (bit(b, 1) << 7)
+            | bit(b, 0) << 6
+            | (bit(a, 2) << 5)
+            | (bit(a, 1) << 4)
+            | (bit(a, 0) << 3)
+            | (bit(c, 2) << 2)
+            | (bit(c, 1) << 1)
+            | bit(c, 0);
+        if let Some(alt) = decode_gillham_altitude(gillham) {
+            mm.altitude = alt;
+        }
+    }
+
+    mm
+}
+
 /// Decode extended squitter message (DF17)
 fn decode_extended_squitter(mm: &mut ModesMessage) {
     if (1..=4).contains(&mm.me_type) {
@@ -1169,19 +1331,35 @@ fn decode_extended_squitter(mm: &mut ModesMessage) {
             | ((mm.msg[8] >> 1) as u32);
         mm.raw_longitude =
             (((mm.msg[8] & 0x01) as u32) << 16) | ((mm.msg[9] as u32) << 8) | (mm.msg[10] as u32);
+    } else if (20..=22).contains(&mm.me_type) {
+        // Same layout as the barometric position, with GNSS height
+        let mut unit = AltitudeUnit::Feet;
+        let altitude = decode_ac12_field(&mm.msg, &mut unit);
+        mm.geom_altitude = (altitude != 0).then_some(altitude);
     } else if mm.me_type == 19 && (1..=4).contains(&mm.me_sub) {
+        // Vertical rate has the same layout in all four subtypes
+        mm.vert_rate_source = (mm.msg[8] & 0x10) >> 4;
+        mm.vert_rate_sign = (mm.msg[8] & 0x08) >> 3;
+        mm.vert_rate = (((mm.msg[8] & 0x07) as u16) << 6) | (((mm.msg[9] & 0xFC) >> 2) as u16);
+        // GNSS minus baro altitude in 25 ft steps, sent as value + 1
+        let diff = (mm.msg[10] & 0x7F) as i32;
+        if diff != 0 {
+            let diff = (diff - 1) * 25;
+            mm.gnss_baro_diff = Some(if mm.msg[10] & 0x80 != 0 { -diff } else { diff });
+        }
+        // Subtypes 2 and 4 (supersonic) count speed in 4 kt units
+        let speed_scale = if mm.me_sub == 2 || mm.me_sub == 4 { 4.0 } else { 1.0 };
+
         if mm.me_sub == 1 || mm.me_sub == 2 {
             mm.ew_dir = (mm.msg[5] & 0x04) >> 2;
             mm.ew_velocity = (((mm.msg[5] & 0x03) as u16) << 8) | (mm.msg[6] as u16);
             mm.ns_dir = (mm.msg[7] & 0x80) >> 7;
             mm.ns_velocity =
                 (((mm.msg[7] & 0x7F) as u16) << 3) | (((mm.msg[8] & 0xE0) >> 5) as u16);
-            mm.vert_rate_source = (mm.msg[8] & 0x10) >> 4;
-            mm.vert_rate_sign = (mm.msg[8] & 0x08) >> 3;
-            mm.vert_rate = (((mm.msg[8] & 0x07) as u16) << 6) | (((mm.msg[9] & 0xFC) >> 2) as u16);
 
-            let ewv = mm.ew_velocity as f64;
-            let nsv = mm.ns_velocity as f64;
+            // Components are sent as speed + 1, 0 meaning unavailable
+            let ewv = mm.ew_velocity.saturating_sub(1) as f64 * speed_scale;
+            let nsv = mm.ns_velocity.saturating_sub(1) as f64 * speed_scale;
             mm.velocity = (ewv * ewv + nsv * nsv).sqrt() as u16;
 
             if mm.velocity > 0 {
@@ -1194,9 +1372,11 @@ fn decode_extended_squitter(mm: &mut ModesMessage) {
                 mm.heading = heading;
             }
         } else if mm.me_sub == 3 || mm.me_sub == 4 {
+            // Airspeed subtypes: 10-bit magnetic heading, then IAS/TAS
             mm.heading_is_valid = (mm.msg[5] & 0x04) != 0;
-            mm.heading = (360.0 / 128.0)
-                * ((((mm.msg[5] & 0x03) as u16) << 5) | ((mm.msg[6] >> 3) as u16)) as f64;
+            mm.heading = (360.0 / 1024.0) * ((((mm.msg[5] & 0x03) as u16) << 8) | mm.msg[6] as u16) as f64;
+            let airspeed = (((mm.msg[7] & 0x7F) as u16) << 3) | ((mm.msg[8] >> 5) as u16);
+            mm.velocity = (airspeed.saturating_sub(1) as f64 * speed_scale) as u16;
         }
     }
 }
@@ -1298,7 +1478,7 @@ fn decode_ac12_field(msg: &[u8], unit: &mut AltitudeUnit) -> i32 {
 /// Get message length in bits based on Downlink Format
 pub fn message_len_by_type(df: u8) -> usize {
     match df {
-        16 | 17 | 19 | 20 | 21 => MODES_LONG_MSG_BITS,
+        16..=21 => MODES_LONG_MSG_BITS,
         _ => MODES_SHORT_MSG_BITS,
     }
 }
@@ -1348,17 +1528,51 @@ fn get_me_description(metype: u8, mesub: u8) -> &'static str {
     }
 }
 
-/// Parse a hex string message (from network input)
-pub fn decode_hex_message(hex: &str, fix_errors: bool, aggressive: bool) -> Option<ModesMessage> {
+/// Parse a hex string message (from network input) in any AVR variant,
+/// keeping its timestamp and signal level
+pub fn decode_hex_message(
+    hex: &str,
+    fix_errors: bool,
+    aggressive: bool,
+    fix_df: bool,
+) -> Option<ModesMessage> {
     let hex = hex.trim();
 
-    if hex.len() < 4 || !hex.starts_with('*') || !hex.ends_with(';') {
+    if hex.len() < 4 || !hex.ends_with(';') || !hex.is_ascii() {
         return None;
     }
+    let format = AvrFormat::from_marker(hex.chars().next()?)?;
+    let mut hex_data = &hex[1..hex.len() - 1];
+
+    // Timestamp and signal level prefixes
+    let mut timestamp = 0;
+    let mut signal = None;
+    if format.has_timestamp() {
+        timestamp = u64::from_str_radix(hex_data.get(..12)?, 16).ok()?;
+        hex_data = &hex_data[12..];
+    }
+    if format == AvrFormat::Signal {
+        signal = Some(u8::from_str_radix(hex_data.get(..2)?, 16).ok()?);
+        hex_data = &hex_data[2..];
+    }
+    let stamp = |mut mm: ModesMessage| {
+        mm.timestamp = timestamp;
+        if let Some(signal) = signal {
+            mm.signal_level = signal_level(signal);
+        }
+        mm
+    };
 
-    let hex_data = &hex[1..hex.len() - 1];
+    // Mode A/C replies are sent as 4 hex digits holding the octal code
+    if hex_data.len() == 4 {
+        let code = u16::from_str_radix(hex_data, 16).ok()?;
+        if code & 0x8888 != 0 {
+            return None; // Not an octal code
+        }
+        return Some(stamp(decode_mode_ac(code)));
+    }
 
-    if hex_data.len() > MODES_LONG_MSG_BYTES * 2 || hex_data.len() % 2 != 0 {
+    if hex_data.len() > MODES_LONG_MSG_BYTES * 2 || !hex_data.len().is_multiple_of(2) {
         return None;
     }
 
@@ -1369,11 +1583,12 @@ pub fn decode_hex_message(hex: &str, fix_errors: bool, aggressive: bool) -> Opti
         msg[i] = (high << 4) | low;
     }
 
-    Some(decode_modes_message(
+    Some(stamp(decode_modes_message(
         &msg[..hex_data.len() / 2],
         fix_errors,
         aggressive,
-    ))
+        fix_df,
+    )))
 }
 
 fn hex_digit_val(c: u8) -> Option<u8> {
@@ -1427,14 +1642,14 @@ mod tests {
 
     #[test]
     fn test_decode_hex_message_format() {
-        assert!(decode_hex_message("*8D4840D6202CC371C32CE0576098;", false, false).is_some());
-        assert!(decode_hex_message("8D4840D6202CC371C32CE0576098", false, false).is_none());
-        assert!(decode_hex_message("*;", false, false).is_none());
+        assert!(decode_hex_message("*8D4840D6202CC371C32CE0576098;", false, false, false).is_some());
+        assert!(decode_hex_message("8D4840D6202CC371C32CE0576098", false, false, false).is_none());
+        assert!(decode_hex_message("*;", false, false, false).is_none());
     }
 
     #[test]
     fn test_decode_df17_message() {
-        let msg = decode_hex_message("*8D4840D6202CC371C32CE0576098;", true, false);
+        let msg = decode_hex_message("*8D4840D6202CC371C32CE0576098;", true, false, false);
         assert!(msg.is_some());
         let msg = msg.unwrap();
         assert_eq!(msg.msg_type, 17);
@@ -1444,25 +1659,29 @@ mod tests {
 
     #[test]
     fn test_icao_address() {
-        let mut mm = ModesMessage::default();
-        mm.aa = [0x48, 0x40, 0xD6];
+        let mm = ModesMessage {
+            aa: [0x48, 0x40, 0xD6],
+            ..Default::default()
+        };
         assert_eq!(mm.icao_address(), 0x4840D6);
     }
 
     #[test]
     fn test_to_raw_string() {
-        let mut mm = ModesMessage::default();
-        mm.msg = [
-            0x8D, 0x48, 0x40, 0xD6, 0x20, 0x2C, 0xC3, 0x71, 0xC3, 0x2C, 0xE0, 0x57, 0x60, 0x98,
-        ];
-        mm.msg_bits = 112;
+        let mm = ModesMessage {
+            msg: [
+                0x8D, 0x48, 0x40, 0xD6, 0x20, 0x2C, 0xC3, 0x71, 0xC3, 0x2C, 0xE0, 0x57, 0x60, 0x98,
+            ],
+            msg_bits: 112,
+            ..Default::default()
+        };
         assert_eq!(mm.to_raw_string(), "*8D4840D6202CC371C32CE0576098;");
     }
 
     #[test]
     fn test_df4_icao_recovery() {
         // DF4 message - ICAO should be recovered from CRC
-        let msg = decode_hex_message("*20000f1f684a6c;", true, false);
+        let msg = decode_hex_message("*20000f1f684a6c;", true, false, false);
         assert!(msg.is_some());
         let msg = msg.unwrap();
         assert_eq!(msg.msg_type, 4);
@@ -1474,7 +1693,7 @@ mod tests {
     #[test]
     fn test_df5_icao_recovery() {
         // DF5 message - ICAO should be recovered from CRC
-        let msg = decode_hex_message("*280010248c796b;", true, false);
+        let msg = decode_hex_message("*280010248c796b;", true, false, false);
         assert!(msg.is_some());
         let msg = msg.unwrap();
         assert_eq!(msg.msg_type, 5);
@@ -1483,6 +1702,80 @@ mod tests {
         assert_ne!(msg.icao_address(), 0);
     }
 
+    #[test]
+    fn test_fix_address_parity() {
+        let clean = decode_hex_message("*20000f1f684a6c;", true, false, false).unwrap();
+        let icao = clean.icao_address();
+
+        // Flip a bit in the altitude field; the recovered ICAO is now wrong
+        let mut msg = clean.msg;
+        msg[3] ^= 0x08;
+        let damaged = decode_modes_message(&msg[..7], true, false, false);
+        assert_ne!(damaged.icao_address(), icao);
+
+        match fix_address_parity(&damaged, |a| a == icao, false) {
+            ApCorrection::Fixed(fixed) => {
+                assert_eq!(fixed.icao_address(), icao);
+                assert_eq!(fixed.error_bit, Some(28));
+                assert_eq!(fixed.msg, clean.msg);
+                assert!(fixed.crc_ok);
+            }
+            other => panic!("expected fix, got {:?}", other),
+        }
+
+        // Unknown addresses are never invented
+        assert!(matches!(
+            fix_address_parity(&damaged, |_| false, false),
+            ApCorrection::NotFound
+        ));
+    }
+
+    #[test]
+    fn test_bds40_selected_vertical_intention() {
+        // MCP and FMS 3008 ft, QNH 1020 hPa, no mode status
+        let mm = decode_hex_message("*A000029C85E42F313000007047D3;", true, false, false).unwrap();
+        assert_eq!(
+            mm.bds_data,
+            Some(BdsData::SelectedVerticalIntention {
+                mcp_altitude: Some(3008),
+                fms_altitude: Some(3008),
+                baro_setting: Some(1020.0),
+                nav_modes: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_geometric_altitude() {
+        // Velocity with GNSS 550 ft above baro
+        let mm = decode_hex_message("*8D485020994409940838175B284F;", true, false, false).unwrap();
+        assert_eq!(mm.gnss_baro_diff, Some(550));
+
+        // TC 20 airborne position with GNSS height
+        let mut msg = [0x8D, 0x48, 0x40, 0xD6, 0xA0, 0xC3, 0x81, 0, 0, 0, 0, 0, 0, 0];
+        let parity = crc::parity(&msg, MODES_LONG_MSG_BITS);
+        crc::write_parity_field(&mut msg, MODES_LONG_MSG_BITS, parity);
+        let mm = decode_modes_message(&msg, false, false, false);
+        assert_eq!(mm.me_type, 20);
+        assert_eq!(mm.geom_altitude, Some(38000));
+        assert_eq!(mm.altitude, 0);
+    }
+
+    #[test]
+    fn test_decode_mode_ac() {
+        let mm = decode_mode_ac(0x7700);
+        assert!(mm.is_mode_ac());
+        assert_eq!(mm.identity, 7700);
+        assert_eq!(mm.to_raw_string(), "*7700;");
+
+        let mm = decode_hex_message("*1200;", true, false, false).unwrap();
+        assert!(mm.is_mode_ac());
+        assert_eq!(mm.identity, 1200);
+
+        // Digits 8/9 can't appear in an octal code
+        assert!(decode_hex_message("*1890;", true, false, false).is_none());
+    }
+
     #[test]
     fn test_gray_to_binary() {
         // Test Gray code to binary conversion
@@ -1511,4 +1804,33 @@ mod tests {
         let result = decode_gillham_altitude(0x010);
         assert!(result.is_none() || result.unwrap() >= -1200);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_avr_variants() {
+        let plain = decode_hex_message("*8D4840D6202CC371C32CE0576098;", true, true, false).unwrap();
+        assert_eq!(plain.timestamp, 0);
+
+        let mlat = decode_hex_message("@00A1B2C3D4E58D4840D6202CC371C32CE0576098;", true, true, false).unwrap();
+        assert!(mlat.crc_ok);
+        assert_eq!(mlat.timestamp, 0x00A1_B2C3_D4E5);
+        assert_eq!(mlat.to_avr_string(AvrFormat::Mlat), "@00A1B2C3D4E58D4840D6202CC371C32CE0576098;");
+
+        let signal = decode_hex_message("<00A1B2C3D4E5805D4840D6F8740F;", true, true, false).unwrap();
+        assert_eq!(signal.icao_address(), 0x4840D6);
+        assert_eq!(signal.timestamp, 0x00A1_B2C3_D4E5);
+        assert_eq!(signal.to_avr_string(AvrFormat::Signal), "<00A1B2C3D4E5805D4840D6F8740F;");
+
+        // The ':' and '~' variants, Mode A/C, and truncated prefixes
+        let colon = decode_hex_message(":5D4840D6F8740F;", true, true, false).unwrap();
+        assert_eq!(colon.icao_address(), 0x4840D6);
+        assert_eq!(colon.to_avr_string(AvrFormat::Colon), ":5D4840D6F8740F;");
+        let tilde = decode_hex_message("~0000000000017700;", true, true, false).unwrap();
+        assert_eq!(tilde.identity, 7700);
+        assert_eq!(tilde.to_avr_string(AvrFormat::Tilde), "~0000000000017700;");
+        for format in [AvrFormat::Plain, AvrFormat::Colon, AvrFormat::Mlat, AvrFormat::Tilde, AvrFormat::Signal] {
+            assert_eq!(AvrFormat::from_marker(format.marker()), Some(format));
+        }
+        assert!(decode_hex_message("@00A1B2;", true, true, false).is_none());
+        assert!(decode_hex_message("<00A1B2C3D4E5;", true, true, false).is_none());
+    }
+}
@@ -2,79 +2,347 @@
 //!
 //!  Detects Mode S preambles and demodulates bit streams from magnitude data.
 
-use std::collections::HashSet;
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufReader, Read};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use crossbeam_channel::Sender;
+use parking_lot::RwLock;
+use serde::Serialize;
 use tracing::debug;
 
 use crate::config::Config;
-use crate::decoder::{self, MODES_LONG_MSG_BITS, ModesMessage};
-use crate::magnitude::{MagnitudeLut, compute_magnitude_vector};
+use crate::decoder::{self, ApCorrection, MODES_LONG_MSG_BITS, MODES_TIMESTAMP_MASK, ModesMessage};
+use crate::iq_correction::{IqCorrector, SharedIqCorrectionStats};
+use crate::magnitude::MagnitudeLut;
+use crate::metrics::Histogram;
+use crate::readsb::rssi_dbfs;
+use crate::sample_format::SampleFormat;
 use crate::signal::SignalProcessor;
+use crate::simd;
 
 /// Preamble duration in microseconds
 const MODES_PREAMBLE_US: usize = 8;
 /// Full message length for buffer sizing
 const MODES_FULL_LEN: usize = MODES_PREAMBLE_US + MODES_LONG_MSG_BITS;
+/// The only sample rate the demodulator handles (Hz)
+pub const MODES_SAMPLE_RATE: u32 = 2_000_000;
+/// Samples carried over from the previous block so messages spanning a
+/// block boundary are seen whole
+pub const MODES_OVERLAP_SAMPLES: usize = (MODES_FULL_LEN - 1) * 2;
+/// 12 MHz timestamp ticks per sample at 2 Msps
+const TICKS_PER_SAMPLE: u64 = 6;
 /// Default data buffer length
 const MODES_DATA_LEN: usize = 16 * 16384; // 256K
+/// Mode A/C F1 to F2 spacing in samples (20.3 µs at 2 Msps)
+const MODEAC_F2_OFFSET: f32 = 40.6;
+/// Mode A/C information pulse spacing in samples (1.45 µs at 2 Msps)
+const MODEAC_PULSE_SPACING: f32 = 2.9;
+/// Samples spanned by a Mode A/C reply, including a guard sample after F2
+const MODEAC_LEN: usize = 44;
+/// Minimum framing pulse level as a multiple of the noise floor (~12 dB)
+const MODEAC_MIN_LEVEL_FACTOR: u16 = 4;
+/// Clean (uncorrected) DF11/DF17/DF18 messages needed before an ICAO may
+/// be used to repair address/parity messages
+const AP_FIX_MIN_CLEAN_MESSAGES: u32 = 2;
+/// How recently a trusted ICAO must have been heard to repair AP messages
+const AP_FIX_WINDOW: Duration = Duration::from_secs(30);
+
+/// Per-ICAO evidence gathered from messages that carry the address in the clear
+#[derive(Debug, Clone, Copy)]
+struct IcaoEntry {
+    /// Last time a DF11/DF17/DF18 was received for this address
+    seen: Instant,
+    /// Number of those messages that passed CRC without correction
+    clean: u32,
+}
+
+/// Known ICAO addresses with a simple confidence model.
+///
+/// Any address heard in a valid DF11/DF17/DF18 validates exact
+/// address/parity matches. Repairing a damaged AP message is riskier,
+/// since every wrong bit guess yields some other address, so it is only
+/// allowed against addresses heard recently and cleanly more than once.
+#[derive(Debug, Default)]
+pub struct KnownIcaos {
+    entries: HashMap<u32, IcaoEntry>,
+}
+
+impl KnownIcaos {
+    /// Record a valid message with the ICAO in the clear
+    pub fn insert(&mut self, addr: u32, clean: bool) {
+        let entry = self.entries.entry(addr).or_insert(IcaoEntry {
+            seen: Instant::now(),
+            clean: 0,
+        });
+        entry.seen = Instant::now();
+        if clean {
+            entry.clean = entry.clean.saturating_add(1);
+        }
+    }
+
+    /// Whether the address has ever been validated
+    pub fn contains(&self, addr: u32) -> bool {
+        self.entries.contains_key(&addr)
+    }
+
+    /// Whether the address is trusted enough to attribute corrected AP messages
+    pub fn is_trusted(&self, addr: u32) -> bool {
+        self.entries.get(&addr).is_some_and(|e| {
+            e.clean >= AP_FIX_MIN_CLEAN_MESSAGES && e.seen.elapsed() <= AP_FIX_WINDOW
+        })
+    }
+}
+
+/// Counters for address/parity (DF0/4/5/16/20/21) validation
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ApStats {
+    /// Recovered ICAO matched a known address directly
+    pub exact: u64,
+    /// Recovered after single-bit correction against a trusted address
+    pub corrected: u64,
+    /// Correction matched several trusted addresses and was dropped
+    pub ambiguous: u64,
+    /// No known address matched
+    pub unknown: u64,
+}
+
+/// Demodulation counters for `/metrics` and the statistics reports
+#[derive(Debug, Clone, Default)]
+pub struct DemodStats {
+    /// Samples demodulated
+    pub samples: u64,
+    /// Preambles that passed the shape checks
+    pub preambles: u64,
+    /// Mode S messages passed on, by downlink format
+    pub messages_by_df: [u64; 32],
+    pub mode_ac: u64,
+    /// Messages passed on without correction
+    pub crc_good: u64,
+    pub crc_fixed_1bit: u64,
+    pub crc_fixed_2bit: u64,
+    /// Preambles that decoded to nothing usable
+    pub crc_bad: u64,
+    /// Messages recovered at a one-sample phase offset
+    pub phase_corrected: u64,
+    pub noise_floor_dbfs: f64,
+    /// Noise floor estimate per block
+    pub noise: Histogram,
+    /// Signal level per message
+    pub signal: Histogram,
+    pub address_parity: ApStats,
+}
+
+pub type SharedDemodStats = Arc<RwLock<DemodStats>>;
 
 /// Mode S demodulator with signal processing
 pub struct Demodulator {
     config: Config,
     pub mag_lut: MagnitudeLut,
-    /// Set of known ICAO addresses (from DF11/DF17 messages)
-    known_icaos: HashSet<u32>,
+    /// Known ICAO addresses (from DF11/DF17/DF18 messages)
+    known_icaos: KnownIcaos,
     /// Signal processor for SNR and noise floor tracking
     signal_processor: SignalProcessor,
+    /// Address/parity validation counters
+    ap_stats: ApStats,
+    /// Messages passed on to the decoder channel
+    messages: u64,
+    /// Demodulation counters, and where to publish them after each block
+    stats: DemodStats,
+    shared_stats: Option<SharedDemodStats>,
+    /// DC offset / I/Q balance correction, when enabled
+    iq_corrector: Option<IqCorrector>,
+    /// Magnitude buffer reused from block to block
+    magnitude: Vec<u16>,
+    /// Preamble candidate buffer reused from block to block
+    candidates: Vec<usize>,
+    /// New samples seen so far (excluding the carried-over overlap)
+    samples: u64,
+    /// Stream position of the first sample in the current block
+    block_start: u64,
 }
 
 impl Demodulator {
+    #[allow(dead_code)]
     pub fn new(config: Config) -> Self {
+        Self::with_iq_stats(config, Default::default())
+    }
+
+    /// Create a demodulator that publishes its I/Q correction values to `iq_stats`
+    pub fn with_iq_stats(config: Config, iq_stats: SharedIqCorrectionStats) -> Self {
+        let iq_corrector = (config.dc_filter || config.iq_balance)
+            .then(|| IqCorrector::new(config.iq_balance, iq_stats));
         Self {
             config,
             mag_lut: MagnitudeLut::new(),
-            known_icaos: HashSet::new(),
+            known_icaos: KnownIcaos::default(),
             signal_processor: SignalProcessor::new(),
+            ap_stats: ApStats::default(),
+            messages: 0,
+            stats: DemodStats::default(),
+            shared_stats: None,
+            iq_corrector,
+            magnitude: Vec::new(),
+            candidates: Vec::new(),
+            samples: 0,
+            block_start: 0,
+        }
+    }
+
+    /// Publish demodulation counters to `stats` after every block
+    pub fn with_demod_stats(mut self, stats: SharedDemodStats) -> Self {
+        self.shared_stats = Some(stats);
+        self
+    }
+
+    /// Convert raw I/Q bytes to magnitudes in the reused buffer, applying
+    /// DC offset and I/Q balance correction when enabled (the plain
+    /// vectorised path otherwise)
+    ///
+    /// Every block starts with the last `MODES_OVERLAP_SAMPLES` of the
+    /// previous one; this also advances the sample clock.
+    fn compute_magnitude(&mut self, data: &[u8], format: SampleFormat) -> Vec<u16> {
+        let mut magnitude = std::mem::take(&mut self.magnitude);
+        match self.iq_corrector.as_mut() {
+            Some(corrector) => corrector.compute_magnitude_into(data, format, &mut magnitude),
+            None => format.compute_magnitude_into(data, &self.mag_lut, &mut magnitude),
+        }
+        // Before the first block the overlap is padding, at negative positions
+        self.block_start = self.samples.wrapping_sub(MODES_OVERLAP_SAMPLES as u64);
+        self.samples += magnitude.len().saturating_sub(MODES_OVERLAP_SAMPLES) as u64;
+        magnitude
+    }
+
+    /// Demodulate a block of raw I/Q bytes from a live source
+    pub fn process_samples(&mut self, data: &[u8], format: SampleFormat, tx: &Sender<ModesMessage>) {
+        let magnitude = self.compute_magnitude(data, format);
+        self.detect_modes_external(&magnitude, tx);
+        self.magnitude = magnitude;
+    }
+
+    /// Number of messages passed on so far
+    pub fn message_count(&self) -> u64 {
+        self.messages
+    }
+
+    /// Pass a message found at sample `pos` of the current block on to the
+    /// decoder channel, stamped with the 12 MHz sample clock
+    fn emit(&mut self, tx: &Sender<ModesMessage>, mut mm: ModesMessage, pos: usize) {
+        mm.timestamp = self.block_start.wrapping_add(pos as u64).wrapping_mul(TICKS_PER_SAMPLE) & MODES_TIMESTAMP_MASK;
+        self.messages += 1;
+        if mm.is_mode_ac() {
+            self.stats.mode_ac += 1;
+        } else {
+            self.stats.messages_by_df[mm.msg_type as usize & 31] += 1;
+            match (mm.error_bit, mm.error_bit2) {
+                (Some(_), Some(_)) => self.stats.crc_fixed_2bit += 1,
+                (Some(_), None) => self.stats.crc_fixed_1bit += 1,
+                _ => self.stats.crc_good += 1,
+            }
+            if mm.phase_corrected {
+                self.stats.phase_corrected += 1;
+            }
+        }
+        self.stats.signal.observe(rssi_dbfs(mm.signal_level));
+        let _ = tx.send(mm);
+    }
+
+    /// Record the block's noise floor and publish the counters
+    fn finish_block(&mut self) {
+        let noise = rssi_dbfs(self.signal_processor.noise_floor());
+        self.stats.noise_floor_dbfs = noise;
+        self.stats.noise.observe(noise);
+        self.stats.samples = self.samples;
+        self.stats.address_parity = self.ap_stats.clone();
+        if let Some(shared) = &self.shared_stats {
+            *shared.write() = self.stats.clone();
+        }
+    }
+
+    /// Validate an address/parity message against the known ICAO set.
+    ///
+    /// Exact matches are accepted outright; otherwise, if error correction
+    /// is enabled, a single-bit repair is attempted against trusted addresses.
+    fn validate_address_parity(&mut self, mut mm: ModesMessage) -> Option<ModesMessage> {
+        if self.known_icaos.contains(mm.icao_address()) {
+            self.ap_stats.exact += 1;
+            mm.crc_ok = true;
+            return Some(mm);
+        }
+
+        if !self.config.fix_errors {
+            self.ap_stats.unknown += 1;
+            return None;
+        }
+
+        let known = &self.known_icaos;
+        match decoder::fix_address_parity(&mm, |a| known.is_trusted(a), self.config.fix_df) {
+            ApCorrection::Fixed(fixed) => {
+                self.ap_stats.corrected += 1;
+                debug!(
+                    "Corrected DF{} bit {:?} for {:06X}",
+                    fixed.msg_type,
+                    fixed.error_bit,
+                    fixed.icao_address()
+                );
+                Some(fixed)
+            }
+            ApCorrection::Ambiguous => {
+                self.ap_stats.ambiguous += 1;
+                None
+            }
+            ApCorrection::NotFound => {
+                self.ap_stats.unknown += 1;
+                None
+            }
         }
     }
 
     /// Get current noise floor estimate
-    #[allow(dead_code)]
     pub fn noise_floor(&self) -> u16 {
         self.signal_processor.noise_floor()
     }
 
     /// Process data from a file
-    pub fn process_file(&self, filename: &str, tx: &Sender<ModesMessage>) -> std::io::Result<()> {
+    pub fn process_file(&mut self, filename: &str, tx: &Sender<ModesMessage>) -> std::io::Result<()> {
+        if self.config.sample_rate != MODES_SAMPLE_RATE {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "unsupported sample rate {} Hz (only {} Hz is supported)",
+                    self.config.sample_rate, MODES_SAMPLE_RATE
+                ),
+            ));
+        }
+
         let file: Box<dyn Read> = if filename == "-" {
             Box::new(std::io::stdin())
         } else {
             Box::new(File::open(filename)?)
         };
 
-        let mut reader = BufReader::with_capacity(MODES_DATA_LEN, file);
+        let format = self.config.sample_format;
+        let bytes_per_sample = format.bytes_per_sample();
+        let data_len = MODES_DATA_LEN / 2 * bytes_per_sample;
+        let overlap = MODES_OVERLAP_SAMPLES * bytes_per_sample;
 
-        let buffer_len = MODES_DATA_LEN + (MODES_FULL_LEN - 1) * 4;
-        let mut data = vec![127u8; buffer_len];
+        let mut reader = BufReader::with_capacity(data_len, file);
 
-        // Track known ICAOs locally for this processing run
-        let mut known_icaos: HashSet<u32> = HashSet::new();
+        let mut data = vec![format.zero_byte(); data_len + overlap];
 
         loop {
-            let overlap = (MODES_FULL_LEN - 1) * 4;
-            data.copy_within(MODES_DATA_LEN..MODES_DATA_LEN + overlap, 0);
+            data.copy_within(data_len..data_len + overlap, 0);
 
-            let bytes_read = reader.read(&mut data[overlap..overlap + MODES_DATA_LEN])?;
+            let bytes_read = read_full(&mut reader, &mut data[overlap..overlap + data_len])?;
 
             if bytes_read == 0 {
                 if self.config.loop_file && filename != "-" {
                     drop(reader);
                     if let Ok(file) = File::open(filename) {
                         reader = BufReader::with_capacity(
-                            MODES_DATA_LEN,
+                            data_len,
                             Box::new(file) as Box<dyn Read>,
                         );
                         debug!("Looping file");
@@ -84,14 +352,16 @@ impl Demodulator {
                 break;
             }
 
-            if bytes_read < MODES_DATA_LEN {
-                data[overlap + bytes_read..].fill(127);
+            if bytes_read < data_len {
+                data[overlap + bytes_read..].fill(format.zero_byte());
             }
 
-            let magnitude = compute_magnitude_vector(&data[..overlap + bytes_read], &self.mag_lut);
-            self.detect_modes_with_icao_tracking(&magnitude, tx, &mut known_icaos);
+            let magnitude = self.compute_magnitude(&data[..overlap + bytes_read], format);
+            self.detect_modes_with_icao_tracking(&magnitude, tx);
+            self.magnitude = magnitude;
         }
 
+        debug!("Address/parity messages: {:?}", self.ap_stats);
         Ok(())
     }
 
@@ -106,22 +376,15 @@ impl Demodulator {
         // Update noise floor estimate periodically
         self.signal_processor.update_noise_floor(magnitude);
 
-        let mut j = 0;
-
-        while j < mlen.saturating_sub(MODES_FULL_LEN * 2) {
-            // Check preamble pattern
-            if !(magnitude[j] > magnitude[j + 1]
-                && magnitude[j + 1] < magnitude[j + 2]
-                && magnitude[j + 2] > magnitude[j + 3]
-                && magnitude[j + 3] < magnitude[j]
-                && magnitude[j + 4] < magnitude[j]
-                && magnitude[j + 5] < magnitude[j]
-                && magnitude[j + 6] < magnitude[j]
-                && magnitude[j + 7] > magnitude[j + 8]
-                && magnitude[j + 8] < magnitude[j + 9]
-                && magnitude[j + 9] > magnitude[j + 6])
-            {
-                j += 1;
+        // Sample ranges covered by decoded Mode S messages
+        let mut decoded: Vec<(usize, usize)> = Vec::new();
+        // Positions matching the preamble pattern; `next` skips past decoded messages
+        let mut candidates = std::mem::take(&mut self.candidates);
+        simd::preamble_candidates(magnitude, mlen.saturating_sub(MODES_FULL_LEN * 2), &mut candidates);
+        let mut next = 0;
+
+        for &j in &candidates {
+            if j < next {
                 continue;
             }
 
@@ -135,7 +398,6 @@ impl Demodulator {
 
             // Check levels between spikes
             if magnitude[j + 4] >= high || magnitude[j + 5] >= high {
-                j += 1;
                 continue;
             }
 
@@ -145,29 +407,40 @@ impl Demodulator {
                 || magnitude[j + 13] >= high
                 || magnitude[j + 14] >= high
             {
-                j += 1;
                 continue;
             }
 
+            self.stats.preambles += 1;
+
             // Calculate signal level from preamble peaks
-            let signal_level = preamble_peaks.iter().sum::<u16>() / 4;
+            let signal_level = (preamble_peaks.iter().map(|&x| x as u32).sum::<u32>() / 4) as u16;
 
             // Try to decode with normal phase first
             if let Some(mm) = self.try_decode_message(magnitude, j, false, signal_level, tx) {
-                j += (MODES_PREAMBLE_US + mm.msg_bits / 8 * 8) * 2;
+                let end = j + (MODES_PREAMBLE_US + mm.msg_bits / 8 * 8) * 2;
+                decoded.push((j, end));
+                next = end;
                 continue;
             }
 
             // If normal phase failed, try with phase correction (1 sample offset)
-            if self.signal_processor.should_try_phase_correction(signal_level) {
-                if let Some(mm) = self.try_decode_message(magnitude, j + 1, true, signal_level, tx) {
-                    j += (MODES_PREAMBLE_US + mm.msg_bits / 8 * 8) * 2;
-                    continue;
-                }
+            if self.signal_processor.should_try_phase_correction(signal_level)
+                && let Some(mm) = self.try_decode_message(magnitude, j + 1, true, signal_level, tx)
+            {
+                let end = j + (MODES_PREAMBLE_US + mm.msg_bits / 8 * 8) * 2;
+                decoded.push((j, end));
+                next = end;
+                continue;
             }
 
-            j += 1;
+            self.stats.crc_bad += 1;
+        }
+        self.candidates = candidates;
+
+        if self.config.mode_ac {
+            self.detect_mode_ac(magnitude, &decoded, tx);
         }
+        self.finish_block();
     }
 
     /// Try to decode a message at the given position
@@ -226,27 +499,24 @@ impl Demodulator {
             &msg[..msg_len],
             self.config.fix_errors,
             self.config.aggressive,
+            self.config.fix_df,
         );
         mm.phase_corrected = phase_corrected;
         mm.signal_level = signal_level;
 
         // For messages with ICAO in CRC, validate against known ICAOs
-        let icao_in_message = matches!(mm.msg_type, 11 | 17 | 18);
-
-        if mm.crc_ok && icao_in_message {
+        if mm.crc_ok && !mm.is_address_parity() {
             // Valid message with explicit ICAO - add to known set
-            self.known_icaos.insert(mm.icao_address());
-            let _ = tx.send(mm.clone());
+            self.known_icaos.insert(mm.icao_address(), mm.error_bit.is_none());
+            self.emit(tx, mm.clone(), start_pos);
             if phase_corrected {
                 debug!("Phase correction recovered DF{} from {:06X}", mm.msg_type, mm.icao_address());
             }
             return Some(mm);
-        } else if !icao_in_message {
+        } else if mm.is_address_parity() {
             // DF0, DF4, DF5, DF16, DF20, DF21 - check if recovered ICAO is known
-            let recovered_icao = mm.icao_address();
-            if self.known_icaos.contains(&recovered_icao) {
-                mm.crc_ok = true;
-                let _ = tx.send(mm.clone());
+            if let Some(mm) = self.validate_address_parity(mm) {
+                self.emit(tx, mm.clone(), start_pos);
                 return Some(mm);
             }
         }
@@ -255,33 +525,24 @@ impl Demodulator {
     }
 
     /// Detect Mode S messages in magnitude data with ICAO tracking
-    fn detect_modes_with_icao_tracking(
-        &self,
-        m: &[u16],
-        tx: &Sender<ModesMessage>,
-        known_icaos: &mut HashSet<u32>,
-    ) {
+    fn detect_modes_with_icao_tracking(&mut self, m: &[u16], tx: &Sender<ModesMessage>) {
         let mlen = m.len();
         if mlen < MODES_FULL_LEN * 2 {
             return;
         }
 
-        let mut j = 0;
-
-        while j < mlen.saturating_sub(MODES_FULL_LEN * 2) {
-            // Check preamble pattern
-            if !(m[j] > m[j + 1]
-                && m[j + 1] < m[j + 2]
-                && m[j + 2] > m[j + 3]
-                && m[j + 3] < m[j]
-                && m[j + 4] < m[j]
-                && m[j + 5] < m[j]
-                && m[j + 6] < m[j]
-                && m[j + 7] > m[j + 8]
-                && m[j + 8] < m[j + 9]
-                && m[j + 9] > m[j + 6])
-            {
-                j += 1;
+        // Noise reference for Mode A/C detection and the metrics
+        self.signal_processor.update_noise_floor(m);
+
+        // Sample ranges covered by decoded Mode S messages
+        let mut decoded: Vec<(usize, usize)> = Vec::new();
+        // Positions matching the preamble pattern; `next` skips past decoded messages
+        let mut candidates = std::mem::take(&mut self.candidates);
+        simd::preamble_candidates(m, mlen.saturating_sub(MODES_FULL_LEN * 2), &mut candidates);
+        let mut next = 0;
+
+        for &j in &candidates {
+            if j < next {
                 continue;
             }
 
@@ -291,15 +552,14 @@ impl Demodulator {
 
             // Check levels between spikes
             if m[j + 4] >= high || m[j + 5] >= high {
-                j += 1;
                 continue;
             }
 
             // Check space between preamble and data
             if m[j + 11] >= high || m[j + 12] >= high || m[j + 13] >= high || m[j + 14] >= high {
-                j += 1;
                 continue;
             }
+            self.stats.preambles += 1;
 
             // Decode all 112 bits
             let mut bits = [0u8; MODES_LONG_MSG_BITS];
@@ -345,29 +605,304 @@ impl Demodulator {
                 &msg[..msg_len],
                 self.config.fix_errors,
                 self.config.aggressive,
+                self.config.fix_df,
             );
+            mm.signal_level = ((m[j] as u32 + m[j + 2] as u32 + m[j + 7] as u32 + m[j + 9] as u32) / 4) as u16;
 
             // For messages with ICAO in CRC, validate against known ICAOs
-            let icao_in_message = matches!(mm.msg_type, 11 | 17 | 18);
-
-            if mm.crc_ok && icao_in_message {
+            if mm.crc_ok && !mm.is_address_parity() {
                 // Valid message with explicit ICAO - add to known set
-                known_icaos.insert(mm.icao_address());
-                j += (MODES_PREAMBLE_US + msg_len * 8) * 2;
-                let _ = tx.send(mm);
-            } else if !icao_in_message {
+                self.known_icaos.insert(mm.icao_address(), mm.error_bit.is_none());
+                let end = j + (MODES_PREAMBLE_US + msg_len * 8) * 2;
+                decoded.push((j, end));
+                next = end;
+                self.emit(tx, mm, j);
+                continue;
+            } else if mm.is_address_parity() {
                 // DF0, DF4, DF5, DF16, DF20, DF21 - check if recovered ICAO is known
-                let recovered_icao = mm.icao_address();
-                if known_icaos.contains(&recovered_icao) {
-                    mm.crc_ok = true;
-                    j += (MODES_PREAMBLE_US + msg_len * 8) * 2;
-                    let _ = tx.send(mm);
-                } else {
-                    j += 1;
+                if let Some(mm) = self.validate_address_parity(mm) {
+                    let end = j + (MODES_PREAMBLE_US + msg_len * 8) * 2;
+                    decoded.push((j, end));
+                    next = end;
+                    self.emit(tx, mm, j);
+                    continue;
                 }
-            } else {
-                j += 1;
             }
+            self.stats.crc_bad += 1;
+        }
+        self.candidates = candidates;
+
+        if self.config.mode_ac {
+            self.detect_mode_ac(m, &decoded, tx);
+        }
+        self.finish_block();
+    }
+
+    /// Detect Mode A/C replies in magnitude data.
+    ///
+    /// A reply is a pair of framing pulses F1/F2 20.3 µs apart with up to
+    /// 13 information pulses on a 1.45 µs grid between them. At 2 Msps that
+    /// grid is 2.9 samples, so each pulse is read as the larger of the two
+    /// samples around its nominal position. Ranges already decoded as
+    /// Mode S (`skip`, sorted by start) are not searched.
+    fn detect_mode_ac(&mut self, m: &[u16], skip: &[(usize, usize)], tx: &Sender<ModesMessage>) {
+        let mlen = m.len();
+        let min_level = self.signal_processor.noise_floor().saturating_mul(MODEAC_MIN_LEVEL_FACTOR).max(1);
+        let mut skip = skip.iter().peekable();
+        let mut i = 2;
+
+        // The next block starts with our last MODES_OVERLAP_SAMPLES and scans
+        // from its own index 2, so stop where that scan takes over
+        let end = (mlen + 2).saturating_sub(MODES_OVERLAP_SAMPLES);
+
+        while i < end && i + MODEAC_LEN < mlen {
+            // Stay clear of Mode S messages
+            if let Some(&&(start, end)) = skip.peek() {
+                if i >= end {
+                    skip.next();
+                    continue;
+                }
+                if i + MODEAC_LEN > start {
+                    i = end;
+                    continue;
+                }
+            }
+
+            match decode_mode_ac_pulses(m, i, min_level) {
+                Some(code) => {
+                    let mut mm = decoder::decode_mode_ac(code);
+                    mm.signal_level = ((m[i] as u32 + pulse_at(m, i, MODEAC_F2_OFFSET) as u32) / 2) as u16;
+                    self.emit(tx, mm, i);
+                    i += MODEAC_LEN;
+                }
+                None => i += 1,
+            }
+        }
+    }
+}
+
+/// Read until `buf` is full or the reader hits EOF, returning the bytes read
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut total = 0;
+    while total < buf.len() {
+        match reader.read(&mut buf[total..]) {
+            Ok(0) => break,
+            Ok(n) => total += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(total)
+}
+
+/// Larger of the two samples around a (fractional) pulse offset from `base`
+#[inline]
+fn pulse_at(m: &[u16], base: usize, offset: f32) -> u16 {
+    let idx = base + offset as usize;
+    m[idx].max(m[idx + 1])
+}
+
+/// Try to read a Mode A/C reply whose F1 pulse is at sample `i`.
+///
+/// Returns the 4-digit octal code packed as nibbles (squawk 7700 -> 0x7700).
+fn decode_mode_ac_pulses(m: &[u16], i: usize, min_level: u16) -> Option<u16> {
+    let f1 = m[i];
+    if f1 < min_level || m[i - 1] > f1 || m[i + 1] > f1 {
+        return None;
+    }
+
+    let f2 = pulse_at(m, i, MODEAC_F2_OFFSET);
+    if f2 < min_level || f1.max(f2) / 2 > f1.min(f2) {
+        return None; // Framing pulses must be present and of similar level
+    }
+
+    // Pulses count as present above half the framing pulse level
+    let high = ((f1 as u32 + f2 as u32) / 4) as u16;
+    if m[i - 2] >= high || m[i + MODEAC_LEN - 1] >= high {
+        return None; // Must be quiet before F1 and after F2
+    }
+
+    let mut pulses = [false; 13];
+    for (k, pulse) in pulses.iter_mut().enumerate() {
+        let level = pulse_at(m, i, MODEAC_PULSE_SPACING * (k + 1) as f32);
+        if level >= high {
+            *pulse = true;
+        } else if level > high / 2 {
+            return None; // Neither clearly on nor clearly off
+        }
+    }
+
+    // Pulse order: C1 A1 C2 A2 C4 A4 X B1 D1 B2 D2 B4 D4
+    let [c1, a1, c2, a2, c4, a4, x, b1, d1, b2, d2, b4, d4] = pulses.map(u16::from);
+    if x != 0 {
+        return None; // The X position is never used
+    }
+
+    let a = (a4 << 2) | (a2 << 1) | a1;
+    let b = (b4 << 2) | (b2 << 1) | b1;
+    let c = (c4 << 2) | (c2 << 1) | c1;
+    let d = (d4 << 2) | (d2 << 1) | d1;
+    let code = (a << 12) | (b << 8) | (c << 4) | d;
+
+    // An empty reply is indistinguishable from two stray noise spikes
+    if code == 0 {
+        return None;
+    }
+    Some(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::decode_hex_message;
+    use crate::generator::{self, GeneratorConfig, Multipath};
+    use crate::sample_format::SampleFormat;
+
+    /// Decode an AVR frame to transmit
+    fn message(hex: &str) -> ModesMessage {
+        decode_hex_message(hex, false, false, false).unwrap()
+    }
+
+    /// Generate a file of synthetic frames, run it through `process_file`
+    /// and return the messages that came out
+    fn demodulate_generated(
+        name: &str,
+        gen_config: &GeneratorConfig,
+        format: SampleFormat,
+        messages: &[ModesMessage],
+        interval_us: f64,
+    ) -> Vec<ModesMessage> {
+        let messages: Vec<_> = messages.iter().map(|mm| (mm.clone(), 0.0)).collect();
+        let (emissions, duration) = generator::schedule(&messages, 1, interval_us);
+        let iq = generator::generate(gen_config, &emissions, duration);
+
+        let path = std::env::temp_dir().join(format!("adsb-e2e-{}-{}", name, std::process::id()));
+        std::fs::write(&path, format.encode(&iq)).unwrap();
+
+        let config = Config { sample_format: format, ..Config::default() };
+        let mut demodulator = Demodulator::new(config);
+        let (tx, rx) = crossbeam_channel::unbounded();
+        demodulator.process_file(path.to_str().unwrap(), &tx).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        drop(tx);
+        rx.iter().collect()
+    }
+
+    #[test]
+    fn test_generated_frames_decode() {
+        // Decoded messages go out as IQ and come back unchanged. The DF4
+        // reply is only trusted once the DF17 before it has made 4840D6 known
+        let sent = [
+            message("*8D4840D6202CC371C32CE0576098;"),
+            message("*8D40621D58C382D690C8AC2863A7;"),
+            message("*5D4840D6F8740F;"),
+            generator::message_from_hex("20001838", Some(0x4840D6)).unwrap(),
+        ];
+        let gen_config = GeneratorConfig { snr_db: Some(15.0), freq_offset_hz: 40_000.0, ..Default::default() };
+
+        for format in [SampleFormat::Cu8, SampleFormat::Cs16, SampleFormat::Cf32] {
+            let received: Vec<_> = demodulate_generated("frames", &gen_config, format, &sent, 500.0)
+                .into_iter()
+                .filter(|m| m.crc_ok)
+                .collect();
+            assert_eq!(received.len(), sent.len(), "{}", format);
+            for (rx, tx) in received.iter().zip(&sent) {
+                assert_eq!(generator::frame_from_message(rx), generator::frame_from_message(tx), "{}", format);
+                assert_eq!(rx.msg_type, tx.msg_type);
+                assert_eq!(rx.icao_address(), tx.icao_address());
+            }
+        }
+    }
+
+    #[test]
+    fn test_generated_overlap_and_multipath() {
+        // A weak echo well after the frame doesn't stop decoding
+        let gen_config = GeneratorConfig {
+            multipath: Some(Multipath { delay_us: 150.0, level_db: -10.0 }),
+            ..Default::default()
+        };
+        let sent = [message("*8D4840D6202CC371C32CE0576098;")];
+        let messages = demodulate_generated("multipath", &gen_config, SampleFormat::Cu8, &sent, 1000.0);
+        assert!(messages.iter().any(|m| m.crc_ok && m.icao_address() == 0x4840D6));
+
+        // A frame starting inside another can only be recovered if it's
+        // much stronger; the earlier one is garbled
+        let gen_config = GeneratorConfig { snr_db: None, ..Default::default() };
+        let messages =
+            [(message("*8D4840D6202CC371C32CE0576098;"), 0.0), (message("*8D40621D58C382D690C8AC2863A7;"), 20.0)];
+        let (emissions, duration) = generator::schedule(&messages, 1, 60.0);
+        let iq = generator::generate(&gen_config, &emissions, duration);
+        let mut padded = Vec::new();
+        SampleFormat::Cf32.compute_magnitude_into(&SampleFormat::Cf32.encode(&iq), &MagnitudeLut::new(), &mut padded);
+        padded.resize(padded.len() + MODES_FULL_LEN * 2, 0);
+
+        let mut demodulator = Demodulator::new(Config::default());
+        let (tx, rx) = crossbeam_channel::unbounded();
+        demodulator.detect_modes_external(&padded, &tx);
+        drop(tx);
+        let icaos: Vec<u32> = rx.iter().filter(|m| m.crc_ok).map(|m| m.icao_address()).collect();
+        assert!(icaos.contains(&0x40621D));
+    }
+
+    /// Build a magnitude buffer holding one Mode A/C reply at `start`
+    fn mode_ac_samples(start: usize, pulses: &[usize]) -> Vec<u16> {
+        let mut m = vec![50u16; 200];
+        m[start] = 5000;
+        m[start + 41] = 5000;
+        for &k in pulses {
+            m[start + (MODEAC_PULSE_SPACING * k as f32).round() as usize] = 5000;
         }
+        m
+    }
+
+    #[test]
+    fn test_mode_ac_pulses() {
+        // Squawk 1200: A1 (k=2), B2 (k=10)
+        let m = mode_ac_samples(20, &[2, 10]);
+        assert_eq!(decode_mode_ac_pulses(&m, 20, 200), Some(0x1200));
+
+        // The X pulse (k=7) is never set in a real reply
+        let m = mode_ac_samples(20, &[2, 7, 10]);
+        assert_eq!(decode_mode_ac_pulses(&m, 20, 200), None);
+
+        // No F2 pulse
+        let mut m = mode_ac_samples(20, &[2, 10]);
+        m[61] = 50;
+        assert_eq!(decode_mode_ac_pulses(&m, 20, 200), None);
+    }
+
+    #[test]
+    fn test_mode_ac_in_overlap_emitted_once() {
+        // One reply mid-block and one whose F1 lands in the first block's
+        // last MODES_OVERLAP_SAMPLES, which the second block rescans. Both
+        // start a little after a sample so F1 and F2 land at similar levels
+        let block = MODES_DATA_LEN / 2;
+        let reply = |sample: usize, code: u16| generator::Emission {
+            frame: Vec::new(),
+            mode_ac: Some(code),
+            start_us: sample as f64 / 2.0 + 0.1,
+            level_db: 0.0,
+        };
+        let emissions = [reply(block / 2, 0x7700), reply(block - MODES_OVERLAP_SAMPLES / 2, 0x1200)];
+        let gen_config = GeneratorConfig { snr_db: Some(30.0), ..Default::default() };
+        let iq = generator::generate(&gen_config, &emissions, block as f64 * 1.5);
+
+        let path = std::env::temp_dir().join(format!("adsb-e2e-modeac-{}", std::process::id()));
+        std::fs::write(&path, SampleFormat::Cu8.encode(&iq)).unwrap();
+
+        let config = Config { mode_ac: true, ..Config::default() };
+        let mut demodulator = Demodulator::new(config);
+        let (tx, rx) = crossbeam_channel::unbounded();
+        demodulator.process_file(path.to_str().unwrap(), &tx).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        drop(tx);
+        let codes: Vec<u16> = rx
+            .iter()
+            .filter(|mm| mm.is_mode_ac())
+            .map(|mm| ((mm.msg[0] as u16) << 8) | mm.msg[1] as u16)
+            .collect();
+        assert_eq!(codes, vec![0x7700, 0x1200]);
     }
 }
@@ -0,0 +1,169 @@
+//! Gain sweep
+//!
+//! Steps the receiver through a list of gain settings, dwelling on each
+//! for a fixed amount of sample time, and reports the message rate at
+//! each so the best setting for a site can be picked.
+
+use std::fmt::Write;
+
+use crate::agc::{HACKRF_LNA_MAX_DB, HACKRF_VGA_MAX_DB, RTLSDR_GAINS_DB};
+use crate::config::{Config, DeviceType};
+
+/// HackRF sweep step in dB of total LNA + VGA gain
+const HACKRF_SWEEP_STEP_DB: u32 = 6;
+
+/// Messages seen at one gain setting
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweepResult {
+    pub gain_db: f64,
+    pub messages: u64,
+    pub seconds: f64,
+}
+
+impl SweepResult {
+    /// Messages per second
+    pub fn rate(&self) -> f64 {
+        if self.seconds > 0.0 { self.messages as f64 / self.seconds } else { 0.0 }
+    }
+}
+
+/// What the capture loop should do after feeding the sweep
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SweepStep {
+    /// Keep capturing at the current gain
+    Continue,
+    /// Retune to this gain (dB)
+    Next(f64),
+    /// All steps measured
+    Done,
+}
+
+/// Gain sweep state
+pub struct GainSweep {
+    steps: Vec<f64>,
+    index: usize,
+    dwell_bytes: u64,
+    bytes_per_second: u64,
+    bytes: u64,
+    start_count: u64,
+    results: Vec<SweepResult>,
+}
+
+impl GainSweep {
+    /// Sweep `steps` (dB), spending `dwell_seconds` of samples on each
+    pub fn new(steps: Vec<f64>, dwell_seconds: u64, bytes_per_second: u64) -> Self {
+        assert!(!steps.is_empty(), "gain sweep needs at least one step");
+        Self {
+            steps,
+            index: 0,
+            dwell_bytes: dwell_seconds.max(1) * bytes_per_second,
+            bytes_per_second,
+            bytes: 0,
+            start_count: 0,
+            results: Vec::new(),
+        }
+    }
+
+    /// Sweep over the configured device's gain range
+    pub fn for_config(config: &Config, dwell_seconds: u64) -> Self {
+        let steps = match config.device_type {
+            DeviceType::RtlSdr | DeviceType::RtlTcp => RTLSDR_GAINS_DB.to_vec(),
+            DeviceType::HackRf => (0..=HACKRF_LNA_MAX_DB + HACKRF_VGA_MAX_DB)
+                .step_by(HACKRF_SWEEP_STEP_DB as usize)
+                .map(f64::from)
+                .collect(),
+        };
+        // 8-bit I/Q at 2 Msps
+        Self::new(steps, dwell_seconds, 2_000_000 * 2)
+    }
+
+    /// Gain to capture at now
+    pub fn gain_db(&self) -> f64 {
+        self.steps[self.index]
+    }
+
+    /// Feed the number of sample bytes just processed and the running
+    /// total of decoded messages
+    pub fn observe(&mut self, bytes: usize, message_count: u64) -> SweepStep {
+        self.bytes += bytes as u64;
+        if self.bytes < self.dwell_bytes {
+            return SweepStep::Continue;
+        }
+
+        self.results.push(SweepResult {
+            gain_db: self.gain_db(),
+            messages: message_count - self.start_count,
+            seconds: self.bytes as f64 / self.bytes_per_second as f64,
+        });
+        self.bytes = 0;
+        self.start_count = message_count;
+        self.index += 1;
+
+        if self.index < self.steps.len() {
+            SweepStep::Next(self.gain_db())
+        } else {
+            SweepStep::Done
+        }
+    }
+
+    /// Results so far, in sweep order
+    #[allow(dead_code)]
+    pub fn results(&self) -> &[SweepResult] {
+        &self.results
+    }
+
+    /// The setting with the highest message rate
+    pub fn best(&self) -> Option<&SweepResult> {
+        self.results.iter().max_by(|a, b| a.rate().total_cmp(&b.rate()))
+    }
+
+    /// Human-readable results table
+    pub fn report(&self) -> String {
+        let max_rate = self.results.iter().map(SweepResult::rate).fold(0.0, f64::max);
+        let mut out = String::from("Gain sweep results:\n  Gain (dB)  Messages   Msg/s\n");
+        for r in &self.results {
+            let bar = if max_rate > 0.0 { (r.rate() / max_rate * 30.0).round() as usize } else { 0 };
+            let _ = writeln!(
+                out,
+                "  {:>9.1}  {:>8}  {:>6.1}  {}",
+                r.gain_db,
+                r.messages,
+                r.rate(),
+                "#".repeat(bar)
+            );
+        }
+        if let Some(best) = self.best() {
+            let _ = writeln!(out, "Best: {:.1} dB ({:.1} msg/s)", best.gain_db, best.rate());
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sweep_steps() {
+        let mut sweep = GainSweep::new(vec![10.0, 20.0, 30.0], 1, 100);
+        assert_eq!(sweep.gain_db(), 10.0);
+
+        assert_eq!(sweep.observe(50, 5), SweepStep::Continue);
+        assert_eq!(sweep.observe(50, 10), SweepStep::Next(20.0));
+        assert_eq!(sweep.observe(100, 40), SweepStep::Next(30.0));
+        assert_eq!(sweep.observe(100, 45), SweepStep::Done);
+
+        let rates: Vec<f64> = sweep.results().iter().map(SweepResult::rate).collect();
+        assert_eq!(rates, vec![10.0, 30.0, 5.0]);
+        assert_eq!(sweep.best().unwrap().gain_db, 20.0);
+        assert!(sweep.report().contains("Best: 20.0 dB"));
+    }
+
+    #[test]
+    fn test_sweep_for_config() {
+        let config = Config { device_type: DeviceType::HackRf, ..Config::default() };
+        let sweep = GainSweep::for_config(&config, 5);
+        assert_eq!(sweep.steps.first(), Some(&0.0));
+        assert_eq!(sweep.steps.last(), Some(&102.0));
+    }
+}
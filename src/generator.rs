@@ -0,0 +1,499 @@
+//! Synthetic Mode S signal generator
+//!
+//! Modulates Mode S frames into pulse-position-modulated I/Q samples, and
+//! Mode A/C replies into their framing and code pulses, so the demodulator
+//! can be exercised without an antenna. Supports noise at
+//! a given SNR, carrier frequency offset, arbitrary sample rates,
+//! overlapping frames and a multipath echo.
+
+use std::f64::consts::PI;
+use std::fs;
+use std::path::Path;
+
+use crate::crc;
+use crate::decoder::{
+    MODES_LONG_MSG_BYTES, MODES_SHORT_MSG_BYTES, ModesMessage, decode_modes_message, message_len_by_type,
+};
+use crate::sample_format::SampleFormat;
+use crate::sigmf;
+
+/// Preamble pulse start times (µs); each pulse is 0.5 µs wide
+const PREAMBLE_PULSES_US: [f64; 4] = [0.0, 1.0, 3.5, 4.5];
+/// Data bits start this long after the preamble (µs)
+const DATA_OFFSET_US: f64 = 8.0;
+/// Width of a PPM pulse (µs)
+const PULSE_US: f64 = 0.5;
+/// Mode A/C code pulses are spaced this far apart (µs)
+const MODEAC_SPACING_US: f64 = 1.45;
+/// Mode A/C F2 framing pulse follows F1 by this much (µs)
+const MODEAC_F2_US: f64 = 20.3;
+/// Width of a Mode A/C pulse (µs)
+const MODEAC_PULSE_US: f64 = 0.45;
+/// Silence before the first and after the last frame (µs)
+const LEAD_US: f64 = 100.0;
+
+/// Frames used by the CLI when none are given
+pub const DEFAULT_FRAMES: [&str; 3] = [
+    "8D4840D6202CC371C32CE0576098", // DF17 identification
+    "8D40621D58C382D690C8AC2863A7", // DF17 airborne position
+    "5D4840D6",                     // DF11 all-call reply (CRC appended)
+];
+
+/// A delayed, attenuated copy of every frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Multipath {
+    /// Echo delay (µs)
+    pub delay_us: f64,
+    /// Echo level relative to the direct signal (dB)
+    pub level_db: f64,
+}
+
+/// Signal generator settings
+#[derive(Debug, Clone)]
+pub struct GeneratorConfig {
+    /// Output sample rate (Hz)
+    pub sample_rate: u32,
+    /// Pulse peak to noise power ratio for a 0 dB frame; `None` for no noise
+    pub snr_db: Option<f64>,
+    /// Carrier offset from the tuned frequency (Hz)
+    pub freq_offset_hz: f64,
+    /// Pulse amplitude of a 0 dB frame (full scale = 1.0)
+    pub amplitude: f64,
+    /// Optional multipath echo
+    pub multipath: Option<Multipath>,
+    /// Seed for noise and carrier phase
+    pub seed: u64,
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        Self {
+            sample_rate: 2_000_000,
+            snr_db: Some(20.0),
+            freq_offset_hz: 0.0,
+            amplitude: 0.5,
+            multipath: None,
+            seed: 1,
+        }
+    }
+}
+
+/// One frame placed in the generated signal
+#[derive(Debug, Clone)]
+pub struct Emission {
+    /// Frame bytes (7 or 14)
+    pub frame: Vec<u8>,
+    /// Mode A/C reply code (one octal digit per nibble, ABCD) sent
+    /// instead of `frame`
+    pub mode_ac: Option<u16>,
+    /// Start of the preamble, or of F1 for Mode A/C (µs from the start of
+    /// the output)
+    pub start_us: f64,
+    /// Level relative to the configured amplitude (dB)
+    pub level_db: f64,
+}
+
+impl Emission {
+    /// Duration of the frame including preamble (µs)
+    pub fn duration_us(&self) -> f64 {
+        match self.mode_ac {
+            Some(_) => MODEAC_F2_US + MODEAC_PULSE_US,
+            None => DATA_OFFSET_US + self.frame.len() as f64 * 8.0,
+        }
+    }
+
+    /// Pulse start times (µs from `start_us`) and the pulse width
+    fn pulses(&self) -> (Vec<f64>, f64) {
+        match self.mode_ac {
+            Some(code) => (mode_ac_pulse_times(code), MODEAC_PULSE_US),
+            None => (pulse_times(&self.frame), PULSE_US),
+        }
+    }
+}
+
+/// Parse a hex frame, with or without `*...;` framing, into the message
+/// to transmit.
+///
+/// A frame given without its parity field (8 or 22 hex digits) gets one
+/// appended: the plain CRC for DF11/17/18, or the CRC overlaid with `icao`
+/// for the address/parity formats DF0/4/5/16/20/21.
+pub fn message_from_hex(hex: &str, icao: Option<u32>) -> Result<ModesMessage, String> {
+    let hex = hex.trim().trim_start_matches('*').trim_end_matches(';');
+    if hex.is_empty() || !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return Err(format!("invalid hex frame: {}", hex));
+    }
+    let mut frame = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|_| format!("invalid hex frame: {}", hex))?;
+
+    let df = frame[0] >> 3;
+    if frame.len() == MODES_SHORT_MSG_BYTES - 3 || frame.len() == MODES_LONG_MSG_BYTES - 3 {
+        frame.extend_from_slice(&[0, 0, 0]);
+        let bits = frame.len() * 8;
+        let parity = match (df, icao) {
+            (0 | 4 | 5 | 16 | 20 | 21, Some(icao)) => crc::address_parity(&frame, bits, icao),
+            (0 | 4 | 5 | 16 | 20 | 21, None) => {
+                return Err(format!("DF{} frame without parity needs an ICAO address", df));
+            }
+            (_, None) => crc::parity(&frame, bits),
+            (_, Some(_)) => return Err(format!("DF{} frames carry the ICAO address in the clear", df)),
+        };
+        crc::write_parity_field(&mut frame, bits, parity);
+    } else if icao.is_some() {
+        return Err("an ICAO address needs a frame without its parity field".to_string());
+    }
+
+    let expected = message_len_by_type(df) / 8;
+    if frame.len() != expected {
+        return Err(format!(
+            "DF{} frame must be {} bytes, got {}",
+            df,
+            expected,
+            frame.len()
+        ));
+    }
+    Ok(decode_modes_message(&frame, false, false, false))
+}
+
+/// Frame bytes of a decoded message
+pub fn frame_from_message(mm: &ModesMessage) -> Vec<u8> {
+    mm.msg[..mm.msg_bits / 8].to_vec()
+}
+
+/// Pulse start times (µs from the start of the preamble) for a frame
+fn pulse_times(frame: &[u8]) -> Vec<f64> {
+    let mut pulses = PREAMBLE_PULSES_US.to_vec();
+    for bit in 0..frame.len() * 8 {
+        let one = frame[bit / 8] & (0x80 >> (bit % 8)) != 0;
+        let t = DATA_OFFSET_US + bit as f64;
+        pulses.push(if one { t } else { t + PULSE_US });
+    }
+    pulses
+}
+
+/// Pulse start times (µs from F1) for a Mode A/C reply
+fn mode_ac_pulse_times(code: u16) -> Vec<f64> {
+    let digit = |shift: u16, bit: u16| code >> shift & bit != 0;
+    let (a, b, c, d) = (12, 8, 4, 0);
+    // Pulse order: C1 A1 C2 A2 C4 A4 X B1 D1 B2 D2 B4 D4
+    let bits = [
+        digit(c, 1), digit(a, 1), digit(c, 2), digit(a, 2), digit(c, 4), digit(a, 4), false,
+        digit(b, 1), digit(d, 1), digit(b, 2), digit(d, 2), digit(b, 4), digit(d, 4),
+    ];
+
+    let mut pulses = vec![0.0];
+    for (k, _) in bits.iter().enumerate().filter(|(_, on)| **on) {
+        pulses.push((k + 1) as f64 * MODEAC_SPACING_US);
+    }
+    pulses.push(MODEAC_F2_US);
+    pulses
+}
+
+/// Render emissions into `duration_us` of normalised I/Q samples
+pub fn generate(config: &GeneratorConfig, emissions: &[Emission], duration_us: f64) -> Vec<(f32, f32)> {
+    let us_per_sample = 1e6 / config.sample_rate as f64;
+    let len = (duration_us / us_per_sample).ceil() as usize;
+    let mut re = vec![0.0f64; len];
+    let mut im = vec![0.0f64; len];
+    let mut rng = XorShift::new(config.seed);
+
+    for emission in emissions {
+        let mut copies = vec![(0.0, emission.level_db)];
+        if let Some(mp) = config.multipath {
+            copies.push((mp.delay_us, emission.level_db + mp.level_db));
+        }
+
+        for (delay, level_db) in copies {
+            let amplitude = config.amplitude * 10f64.powf(level_db / 20.0);
+            let phase = rng.next_f64() * 2.0 * PI;
+
+            let (pulses, width) = emission.pulses();
+            for t in pulses {
+                let t0 = emission.start_us + delay + t;
+                let t1 = t0 + width;
+                let first = (t0 / us_per_sample).floor().max(0.0) as usize;
+                let last = ((t1 / us_per_sample).ceil() as usize).min(len);
+
+                // Box-filter the pulse onto the sample grid
+                for k in first..last {
+                    let s0 = k as f64 * us_per_sample;
+                    let s1 = s0 + us_per_sample;
+                    let overlap = (t1.min(s1) - t0.max(s0)).max(0.0) / us_per_sample;
+                    if overlap <= 0.0 {
+                        continue;
+                    }
+                    let centre = (s0 + s1) / 2.0 * 1e-6;
+                    let theta = 2.0 * PI * config.freq_offset_hz * centre + phase;
+                    re[k] += amplitude * overlap * theta.cos();
+                    im[k] += amplitude * overlap * theta.sin();
+                }
+            }
+        }
+    }
+
+    if let Some(snr_db) = config.snr_db {
+        let sigma = config.amplitude / (2.0 * 10f64.powf(snr_db / 10.0)).sqrt();
+        for k in 0..len {
+            let (n_re, n_im) = rng.next_gaussian_pair();
+            re[k] += n_re * sigma;
+            im[k] += n_im * sigma;
+        }
+    }
+
+    re.into_iter()
+        .zip(im)
+        .map(|(i, q)| (i as f32, q as f32))
+        .collect()
+}
+
+/// Place messages one after another, `interval_us` apart, repeated
+/// `repeat` times. Intervals shorter than a frame produce overlapping frames.
+pub fn schedule(messages: &[(ModesMessage, f64)], repeat: usize, interval_us: f64) -> (Vec<Emission>, f64) {
+    let mut emissions = Vec::new();
+    let mut start = LEAD_US;
+    let mut end: f64 = 0.0;
+
+    for _ in 0..repeat {
+        for (mm, level_db) in messages {
+            let emission =
+                Emission { frame: frame_from_message(mm), mode_ac: None, start_us: start, level_db: *level_db };
+            end = end.max(start + emission.duration_us());
+            emissions.push(emission);
+            start += interval_us;
+        }
+    }
+    (emissions, end + LEAD_US)
+}
+
+/// Small deterministic PRNG for noise and phase (xorshift64*)
+struct XorShift(u64);
+
+impl XorShift {
+    fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545f4914f6cdd1d)
+    }
+
+    /// Uniform in [0, 1)
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Two independent standard normal values (Box-Muller)
+    fn next_gaussian_pair(&mut self) -> (f64, f64) {
+        let u1 = self.next_f64().max(f64::MIN_POSITIVE);
+        let u2 = self.next_f64();
+        let r = (-2.0 * u1.ln()).sqrt();
+        (r * (2.0 * PI * u2).cos(), r * (2.0 * PI * u2).sin())
+    }
+}
+
+/// Entry point for `adsb-rx generate ...`
+pub fn run_cli(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = GeneratorConfig::default();
+    let mut format = SampleFormat::Cu8;
+    let mut out: Option<String> = None;
+    let mut messages: Vec<(ModesMessage, f64)> = Vec::new();
+    let mut repeat = 1;
+    let mut interval_us = 1000.0;
+    let mut write_sigmf = false;
+
+    let mut i = 0;
+    while i < args.len() {
+        let value = |i: usize| args.get(i).cloned().ok_or_else(|| format!("{} needs a value", args[i - 1]));
+        match args[i].as_str() {
+            "--out" => {
+                i += 1;
+                out = Some(value(i)?);
+            }
+            "--message" => {
+                i += 1;
+                let spec = value(i)?;
+                let (hex, options) = spec.split_once('@').unwrap_or((&spec, ""));
+                let (level, icao) = options.split_once(',').unwrap_or((options, ""));
+                let level = if level.is_empty() { 0.0 } else { level.parse::<f64>()? };
+                let icao = if icao.is_empty() { None } else { Some(u32::from_str_radix(icao, 16)?) };
+                messages.push((message_from_hex(hex, icao)?, level));
+            }
+            "--repeat" => {
+                i += 1;
+                repeat = value(i)?.parse()?;
+            }
+            "--interval" => {
+                i += 1;
+                interval_us = value(i)?.parse()?;
+            }
+            "--snr" => {
+                i += 1;
+                config.snr_db = Some(value(i)?.parse()?);
+            }
+            "--no-noise" => config.snr_db = None,
+            "--amplitude" => {
+                i += 1;
+                config.amplitude = value(i)?.parse()?;
+            }
+            "--freq-offset" => {
+                i += 1;
+                config.freq_offset_hz = value(i)?.parse()?;
+            }
+            "--sample-rate" => {
+                i += 1;
+                config.sample_rate = value(i)?.parse()?;
+            }
+            "--format" => {
+                i += 1;
+                let name = value(i)?;
+                format = SampleFormat::from_name(&name).ok_or_else(|| format!("unknown sample format: {}", name))?;
+            }
+            "--multipath" => {
+                i += 1;
+                let spec = value(i)?;
+                let (delay, level) = spec.split_once(',').ok_or("--multipath takes <delay_us>,<level_db>")?;
+                config.multipath = Some(Multipath { delay_us: delay.parse()?, level_db: level.parse()? });
+            }
+            "--seed" => {
+                i += 1;
+                config.seed = value(i)?.parse()?;
+            }
+            "--sigmf" => write_sigmf = true,
+            "--help" => {
+                print_help();
+                return Ok(());
+            }
+            other => {
+                print_help();
+                return Err(format!("unknown option: {}", other).into());
+            }
+        }
+        i += 1;
+    }
+
+    let out = out.ok_or("--out <file> is required")?;
+    if messages.is_empty() {
+        messages = DEFAULT_FRAMES.iter().map(|h| (message_from_hex(h, None).unwrap(), 0.0)).collect();
+    }
+
+    let (emissions, duration_us) = schedule(&messages, repeat, interval_us);
+    let iq = generate(&config, &emissions, duration_us);
+    fs::write(&out, format.encode(&iq))?;
+
+    if write_sigmf {
+        sigmf::write_meta(
+            &Path::new(&out).with_extension("sigmf-meta"),
+            format,
+            config.sample_rate,
+            1_090_000_000,
+            "adsb-rx signal generator",
+            "1970-01-01T00:00:00Z",
+        )?;
+    }
+
+    eprintln!(
+        "Wrote {} frames, {} samples of {} at {} Hz to {}",
+        emissions.len(),
+        iq.len(),
+        format,
+        config.sample_rate,
+        out
+    );
+    Ok(())
+}
+
+fn print_help() {
+    println!(
+        r#"Usage: adsb generate --out <file> [options]
+
+Generate synthetic Mode S I/Q samples for testing.
+
+OPTIONS:
+  --out <file>           Output file
+  --message <hex[@db[,icao]]>
+                         Frame to transmit, optionally with a level in dB; may repeat.
+                         8/22 hex digits get the parity appended, overlaid with the
+                         ICAO address for DF0/4/5/16/20/21 (default: built-in frames)
+  --repeat <N>           Transmit the frame list N times (default: 1)
+  --interval <us>        Start-to-start spacing of frames (default: 1000; <120 overlaps)
+  --snr <db>             Pulse to noise power ratio (default: 20)
+  --no-noise             Generate a noiseless signal
+  --amplitude <a>        Pulse amplitude, full scale 1.0 (default: 0.5)
+  --freq-offset <hz>     Carrier offset from the tuned frequency (default: 0)
+  --sample-rate <hz>     Output sample rate (default: 2000000)
+  --format <fmt>         cu8 (default), cs8, cs16 or cf32
+  --multipath <us>,<db>  Add an echo delayed by <us> at <db> relative level
+  --seed <N>             Noise seed (default: 1)
+  --sigmf                Write a .sigmf-meta next to the output
+
+EXAMPLE:
+  adsb generate --out test.cu8 --repeat 100 --snr 12
+  adsb --ifile test.cu8"#
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::magnitude::MagnitudeLut;
+
+    #[test]
+    fn test_message_from_hex_appends_parity() {
+        let full = message_from_hex("*8D4840D6202CC371C32CE0576098;", None).unwrap();
+        let short = message_from_hex("8D4840D6202CC371C32CE0", None).unwrap();
+        assert_eq!(frame_from_message(&full), frame_from_message(&short));
+        assert!(short.crc_ok);
+
+        assert!(message_from_hex("8D4840D6", None).is_err()); // DF17 needs 14 bytes
+        assert!(message_from_hex("zz", None).is_err());
+        assert!(message_from_hex("8D4840D6202CC371C32CE0", Some(0x4840D6)).is_err());
+    }
+
+    #[test]
+    fn test_message_from_hex_address_parity() {
+        // DF4 altitude reply from 4840D6
+        let mm = message_from_hex("20001838", Some(0x4840D6)).unwrap();
+        assert_eq!(mm.msg_type, 4);
+        assert_eq!(mm.icao_address(), 0x4840D6);
+
+        assert!(message_from_hex("20001838", None).is_err());
+    }
+
+    #[test]
+    fn test_ppm_shape() {
+        let config = GeneratorConfig { snr_db: None, freq_offset_hz: 25_000.0, ..Default::default() };
+        let frame = frame_from_message(&message_from_hex("5D4840D6", None).unwrap());
+        let emission = Emission { frame, mode_ac: None, start_us: 0.0, level_db: 0.0 };
+        let iq = generate(&config, &[emission], 64.0);
+        let mut m = Vec::new();
+        SampleFormat::Cf32.compute_magnitude_into(&SampleFormat::Cf32.encode(&iq), &MagnitudeLut::new(), &mut m);
+
+        // Preamble pulses at samples 0, 2, 7, 9
+        let high = m[0] / 2;
+        let pulses: Vec<usize> = (0..16).filter(|&k| m[k] > high).collect();
+        assert_eq!(pulses, vec![0, 2, 7, 9]);
+
+        // DF11 = 01011: first bit is 0 (second half high), second is 1
+        assert!(m[16] < high && m[17] > high);
+        assert!(m[18] > high && m[19] < high);
+    }
+
+    #[test]
+    fn test_multipath_echo() {
+        let frame = frame_from_message(&message_from_hex("5D4840D6", None).unwrap());
+        let emission = Emission { frame, mode_ac: None, start_us: 0.0, level_db: 0.0 };
+        let mut config = GeneratorConfig { snr_db: None, ..Default::default() };
+        config.multipath = Some(Multipath { delay_us: 100.0, level_db: -6.0 });
+
+        let iq = generate(&config, &[emission], 200.0);
+        let direct = (iq[0].0.hypot(iq[0].1)) as f64;
+        let echo = (iq[200].0.hypot(iq[200].1)) as f64;
+        assert!((direct - 0.5).abs() < 1e-6);
+        assert!((echo / direct - 0.501).abs() < 0.01);
+    }
+}
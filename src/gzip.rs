@@ -0,0 +1,239 @@
+//! gzip compression for HTTP responses
+//!
+//! A single deflate block with the fixed Huffman codes (RFC 1951 3.2.6)
+//! and greedy LZ77 matching over hash chains. JSON is repetitive enough
+//! that this gets most of what a full encoder would, at a fraction of
+//! the code.
+
+/// LZ77 window (the deflate maximum)
+const WINDOW: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+/// Hash chain entries tried per position
+const MAX_CHAIN: usize = 32;
+const HASH_BITS: u32 = 15;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145,
+    8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+/// CRC-32 (IEEE, reflected), as used in the gzip trailer
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in data {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// Writes bits least significant first, as deflate packs them
+struct BitWriter {
+    out: Vec<u8>,
+    acc: u32,
+    nbits: u32,
+}
+
+impl BitWriter {
+    fn new(out: Vec<u8>) -> Self {
+        Self { out, acc: 0, nbits: 0 }
+    }
+
+    fn bits(&mut self, value: u32, n: u32) {
+        self.acc |= value << self.nbits;
+        self.nbits += n;
+        while self.nbits >= 8 {
+            self.out.push(self.acc as u8);
+            self.acc >>= 8;
+            self.nbits -= 8;
+        }
+    }
+
+    /// A Huffman code, which deflate stores most significant bit first
+    fn code(&mut self, code: u32, n: u32) {
+        self.bits(code.reverse_bits() >> (32 - n), n);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.nbits > 0 {
+            self.out.push(self.acc as u8);
+        }
+        self.out
+    }
+}
+
+/// Fixed Huffman code for a literal/length symbol
+fn lit_code(w: &mut BitWriter, sym: u16) {
+    let sym = sym as u32;
+    match sym {
+        0..=143 => w.code(0x30 + sym, 8),
+        144..=255 => w.code(0x190 + sym - 144, 9),
+        256..=279 => w.code(sym - 256, 7),
+        _ => w.code(0xC0 + sym - 280, 8),
+    }
+}
+
+fn write_match(w: &mut BitWriter, len: usize, dist: usize) {
+    let li = LENGTH_BASE.iter().rposition(|&b| b as usize <= len).unwrap_or(0);
+    lit_code(w, 257 + li as u16);
+    w.bits((len - LENGTH_BASE[li] as usize) as u32, LENGTH_EXTRA[li] as u32);
+    let di = DIST_BASE.iter().rposition(|&b| b as usize <= dist).unwrap_or(0);
+    w.code(di as u32, 5);
+    w.bits((dist - DIST_BASE[di] as usize) as u32, DIST_EXTRA[di] as u32);
+}
+
+fn hash(data: &[u8], i: usize) -> usize {
+    let v = (data[i] as u32) << 16 | (data[i + 1] as u32) << 8 | data[i + 2] as u32;
+    (v.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+}
+
+/// Raw deflate stream of `data`, appended to `out`
+fn deflate_into(data: &[u8], out: Vec<u8>) -> Vec<u8> {
+    let mut w = BitWriter::new(out);
+    w.bits(1, 1); // BFINAL
+    w.bits(1, 2); // BTYPE = fixed Huffman
+
+    // Most recent position per hash, and the previous one per position
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; WINDOW];
+    let insert = |i: usize, head: &mut [usize], prev: &mut [usize]| {
+        if i + MIN_MATCH <= data.len() {
+            let h = hash(data, i);
+            prev[i % WINDOW] = head[h];
+            head[h] = i;
+        }
+    };
+
+    let mut i = 0;
+    while i < data.len() {
+        let mut best = (0, 0);
+        if i + MIN_MATCH <= data.len() {
+            let max = (data.len() - i).min(MAX_MATCH);
+            let mut cand = head[hash(data, i)];
+            let mut chain = 0;
+            while cand != usize::MAX && i - cand <= WINDOW && chain < MAX_CHAIN {
+                let len = data[cand..].iter().zip(&data[i..i + max]).take_while(|(a, b)| a == b).count();
+                if len > best.0 {
+                    best = (len, i - cand);
+                    if len == max {
+                        break;
+                    }
+                }
+                let next = prev[cand % WINDOW];
+                if next == usize::MAX || next >= cand {
+                    break;
+                }
+                cand = next;
+                chain += 1;
+            }
+        }
+
+        if best.0 >= MIN_MATCH {
+            write_match(&mut w, best.0, best.1);
+            for j in i..i + best.0 {
+                insert(j, &mut head, &mut prev);
+            }
+            i += best.0;
+        } else {
+            lit_code(&mut w, data[i] as u16);
+            insert(i, &mut head, &mut prev);
+            i += 1;
+        }
+    }
+    lit_code(&mut w, 256);
+    w.finish()
+}
+
+/// gzip member (RFC 1952) holding `data`
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    // Magic, deflate, no flags, no mtime, no extra flags, unknown OS
+    let header = vec![0x1F, 0x8B, 8, 0, 0, 0, 0, 0, 0, 0xFF];
+    let mut out = deflate_into(data, header);
+    out.extend_from_slice(&crc32(data).to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Inflate a single fixed-Huffman block, enough to check our output
+    fn inflate_fixed(data: &[u8]) -> Vec<u8> {
+        let mut pos = 0usize;
+        let mut bit = |n: u32| {
+            let mut v = 0;
+            for k in 0..n {
+                v |= ((data[pos / 8] >> (pos % 8)) as u32 & 1) << k;
+                pos += 1;
+            }
+            v
+        };
+        assert_eq!((bit(1), bit(2)), (1, 1));
+        let mut out = Vec::new();
+        loop {
+            // Read the code MSB first, widening until it falls in a range
+            let mut code = 0;
+            let mut sym = None;
+            for len in 1..=9 {
+                code = code << 1 | bit(1);
+                sym = match (len, code) {
+                    (7, 0..=0x17) => Some(256 + code),
+                    (8, 0x30..=0xBF) => Some(code - 0x30),
+                    (8, 0xC0..=0xC7) => Some(280 + code - 0xC0),
+                    (9, 0x190..=0x1FF) => Some(144 + code - 0x190),
+                    _ => None,
+                };
+                if sym.is_some() {
+                    break;
+                }
+            }
+            let sym = sym.unwrap() as usize;
+            match sym {
+                0..=255 => out.push(sym as u8),
+                256 => return out,
+                _ => {
+                    let li = sym - 257;
+                    let len = LENGTH_BASE[li] as usize + bit(LENGTH_EXTRA[li] as u32) as usize;
+                    let di = (0..5).fold(0, |acc, _| acc << 1 | bit(1)) as usize;
+                    let dist = DIST_BASE[di] as usize + bit(DIST_EXTRA[di] as u32) as usize;
+                    for _ in 0..len {
+                        out.push(out[out.len() - dist]);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut json = String::from("{\"aircraft\":[");
+        for i in 0..200 {
+            json.push_str(&format!("{{\"hex\":\"{:06x}\",\"alt_baro\":{},\"rssi\":-12.5}},", 0x4840d6 + i, 1000 * i));
+        }
+        json.push_str("]}");
+        for data in [json.as_bytes(), b"", b"a", &[0xFFu8; 1000], b"abcabcabcabcx"] {
+            let gz = compress(data);
+            assert_eq!(gz[..3], [0x1F, 0x8B, 8]);
+            let n = gz.len();
+            assert_eq!(gz[n - 8..n - 4], crc32(data).to_le_bytes());
+            assert_eq!(gz[n - 4..], (data.len() as u32).to_le_bytes());
+            assert_eq!(inflate_fixed(&gz[10..n - 8]), data);
+        }
+        assert!(compress(json.as_bytes()).len() < json.len() / 3);
+    }
+}
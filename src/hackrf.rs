@@ -0,0 +1,157 @@
+//! HackRF One front-end settings
+//!
+//! Maps the gain options in `Config` onto the HackRF's RF amplifier, LNA
+//! (IF) and VGA (baseband) stages and baseband filter, and validates them
+//! against what `hackrf_transfer` accepts.
+
+use crate::agc::{
+    HACKRF_LNA_MAX_DB, HACKRF_LNA_STEP_DB, HACKRF_VGA_MAX_DB, HACKRF_VGA_STEP_DB, hackrf_gain_split,
+};
+
+/// Baseband filter bandwidths supported by the MAX2837 (Hz)
+pub const HACKRF_BB_FILTERS_HZ: [u32; 16] = [
+    1_750_000, 2_500_000, 3_500_000, 5_000_000, 5_500_000, 6_000_000, 7_000_000, 8_000_000,
+    9_000_000, 10_000_000, 12_000_000, 14_000_000, 15_000_000, 20_000_000, 24_000_000, 28_000_000,
+];
+
+/// Gain, amplifier and filter settings for `hackrf_transfer`
+#[derive(Debug, Clone, PartialEq)]
+pub struct HackRfSettings {
+    /// LNA (IF) gain, 0-40 dB in 8 dB steps
+    pub lna_db: u32,
+    /// VGA (baseband) gain, 0-62 dB in 2 dB steps
+    pub vga_db: u32,
+    /// RF amplifier (~11 dB) on
+    pub amp: bool,
+    /// Baseband filter bandwidth; `None` lets hackrf_transfer pick one
+    pub bb_filter_hz: Option<u32>,
+}
+
+impl Default for HackRfSettings {
+    fn default() -> Self {
+        Self {
+            lna_db: 32,
+            vga_db: 20,
+            amp: true,
+            bb_filter_hz: None,
+        }
+    }
+}
+
+impl HackRfSettings {
+    /// Resolve settings from the command line options.
+    ///
+    /// Explicit `lna`/`vga` values win; otherwise a `gain` in tenths of a dB
+    /// (as in `Config::gain`, 999999 meaning "not set") is split across
+    /// LNA and VGA, filling the LNA first.
+    pub fn resolve(
+        lna: Option<u32>,
+        vga: Option<u32>,
+        amp: bool,
+        bb_filter_hz: Option<u32>,
+        gain: i32,
+    ) -> Result<Self, String> {
+        let mut settings = Self { amp, bb_filter_hz, ..Self::default() };
+
+        if (0..999999).contains(&gain) {
+            let total = gain as f64 / 10.0;
+            let max = (HACKRF_LNA_MAX_DB + HACKRF_VGA_MAX_DB) as f64;
+            if total > max {
+                return Err(format!("HackRF gain must be 0-{} dB, got {}", max, total));
+            }
+            (settings.lna_db, settings.vga_db) = hackrf_gain_split(total);
+        }
+
+        if let Some(lna) = lna {
+            if lna > HACKRF_LNA_MAX_DB || !lna.is_multiple_of(HACKRF_LNA_STEP_DB) {
+                return Err(format!(
+                    "HackRF LNA gain must be 0-{} dB in {} dB steps, got {}",
+                    HACKRF_LNA_MAX_DB, HACKRF_LNA_STEP_DB, lna
+                ));
+            }
+            settings.lna_db = lna;
+        }
+        if let Some(vga) = vga {
+            if vga > HACKRF_VGA_MAX_DB || !vga.is_multiple_of(HACKRF_VGA_STEP_DB) {
+                return Err(format!(
+                    "HackRF VGA gain must be 0-{} dB in {} dB steps, got {}",
+                    HACKRF_VGA_MAX_DB, HACKRF_VGA_STEP_DB, vga
+                ));
+            }
+            settings.vga_db = vga;
+        }
+        if let Some(bw) = bb_filter_hz
+            && !HACKRF_BB_FILTERS_HZ.contains(&bw)
+        {
+            let supported: Vec<String> =
+                HACKRF_BB_FILTERS_HZ.iter().map(|f| format!("{}", *f as f64 / 1e6)).collect();
+            return Err(format!(
+                "HackRF baseband filter must be one of {} MHz, got {} Hz",
+                supported.join("/"),
+                bw
+            ));
+        }
+
+        Ok(settings)
+    }
+
+    /// Combined LNA + VGA gain in dB (excluding the RF amp)
+    pub fn total_gain_db(&self) -> f64 {
+        (self.lna_db + self.vga_db) as f64
+    }
+
+    /// The same settings with a different total LNA + VGA gain
+    pub fn with_total_gain(&self, total_db: f64) -> Self {
+        let (lna_db, vga_db) = hackrf_gain_split(total_db);
+        Self { lna_db, vga_db, ..self.clone() }
+    }
+
+    /// `hackrf_transfer` arguments for these settings
+    pub fn args(&self) -> Vec<String> {
+        // -a : amp enable (0 or 1)
+        // -l : LNA gain (0-40 dB)
+        // -g : VGA gain (0-62 dB)
+        // -b : baseband filter bandwidth (Hz)
+        let mut args = vec![
+            "-a".to_string(),
+            (self.amp as u8).to_string(),
+            "-l".to_string(),
+            self.lna_db.to_string(),
+            "-g".to_string(),
+            self.vga_db.to_string(),
+        ];
+        if let Some(bw) = self.bb_filter_hz {
+            args.push("-b".to_string());
+            args.push(bw.to_string());
+        }
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_gain() {
+        // Defaults match the historical -a 1 -l 32 -g 20
+        let s = HackRfSettings::resolve(None, None, true, None, 999999).unwrap();
+        assert_eq!(s.args(), vec!["-a", "1", "-l", "32", "-g", "20"]);
+
+        // --gain is split LNA first; explicit stages override it
+        let s = HackRfSettings::resolve(None, None, false, Some(1_750_000), 300).unwrap();
+        assert_eq!((s.lna_db, s.vga_db), (24, 6));
+        assert_eq!(s.args(), vec!["-a", "0", "-l", "24", "-g", "6", "-b", "1750000"]);
+        let s = HackRfSettings::resolve(Some(16), None, true, None, 300).unwrap();
+        assert_eq!((s.lna_db, s.vga_db), (16, 6));
+    }
+
+    #[test]
+    fn test_resolve_validation() {
+        assert!(HackRfSettings::resolve(Some(12), None, true, None, 999999).is_err());
+        assert!(HackRfSettings::resolve(Some(48), None, true, None, 999999).is_err());
+        assert!(HackRfSettings::resolve(None, Some(63), true, None, 999999).is_err());
+        assert!(HackRfSettings::resolve(None, None, true, Some(2_000_000), 999999).is_err());
+        assert!(HackRfSettings::resolve(None, None, true, None, 1100).is_err());
+    }
+}
@@ -0,0 +1,521 @@
+//! HTTP/1.1 server for the web interface and JSON APIs
+//!
+//! Routes, for GET and HEAD:
+//!
+//! - `/data.json`: the original dump1090 aircraft list, used by `gmap.html`
+//! - `/data/aircraft.json`, `/data/receiver.json`, `/data/stats.json`:
+//!   readsb-compatible documents
+//! - `/ws`: WebSocket push stream (see `websocket`)
+//! - `/metrics`: Prometheus metrics (see `metrics`)
+//! - anything else from `--net-http-dir` when given, falling back to the
+//!   built-in `gmap.html` for `/` and `/gmap.html`
+//!
+//! Connections are kept alive (HTTP/1.1 default, or `Connection:
+//! keep-alive` from 1.0 clients) and requests may be pipelined. JSON is
+//! gzipped for clients that accept it; static files get an ETag so
+//! browsers can revalidate cheaply.
+
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::RwLock;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, info};
+
+use crate::aircraft::AircraftStore;
+use crate::gzip;
+use crate::metrics::{self, MetricsSources};
+use crate::network::PortCounters;
+use crate::readsb::{AircraftFile, ReceiverJson};
+use crate::stats::{self, SharedReceiverStats};
+use crate::websocket::{self, Filter, Snapshot, SnapshotReceiver};
+
+/// The map page, built into the binary
+const GMAP_HTML: &str = include_str!("../gmap.html");
+
+/// Longest request line plus headers
+const MAX_HEAD_LEN: usize = 16 * 1024;
+/// Largest request body we read past (we use none)
+const MAX_BODY_LEN: usize = 64 * 1024;
+/// Idle keep-alive connections are closed after this
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+/// Smaller bodies are not worth compressing
+const GZIP_MIN_LEN: usize = 256;
+
+/// Caching for data that changes all the time
+const NO_CACHE: &str = "no-cache";
+/// Caching for static assets other than pages
+const STATIC_CACHE: &str = "public, max-age=3600";
+
+/// What request handlers need
+pub struct HttpContext {
+    pub store: Arc<RwLock<AircraftStore>>,
+    pub stats: SharedReceiverStats,
+    pub receiver: ReceiverJson,
+    /// Static web root, searched before the built-in page
+    pub web_root: Option<PathBuf>,
+    /// Log each request at info rather than debug level (`--debug n`)
+    pub log_requests: bool,
+    /// Clients and traffic of the HTTP port
+    pub net: PortCounters,
+    pub metrics: MetricsSources,
+}
+
+/// A parsed request head
+#[derive(Debug)]
+struct Request {
+    method: String,
+    path: String,
+    query: String,
+    /// 0 for HTTP/1.0, 1 for HTTP/1.1
+    minor_version: u8,
+    headers: Vec<(String, String)>,
+}
+
+impl Request {
+    /// Parse the request line and headers; `None` if malformed
+    fn parse(head: &str) -> Option<Self> {
+        let mut lines = head.split("\r\n");
+        let mut parts = lines.next()?.split(' ');
+        let (method, target, version) = (parts.next()?, parts.next()?, parts.next()?);
+        if parts.next().is_some() || !target.starts_with('/') {
+            return None;
+        }
+        let minor_version = match version {
+            "HTTP/1.0" => 0,
+            "HTTP/1.1" => 1,
+            _ => return None,
+        };
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let mut headers = Vec::new();
+        for line in lines.take_while(|l| !l.is_empty()) {
+            let (name, value) = line.split_once(':')?;
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+        Some(Self {
+            method: method.to_string(),
+            path: path.to_string(),
+            query: query.to_string(),
+            minor_version,
+            headers,
+        })
+    }
+
+    /// Header value by lowercase name
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    /// Whether the header holds `token` in its comma-separated list
+    fn header_has(&self, name: &str, token: &str) -> bool {
+        self.header(name)
+            .is_some_and(|v| v.split(',').any(|t| t.split(';').next().unwrap_or("").trim().eq_ignore_ascii_case(token)))
+    }
+
+    fn keep_alive(&self) -> bool {
+        if self.header_has("connection", "close") {
+            false
+        } else {
+            self.minor_version == 1 || self.header_has("connection", "keep-alive")
+        }
+    }
+
+    fn content_length(&self) -> Option<usize> {
+        self.header("content-length").map_or(Some(0), |v| v.parse().ok())
+    }
+}
+
+/// A response before encoding
+#[derive(Debug)]
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    cache_control: &'static str,
+    body: Vec<u8>,
+    /// Compress for clients that accept gzip
+    compressible: bool,
+    etag: Option<String>,
+    /// For 405: the methods we do accept
+    allow: Option<&'static str>,
+}
+
+impl Response {
+    fn new(status: u16, content_type: &'static str, body: Vec<u8>) -> Self {
+        Self {
+            status,
+            content_type,
+            cache_control: NO_CACHE,
+            body,
+            compressible: false,
+            etag: None,
+            allow: None,
+        }
+    }
+
+    fn json(body: String) -> Self {
+        Self { compressible: true, ..Self::new(200, "application/json;charset=utf-8", body.into_bytes()) }
+    }
+
+    /// A file's content, revalidated by ETag; pages are always revalidated
+    fn file(content_type: &'static str, body: Vec<u8>) -> Self {
+        let etag = format!("\"{:08x}-{:x}\"", gzip::crc32(&body), body.len());
+        let cache_control = if content_type.starts_with("text/html") { NO_CACHE } else { STATIC_CACHE };
+        Self { cache_control, etag: Some(etag), ..Self::new(200, content_type, body) }
+    }
+
+    fn error(status: u16) -> Self {
+        Self::new(status, "text/plain;charset=utf-8", format!("{} {}\n", status, reason(status)).into_bytes())
+    }
+
+    /// Status line, headers and (unless `head_only`) body
+    fn encode(mut self, head_only: bool, gzip: bool, keep_alive: bool) -> Vec<u8> {
+        let gzip = gzip && self.compressible && self.body.len() >= GZIP_MIN_LEN;
+        if gzip {
+            self.body = gzip::compress(&self.body);
+        }
+        let mut head = format!(
+            "HTTP/1.1 {} {}\r\n\
+             Server: adsb-rx/{}\r\n\
+             Content-Type: {}\r\n\
+             Content-Length: {}\r\n\
+             Cache-Control: {}\r\n\
+             Connection: {}\r\n\
+             Access-Control-Allow-Origin: *\r\n",
+            self.status,
+            reason(self.status),
+            env!("CARGO_PKG_VERSION"),
+            self.content_type,
+            self.body.len(),
+            self.cache_control,
+            if keep_alive { "keep-alive" } else { "close" },
+        );
+        if self.compressible {
+            head.push_str("Vary: Accept-Encoding\r\n");
+        }
+        if gzip {
+            head.push_str("Content-Encoding: gzip\r\n");
+        }
+        if let Some(etag) = &self.etag {
+            head.push_str(&format!("ETag: {}\r\n", etag));
+        }
+        if let Some(allow) = self.allow {
+            head.push_str(&format!("Allow: {}\r\n", allow));
+        }
+        head.push_str("\r\n");
+
+        let mut out = head.into_bytes();
+        if !head_only {
+            out.extend_from_slice(&self.body);
+        }
+        out
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        _ => "Internal Server Error",
+    }
+}
+
+/// Content type by file extension
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase()).as_deref() {
+        Some("html" | "htm") => "text/html;charset=utf-8",
+        Some("js" | "mjs") => "text/javascript;charset=utf-8",
+        Some("css") => "text/css;charset=utf-8",
+        Some("json" | "map") => "application/json;charset=utf-8",
+        Some("txt") => "text/plain;charset=utf-8",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("ico") => "image/x-icon",
+        Some("webp") => "image/webp",
+        Some("woff2") => "font/woff2",
+        Some("woff") => "font/woff",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Map a URL path into `root`, refusing anything that could escape it
+fn resolve_static(root: &Path, url_path: &str) -> Option<PathBuf> {
+    let rel = Path::new(url_path.trim_start_matches('/'));
+    if url_path.contains('\\') || !rel.components().all(|c| matches!(c, Component::Normal(_))) {
+        return None;
+    }
+    let mut path = root.join(rel);
+    if path.is_dir() {
+        path.push("index.html");
+    }
+    Some(path)
+}
+
+/// The original dump1090 `/data.json`: aircraft with a position
+fn aircrafts_to_json(store: &AircraftStore) -> String {
+    let aircraft: Vec<_> = store.all().collect();
+
+    if aircraft.is_empty() {
+        return "[\n]\n".to_string();
+    }
+
+    let mut json = String::from("[\n");
+
+    let mut first = true;
+    for ac in aircraft {
+        // Only include aircraft with position (matching original behavior)
+        if ac.lat != 0.0 && ac.lon != 0.0 {
+            if !first {
+                json.push_str(",\n");
+            }
+            first = false;
+
+            json.push_str(&format!(
+                "{{\"hex\":\"{}\", \"flight\":\"{}\", \"lat\":{}, \"lon\":{}, \"altitude\":{}, \"track\":{}, \"speed\": {}}}",
+                ac.hex_addr,
+                ac.flight.trim(),
+                ac.lat,
+                ac.lon,
+                ac.altitude,
+                ac.track,
+                ac.speed
+            ));
+        }
+    }
+
+    json.push_str("\n]\n");
+    json
+}
+
+/// The response for a GET (or HEAD) of `path`
+async fn route(path: &str, ctx: &HttpContext) -> Response {
+    match path {
+        "/data.json" => return Response::json(aircrafts_to_json(&ctx.store.read())),
+        "/data/aircraft.json" => {
+            let file = AircraftFile::from_store(&ctx.store.read(), stats::unix_now());
+            return serde_json::to_string(&file).map_or_else(|_| Response::error(500), Response::json);
+        }
+        "/data/receiver.json" => {
+            return serde_json::to_string(&ctx.receiver).map_or_else(|_| Response::error(500), Response::json);
+        }
+        "/data/stats.json" => {
+            let json = ctx.stats.write().snapshot_at(stats::unix_now());
+            return serde_json::to_string(&json).map_or_else(|_| Response::error(500), Response::json);
+        }
+        "/metrics" => {
+            let text = metrics::render(&ctx.metrics, &ctx.store.read(), &ctx.stats.read());
+            return Response { compressible: true, ..Response::new(200, metrics::CONTENT_TYPE, text.into_bytes()) };
+        }
+        _ => {}
+    }
+
+    if let Some(root) = &ctx.web_root {
+        let Some(file) = resolve_static(root, path) else {
+            return Response::error(404);
+        };
+        if let Ok(body) = tokio::fs::read(&file).await {
+            return Response::file(content_type(&file), body);
+        }
+    }
+
+    match path {
+        "/" | "/gmap.html" => Response::file("text/html;charset=utf-8", GMAP_HTML.as_bytes().to_vec()),
+        _ => Response::error(404),
+    }
+}
+
+/// Log one request, at info level with `--debug n`
+fn log_request(ctx: &HttpContext, addr: &str, req: &Request, status: u16, len: usize, started: Instant) {
+    let ms = started.elapsed().as_secs_f64() * 1000.0;
+    if ctx.log_requests {
+        info!("HTTP {} \"{} {}\" {} {} {:.1}ms", addr, req.method, req.path, status, len, ms);
+    } else {
+        debug!("HTTP {} \"{} {}\" {} {} {:.1}ms", addr, req.method, req.path, status, len, ms);
+    }
+}
+
+/// Serve requests on one connection until the client closes it, goes idle
+/// or asks to close
+async fn handle_connection(
+    mut socket: TcpStream,
+    addr: String,
+    ctx: Arc<HttpContext>,
+    snapshots: SnapshotReceiver,
+) -> std::io::Result<()> {
+    let mut buf: Vec<u8> = Vec::with_capacity(4096);
+    let mut chunk = vec![0u8; 4096];
+    loop {
+        // Read a request head; what follows it stays in `buf`
+        let head_end = loop {
+            if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                break pos + 4;
+            }
+            if buf.len() > MAX_HEAD_LEN {
+                socket.write_all(&Response::error(431).encode(false, false, false)).await?;
+                return Ok(());
+            }
+            let n = match tokio::time::timeout(IDLE_TIMEOUT, socket.read(&mut chunk)).await {
+                Ok(n) => n?,
+                Err(_) => return Ok(()),
+            };
+            if n == 0 {
+                return Ok(());
+            }
+            ctx.net.received(n);
+            buf.extend_from_slice(&chunk[..n]);
+        };
+        let started = Instant::now();
+        let head = String::from_utf8_lossy(&buf[..head_end]).into_owned();
+        buf.drain(..head_end);
+
+        let Some(req) = Request::parse(&head) else {
+            socket.write_all(&Response::error(400).encode(false, false, false)).await?;
+            return Ok(());
+        };
+
+        // Skip any body; without a length we can't find the next request
+        let body_len = match req.content_length() {
+            Some(len) if req.header("transfer-encoding").is_none() => len,
+            _ => {
+                socket.write_all(&Response::error(400).encode(false, false, false)).await?;
+                return Ok(());
+            }
+        };
+        if body_len > MAX_BODY_LEN {
+            socket.write_all(&Response::error(413).encode(false, false, false)).await?;
+            return Ok(());
+        }
+        while buf.len() < body_len {
+            let n = socket.read(&mut chunk).await?;
+            if n == 0 {
+                return Ok(());
+            }
+            ctx.net.received(n);
+            buf.extend_from_slice(&chunk[..n]);
+        }
+        buf.drain(..body_len);
+
+        // WebSocket upgrade: the connection is the push stream's from here
+        if req.path == websocket::WS_PATH
+            && req.method == "GET"
+            && req.header_has("upgrade", "websocket")
+            && let Some(key) = req.header("sec-websocket-key")
+        {
+            socket.write_all(websocket::handshake_response(key).as_bytes()).await?;
+            log_request(&ctx, &addr, &req, 101, 0, started);
+            let store = Arc::clone(&ctx.store);
+            return websocket::serve(socket, store, snapshots, Filter::from_query(&req.query)).await;
+        }
+
+        let head_only = req.method == "HEAD";
+        let mut response = if head_only || req.method == "GET" {
+            route(&req.path, &ctx).await
+        } else {
+            Response { allow: Some("GET, HEAD"), ..Response::error(405) }
+        };
+        if response.etag.is_some() && req.header("if-none-match") == response.etag.as_deref() {
+            response = Response { status: 304, body: Vec::new(), ..response };
+        }
+
+        let keep_alive = req.keep_alive();
+        let status = response.status;
+        let out = response.encode(head_only || status == 304, req.header_has("accept-encoding", "gzip"), keep_alive);
+        socket.write_all(&out).await?;
+        ctx.net.sent(out.len());
+        log_request(&ctx, &addr, &req, status, out.len(), started);
+
+        if !keep_alive {
+            return Ok(());
+        }
+    }
+}
+
+pub async fn run_http_server(port: u16, ctx: HttpContext) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let ctx = Arc::new(ctx);
+
+    // One store snapshot per push interval, shared by all WebSocket clients
+    let (snapshot_tx, snapshot_rx) = tokio::sync::watch::channel(Arc::new(Snapshot::default()));
+    tokio::spawn(websocket::run_publisher(Arc::clone(&ctx.store), snapshot_tx));
+
+    let listener = TcpListener::bind(format!("0.0.0.0:{}", port)).await?;
+    info!("HTTP server listening on port {}", port);
+
+    loop {
+        let (socket, addr) = listener.accept().await?;
+        debug!("HTTP client connected: {}", addr);
+
+        let ctx = Arc::clone(&ctx);
+        let snapshots = snapshot_rx.clone();
+
+        tokio::spawn(async move {
+            ctx.net.connected();
+            if let Err(e) = handle_connection(socket, addr.to_string(), Arc::clone(&ctx), snapshots).await {
+                debug!("HTTP error: {}", e);
+            }
+            ctx.net.disconnected();
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request() {
+        let req = Request::parse(
+            "GET /data/aircraft.json?_=17 HTTP/1.1\r\nHost: x\r\nAccept-Encoding: br, GZip;q=0.8\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!((req.method.as_str(), req.path.as_str(), req.query.as_str()), ("GET", "/data/aircraft.json", "_=17"));
+        assert!(req.keep_alive());
+        assert!(req.header_has("accept-encoding", "gzip"));
+        assert_eq!(req.content_length(), Some(0));
+
+        let old = Request::parse("GET / HTTP/1.0\r\nConnection: Keep-Alive\r\n\r\n").unwrap();
+        assert!(old.keep_alive());
+        assert!(!Request::parse("GET / HTTP/1.0\r\n\r\n").unwrap().keep_alive());
+        assert!(!Request::parse("GET / HTTP/1.1\r\nConnection: close\r\n\r\n").unwrap().keep_alive());
+        assert!(Request::parse("GET /\r\n\r\n").is_none());
+        assert!(Request::parse("GET / HTTP/1.1\r\nbad header\r\n\r\n").is_none());
+    }
+
+    #[test]
+    fn test_static_paths() {
+        let root = Path::new("/srv/www");
+        assert_eq!(resolve_static(root, "/js/app.js"), Some(PathBuf::from("/srv/www/js/app.js")));
+        assert_eq!(resolve_static(root, "/../etc/passwd"), None);
+        assert_eq!(resolve_static(root, "/a/./b"), Some(PathBuf::from("/srv/www/a/b")));
+        assert_eq!(resolve_static(root, "/a\\..\\b"), None);
+        assert_eq!(content_type(Path::new("x/Style.CSS")), "text/css;charset=utf-8");
+        assert_eq!(content_type(Path::new("noext")), "application/octet-stream");
+    }
+
+    #[test]
+    fn test_encode_response() {
+        let body = format!("[{}]", vec!["{\"hex\":\"4840d6\"}"; 50].join(","));
+        let plain = String::from_utf8(Response::json(body.clone()).encode(false, false, true)).unwrap();
+        assert!(plain.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(plain.contains(&format!("Content-Length: {}\r\n", body.len())));
+        assert!(plain.contains("Connection: keep-alive\r\n") && plain.ends_with(&body));
+
+        let gz = Response::json(body.clone()).encode(false, true, true);
+        let head_len = gz.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+        let head = String::from_utf8_lossy(&gz[..head_len]);
+        assert!(head.contains("Content-Encoding: gzip\r\n"));
+        assert!(head.contains(&format!("Content-Length: {}\r\n", gz.len() - head_len)));
+
+        let not_allowed = Response { allow: Some("GET, HEAD"), ..Response::error(405) };
+        let out = String::from_utf8(not_allowed.encode(true, false, false)).unwrap();
+        assert!(out.starts_with("HTTP/1.1 405 Method Not Allowed\r\n") && out.contains("Allow: GET, HEAD\r\n"));
+        assert!(out.ends_with("\r\n\r\n"));
+    }
+}
@@ -0,0 +1,247 @@
+//! DC offset and I/Q imbalance correction
+//!
+//! Cheap tuners leave a DC offset on I and Q and mismatch the gain and
+//! phase of the two channels, which biases every magnitude and costs weak
+//! signals. The corrector tracks the offset and, optionally, the imbalance
+//! block by block and removes them before magnitudes are computed.
+
+use std::fmt;
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+use serde::Serialize;
+
+use crate::sample_format::{SampleFormat, scale_magnitude};
+
+/// Weight of each new block in the running estimates (~20 blocks, ~1.3 s)
+const TRACKING_ALPHA: f32 = 0.05;
+/// Ignore blocks with less power than this (e.g. zero padding)
+const MIN_POWER: f32 = 1e-9;
+/// Largest phase error we trust, as sin(phase) (30°)
+const MAX_SIN_PHASE: f32 = 0.5;
+
+/// Correction values shared with the display and stats consumers
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct IqCorrectionStats {
+    /// DC offset removed from I, in full-scale units
+    pub dc_i: f32,
+    /// DC offset removed from Q, in full-scale units
+    pub dc_q: f32,
+    /// Q/I amplitude ratio corrected, in dB (0 when balance is off)
+    pub gain_imbalance_db: f32,
+    /// Quadrature phase error corrected, in degrees (0 when balance is off)
+    pub phase_error_deg: f32,
+    /// Blocks corrected
+    pub blocks: u64,
+}
+
+impl fmt::Display for IqCorrectionStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "DC offset I {:+.4} Q {:+.4}, gain imbalance {:+.2} dB, phase error {:+.2} deg",
+            self.dc_i, self.dc_q, self.gain_imbalance_db, self.phase_error_deg
+        )
+    }
+}
+
+pub type SharedIqCorrectionStats = Arc<RwLock<IqCorrectionStats>>;
+
+/// Running second-order I/Q statistics for the balance estimate
+#[derive(Debug, Clone, Copy)]
+struct IqPower {
+    ii: f32,
+    qq: f32,
+    iq: f32,
+}
+
+/// Tracking DC offset remover with optional I/Q balance correction
+pub struct IqCorrector {
+    dc_i: f32,
+    dc_q: f32,
+    iq_balance: bool,
+    /// Smoothed power estimates, once a usable block has been seen
+    power: Option<IqPower>,
+    started: bool,
+    stats: SharedIqCorrectionStats,
+    /// Decoded samples of the current block, reused between blocks
+    samples: Vec<(f32, f32)>,
+}
+
+impl IqCorrector {
+    pub fn new(iq_balance: bool, stats: SharedIqCorrectionStats) -> Self {
+        Self {
+            dc_i: 0.0,
+            dc_q: 0.0,
+            iq_balance,
+            power: None,
+            started: false,
+            stats,
+            samples: Vec::new(),
+        }
+    }
+
+    /// Correct a block of raw I/Q bytes into its magnitudes in LUT units,
+    /// updating the estimates from the block first. Replaces the contents
+    /// of `out` so its allocation can be reused.
+    pub fn compute_magnitude_into(&mut self, data: &[u8], format: SampleFormat, out: &mut Vec<u16>) {
+        out.clear();
+        let mut samples = std::mem::take(&mut self.samples);
+        format.decode_into(data, &mut samples);
+        if !samples.is_empty() {
+            self.correct(&mut samples, out);
+        }
+        self.samples = samples;
+    }
+
+    /// Track and remove the impairments of one block, appending its
+    /// magnitudes to `out`
+    fn correct(&mut self, samples: &mut [(f32, f32)], out: &mut Vec<u16>) {
+        self.track_dc(samples);
+        for s in samples.iter_mut() {
+            s.0 -= self.dc_i;
+            s.1 -= self.dc_q;
+        }
+
+        let (gain, sin_phase) = if self.iq_balance { self.track_balance(samples) } else { (1.0, 0.0) };
+        self.started = true;
+
+        {
+            let mut stats = self.stats.write();
+            stats.dc_i = self.dc_i;
+            stats.dc_q = self.dc_q;
+            stats.gain_imbalance_db = 20.0 * gain.log10();
+            stats.phase_error_deg = sin_phase.asin().to_degrees();
+            stats.blocks += 1;
+        }
+
+        if !self.iq_balance {
+            out.extend(samples.iter().map(|&(i, q)| scale_magnitude(i, q)));
+            return;
+        }
+
+        // Q was measured as g·sin(wt + φ); recover sin(wt) against I = cos(wt)
+        let cos_phase = (1.0 - sin_phase * sin_phase).sqrt();
+        out.extend(
+            samples
+                .iter()
+                .map(|&(i, q)| scale_magnitude(i, (q / gain - i * sin_phase) / cos_phase)),
+        );
+    }
+
+    fn track_dc(&mut self, samples: &[(f32, f32)]) {
+        let n = samples.len() as f32;
+        let (sum_i, sum_q) = samples.iter().fold((0.0f32, 0.0f32), |(a, b), &(i, q)| (a + i, b + q));
+        let (mean_i, mean_q) = (sum_i / n, sum_q / n);
+
+        if self.started {
+            self.dc_i += TRACKING_ALPHA * (mean_i - self.dc_i);
+            self.dc_q += TRACKING_ALPHA * (mean_q - self.dc_q);
+        } else {
+            self.dc_i = mean_i;
+            self.dc_q = mean_q;
+        }
+    }
+
+    /// Update the power estimates; returns (Q/I gain, sin(phase error))
+    fn track_balance(&mut self, samples: &[(f32, f32)]) -> (f32, f32) {
+        let n = samples.len() as f32;
+        let mut block = IqPower { ii: 0.0, qq: 0.0, iq: 0.0 };
+        for &(i, q) in samples {
+            block.ii += i * i;
+            block.qq += q * q;
+            block.iq += i * q;
+        }
+        block.ii /= n;
+        block.qq /= n;
+        block.iq /= n;
+
+        if block.ii > MIN_POWER && block.qq > MIN_POWER {
+            self.power = Some(match self.power {
+                Some(p) => IqPower {
+                    ii: p.ii + TRACKING_ALPHA * (block.ii - p.ii),
+                    qq: p.qq + TRACKING_ALPHA * (block.qq - p.qq),
+                    iq: p.iq + TRACKING_ALPHA * (block.iq - p.iq),
+                },
+                None => block,
+            });
+        }
+
+        match self.power {
+            Some(p) => {
+                let gain = (p.qq / p.ii).sqrt();
+                let sin_phase = (p.iq / (p.ii * p.qq).sqrt()).clamp(-MAX_SIN_PHASE, MAX_SIN_PHASE);
+                (gain, sin_phase)
+            }
+            None => (1.0, 0.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A tone with the given DC offset and Q gain/phase error
+    fn impaired_tone(dc: (f32, f32), gain: f32, phase_deg: f32) -> Vec<(f32, f32)> {
+        let phase = phase_deg.to_radians();
+        (0..20_000)
+            .map(|n| {
+                let w = n as f32 * 0.37;
+                (0.3 * w.cos() + dc.0, gain * 0.3 * (w + phase).sin() + dc.1)
+            })
+            .collect()
+    }
+
+    fn stats() -> SharedIqCorrectionStats {
+        Arc::new(RwLock::new(IqCorrectionStats::default()))
+    }
+
+    #[test]
+    fn test_dc_removal() {
+        let format = SampleFormat::Cf32;
+        let data = format.encode(&impaired_tone((0.1, -0.05), 1.0, 0.0));
+        let stats = stats();
+        let mut corrector = IqCorrector::new(false, Arc::clone(&stats));
+
+        let mut mag = Vec::new();
+        corrector.compute_magnitude_into(&data, format, &mut mag);
+        let stats = stats.read();
+        assert!((stats.dc_i - 0.1).abs() < 0.001 && (stats.dc_q + 0.05).abs() < 0.001, "{}", *stats);
+
+        // A constant-envelope tone again once the offset is gone
+        let expected = 0.3 * 128.0 * 360.0;
+        assert!(mag.iter().all(|&m| (m as f32 - expected).abs() < expected * 0.01));
+    }
+
+    #[test]
+    fn test_iq_balance() {
+        let format = SampleFormat::Cf32;
+        let data = format.encode(&impaired_tone((0.0, 0.0), 1.2, 5.0));
+        let stats = stats();
+        let mut corrector = IqCorrector::new(true, Arc::clone(&stats));
+
+        let mut mag = Vec::new();
+        corrector.compute_magnitude_into(&data, format, &mut mag);
+        let stats = stats.read();
+        assert!((stats.gain_imbalance_db - 20.0 * 1.2f32.log10()).abs() < 0.05, "{}", *stats);
+        assert!((stats.phase_error_deg - 5.0).abs() < 0.1, "{}", *stats);
+
+        let expected = 0.3 * 128.0 * 360.0;
+        assert!(mag.iter().all(|&m| (m as f32 - expected).abs() < expected * 0.01));
+    }
+
+    #[test]
+    fn test_matches_lut_without_impairments() {
+        let lut = crate::magnitude::MagnitudeLut::new();
+        let format = SampleFormat::Cu8;
+        let data = format.encode(&impaired_tone((0.0, 0.0), 1.0, 0.0));
+        let mut corrector = IqCorrector::new(false, stats());
+
+        let mut corrected = Vec::new();
+        corrector.compute_magnitude_into(&data, format, &mut corrected);
+        let mut plain = Vec::new();
+        format.compute_magnitude_into(&data, &lut, &mut plain);
+        assert!(corrected.iter().zip(&plain).all(|(&a, &b)| (a as i32 - b as i32).abs() < 400));
+    }
+}
@@ -74,6 +74,14 @@ impl Default for MagnitudeLut {
 /// - Safe indexing into the LUT
 pub fn compute_magnitude_vector(data: &[u8], lut: &MagnitudeLut) -> Vec<u16> {
     let mut magnitude = Vec::with_capacity(data.len() / 2);
+    compute_magnitude_into(data, lut, &mut magnitude);
+    magnitude
+}
+
+/// Convert raw I/Q samples to magnitudes, replacing the contents of
+/// `magnitude` so its allocation can be reused from block to block.
+pub fn compute_magnitude_into(data: &[u8], lut: &MagnitudeLut, magnitude: &mut Vec<u16>) {
+    magnitude.clear();
 
     // Process I/Q pairs using safe iteration
     for chunk in data.chunks_exact(2) {
@@ -87,8 +95,6 @@ pub fn compute_magnitude_vector(data: &[u8], lut: &MagnitudeLut) -> Vec<u16> {
 
         magnitude.push(lut.lookup(i, q));
     }
-
-    magnitude
 }
 
 #[cfg(test)]
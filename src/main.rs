@@ -4,14 +4,39 @@
 //! 
 //! 
 
+mod agc;
 mod aircraft;
+mod backoff;
+mod beast;
+mod bench;
 mod config;
+mod connector;
 mod crc;
+mod date;
 mod decoder;
 mod demodulator;
+mod gain_sweep;
+mod generator;
+mod gzip;
+mod hackrf;
+mod http;
+mod iq_correction;
 mod magnitude;
+mod metrics;
+mod mqtt;
 mod network;
+mod readsb;
+mod recorder;
+mod report;
+mod rtltcp;
+mod sample_format;
+mod sbs;
+mod sigmf;
 mod signal;
+mod simd;
+mod stats;
+mod supervisor;
+mod websocket;
 
 use std::io::{self, Write};
 use std::sync:: Arc;
@@ -19,16 +44,40 @@ use std::time::{Duration, Instant};
 
 use crossbeam_channel::{Receiver, Sender, bounded};
 use parking_lot::RwLock;
-use tracing::{Level, info, error};
+use tracing::{Level, info, error, warn};
 use tracing_subscriber:: FmtSubscriber;
 
+use crate::agc::{Agc, AgcStats, SharedAgcStats};
 use crate::aircraft::AircraftStore;
-use crate::config::Config;
+use crate::config::{Config, DeviceType};
+use crate::connector::{ConnectorState, ConnectorStatus, SharedConnectorStatus};
 use crate::decoder::ModesMessage;
-use crate::demodulator::Demodulator;
+use crate::demodulator::{DemodStats, Demodulator, MODES_OVERLAP_SAMPLES, SharedDemodStats};
+use crate::gain_sweep::{GainSweep, SweepStep};
+use crate::iq_correction::{IqCorrectionStats, SharedIqCorrectionStats};
+use crate::metrics::MetricsSources;
+use crate::network::NetStats;
+use crate::recorder::{IqRecorder, RecorderConfig};
+use crate::sample_format::SampleFormat;
+use crate::report::{Checkpoint, ReportKind, ReportSources, StatsHistory};
+use crate::stats::{MessageOrigin, ReceiverStats, SharedReceiverStats, unix_now};
+use crate::supervisor::{DeviceHealth, ReadOutcome, SharedDeviceHealth, Supervisor};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let config = Config::from_args();
+    // `adsb generate ...` writes synthetic test data instead of decoding
+    if std::env::args().nth(1).as_deref() == Some("generate") {
+        let args: Vec<String> = std::env::args().skip(2).collect();
+        return generator::run_cli(&args);
+    }
+
+    // `adsb bench ...` times the per-sample hot loops
+    if std::env::args().nth(1).as_deref() == Some("bench") {
+        let args: Vec<String> = std::env::args().skip(2).collect();
+        return bench::run_cli(&args);
+    }
+
+    let mut config = Config::from_args();
+    let sigmf_meta = config.apply_sigmf_meta();
 
     // Initialize logging only if not in interactive mode
     if !config.interactive {
@@ -38,6 +87,16 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         tracing::subscriber::set_global_default(subscriber).ok();
         info!("dump1090-rs starting.. .");
         info!("Configuration: {:?}", config);
+        match &sigmf_meta {
+            Ok(Some(meta)) => {
+                info!("Using SigMF metadata: {:?}", meta);
+                for conflict in config.sigmf_conflicts(meta) {
+                    warn!("{}", conflict);
+                }
+            }
+            Ok(None) => {}
+            Err(e) => error!("Ignoring unreadable SigMF metadata: {}", e),
+        }
     }
 
     // Shared aircraft store with min_messages filter from config
@@ -49,16 +108,74 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Channel for decoded messages
     let (msg_tx, msg_rx): (Sender<ModesMessage>, Receiver<ModesMessage>) = bounded(1024);
 
+    // Software AGC state, updated by the capture loop
+    let agc_stats: SharedAgcStats = Arc::new(RwLock::new(AgcStats::default()));
+
+    // DC offset / I/Q balance correction values, updated by the demodulator
+    let iq_stats: SharedIqCorrectionStats = Arc::new(RwLock::new(IqCorrectionStats::default()));
+
+    // Capture process health, updated by the supervisor
+    let device_health: SharedDeviceHealth = Arc::new(RwLock::new(DeviceHealth::default()));
+
+    // Outbound connector status, updated by the connectors
+    let connector_status: Vec<SharedConnectorStatus> =
+        config.net_connectors.iter().map(ConnectorStatus::shared).collect();
+
+    // Message counts for stats.json, from the processor and network inputs
+    let receiver_stats: SharedReceiverStats = ReceiverStats::shared();
+
+    // Demodulation counters for /metrics, published after every block
+    let demod_stats: SharedDemodStats = Arc::new(RwLock::new(DemodStats::default()));
+
+    // Device health is tracked for a local SDR only
+    let local_device = config.filename.is_none() && !config.net_only && config.device_type != DeviceType::RtlTcp;
+
+    // Everything the statistics reports draw on
+    let report_sources = ReportSources {
+        demod: Arc::clone(&demod_stats),
+        receiver: Arc::clone(&receiver_stats),
+        store: Arc::clone(&aircraft_store),
+        agc: config.enable_agc.then(|| Arc::clone(&agc_stats)),
+        iq: (config.dc_filter || config.iq_balance).then(|| Arc::clone(&iq_stats)),
+        device: local_device.then(|| Arc::clone(&device_health)),
+        connectors: connector_status.clone(),
+    };
+    let run_start = Checkpoint::start(unix_now());
+    let stats_history = config
+        .stats_history
+        .as_ref()
+        .map(|path| StatsHistory::new(path, config.stats_history_max_size_mb));
+
+    // Accepted messages for the network output servers
+    let (net_tx, _) = tokio::sync::broadcast::channel::<ModesMessage>(network::BROADCAST_CAPACITY);
+    let net_tx = (config.net || config.net_only).then_some(net_tx);
+
+    // Recording trigger events, from the message processor to the capture loop
+    let (trigger_tx, trigger_rx) = if config.record_trigger.is_active() {
+        let (tx, rx) = crossbeam_channel::unbounded::<String>();
+        (Some(tx), Some(rx))
+    } else {
+        (None, None)
+    };
+
     // Start the runtime
     let rt = tokio::runtime::Runtime::new()?;
 
     rt.block_on(async {
         // Start network services if enabled
-        let net_handle = if config.net || config.net_only {
+        let net_handle = if let Some(tx) = net_tx.clone() {
             let store = Arc::clone(&aircraft_store);
+            let stats = Arc::clone(&receiver_stats);
             let cfg = config.clone();
+            let connectors = connector_status.clone();
+            let metrics = MetricsSources {
+                demod: Arc::clone(&demod_stats),
+                net: NetStats::shared(),
+                decoder_queue: msg_rx.clone(),
+                net_queue: tx.clone(),
+            };
             Some(tokio::spawn(async move {
-                if let Err(e) = network::run_servers(cfg, store).await {
+                if let Err(e) = network::run_servers(cfg, store, stats, tx, connectors, metrics).await {
                     error!("Network error: {}", e);
                 }
             }))
@@ -68,9 +185,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         // Message processing task
         let store_for_processor = Arc::clone(&aircraft_store);
+        let stats_for_processor = Arc::clone(&receiver_stats);
         let config_for_processor = config.clone();
-        let processor_handle = tokio::spawn(async move {
-            process_messages(msg_rx, store_for_processor, config_for_processor).await;
+        // Blocks on the channel, so keep it off the async worker threads
+        let mut processor_handle = tokio::task::spawn_blocking(move || {
+            process_messages(msg_rx, store_for_processor, stats_for_processor, config_for_processor, trigger_tx, net_tx);
         });
 
         let interactive_handle = if config.interactive {
@@ -79,13 +198,36 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             let metric = config.metric;
             let receiver_lat = config.receiver_lat;
             let receiver_lon = config.receiver_lon;
+            let status = StatusHandles {
+                agc: config.enable_agc.then(|| Arc::clone(&agc_stats)),
+                device_health: local_device.then(|| Arc::clone(&device_health)),
+                connectors: connector_status.clone(),
+            };
             Some(tokio::spawn(async move {
-                interactive_display(store, rows, metric, receiver_lat, receiver_lon).await;
+                interactive_display(store, rows, metric, receiver_lat, receiver_lon, status).await;
             }))
         } else {
             None
         };
 
+        // Interval statistics in live mode (printed unless the display owns the screen)
+        let report_handle = match config.stats_every {
+            Some(secs) if config.filename.is_none() => {
+                let sources = report_sources.clone();
+                let history = stats_history.clone();
+                let print = !config.interactive;
+                Some(tokio::spawn(async move {
+                    report::run_interval_reports(sources, Duration::from_secs(secs), history, print).await;
+                }))
+            }
+            _ => None,
+        };
+
+        // MQTT publisher
+        let mqtt_handle = config.mqtt.broker.is_some().then(|| {
+            tokio::spawn(mqtt::run_publisher(config.mqtt.clone(), Arc::clone(&aircraft_store)))
+        });
+
         // Stale aircraft removal task
         let cleanup_handle = {
             let store = Arc::clone(&aircraft_store);
@@ -101,7 +243,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         // Data acquisition and demodulation
         if ! config.net_only {
-            run_demodulation(&config, msg_tx).await;
+            run_demodulation(&config, msg_tx, &agc_stats, &iq_stats, &demod_stats, &device_health, trigger_rx).await;
+        }
+
+        // The demodulator has hung up; let the processor finish the file's
+        // messages so the final statistics include them
+        if config.filename.is_some() {
+            (&mut processor_handle).await.ok();
         }
 
         // After file processing, keep running if interactive or net mode
@@ -122,40 +270,82 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         if let Some(h) = interactive_handle {
             h.abort();
         }
+        if let Some(h) = report_handle {
+            h.abort();
+        }
+        if let Some(h) = mqtt_handle {
+            h.abort();
+        }
         processor_handle.abort();
     });
 
+    if config.stats || stats_history.is_some() {
+        let report = report_sources.report(ReportKind::Total, &run_start, &report_sources.checkpoint());
+        report::publish(&report, stats_history.as_ref(), config.stats);
+    }
+
     Ok(())
 }
 
-async fn run_demodulation(config: &Config, msg_tx: Sender<ModesMessage>) {
-    use crate::config::DeviceType;
-    
-    let demodulator = Demodulator::new(config.clone());
+async fn run_demodulation(
+    config: &Config,
+    msg_tx: Sender<ModesMessage>,
+    agc_stats: &SharedAgcStats,
+    iq_stats: &SharedIqCorrectionStats,
+    demod_stats: &SharedDemodStats,
+    device_health: &SharedDeviceHealth,
+    trigger_rx: Option<Receiver<String>>,
+) {
+    let mut demodulator =
+        Demodulator::with_iq_stats(config.clone(), Arc::clone(iq_stats)).with_demod_stats(Arc::clone(demod_stats));
 
     if let Some(ref filename) = config.filename {
         if !config.interactive {
             info!("Reading from file: {}", filename);
         }
-        if let Err(e) = demodulator.process_file(filename, &msg_tx) {
-            if !config.interactive {
-                error!("Error processing file: {}", e);
-            }
+        if let Err(e) = demodulator.process_file(filename, &msg_tx)
+            && !config.interactive
+        {
+            error!("Error processing file: {}", e);
         }
     } else {
+        let mut recorder = match RecorderConfig::from_config(config) {
+            Some(rec_config) => {
+                let (format, device) = match config.device_type {
+                    DeviceType::RtlSdr => (SampleFormat::Cu8, "RTL-SDR"),
+                    DeviceType::HackRf => (SampleFormat::Cs8, "HackRF One"),
+                    DeviceType::RtlTcp => (SampleFormat::Cu8, "RTL-SDR via rtl_tcp"),
+                };
+                match IqRecorder::new(rec_config, format, 2_000_000, config.freq, device, trigger_rx) {
+                    Ok(recorder) => Some(recorder),
+                    Err(e) => {
+                        error!("Cannot record I/Q: {}", e);
+                        None
+                    }
+                }
+            }
+            None => None,
+        };
+
         // Run appropriate SDR command based on device type
         let result = match config.device_type {
             DeviceType::RtlSdr => {
                 if !config.interactive {
                     info!("Attempting to read from RTL-SDR...");
                 }
-                run_rtlsdr_command(config, &msg_tx).await
+                run_rtlsdr_command(config, &mut demodulator, &msg_tx, agc_stats, device_health, &mut recorder).await
             }
             DeviceType::HackRf => {
                 if !config.interactive {
                     info!("Attempting to read from HackRF One...");
                 }
-                run_hackrf_command(config, &msg_tx).await
+                run_hackrf_command(config, &mut demodulator, &msg_tx, agc_stats, device_health, &mut recorder).await
+            }
+            DeviceType::RtlTcp => {
+                if !config.interactive {
+                    info!("Connecting to rtl_tcp server at {}...", config.rtltcp_addr);
+                }
+                run_rtltcp(config, &mut demodulator, &msg_tx, agc_stats, &mut recorder).await
             }
         };
 
@@ -163,6 +353,7 @@ async fn run_demodulation(config: &Config, msg_tx: Sender<ModesMessage>) {
             let device_name = match config.device_type {
                 DeviceType::RtlSdr => "RTL-SDR",
                 DeviceType::HackRf => "HackRF",
+                DeviceType::RtlTcp => "rtl_tcp",
             };
             error!("Error with {}: {}", device_name, e);
             if !config.interactive {
@@ -173,6 +364,9 @@ async fn run_demodulation(config: &Config, msg_tx: Sender<ModesMessage>) {
                     DeviceType::HackRf => {
                         eprintln!("\nMake sure hackrf is installed: sudo dnf install hackrf");
                     }
+                    DeviceType::RtlTcp => {
+                        eprintln!("\nMake sure rtl_tcp is running on {}", config.rtltcp_addr);
+                    }
                 }
                 eprintln!("Or use --ifile to read from a file, or --net-only for network mode");
             }
@@ -180,165 +374,351 @@ async fn run_demodulation(config: &Config, msg_tx: Sender<ModesMessage>) {
     }
 }
 
+/// A capture tool that streams I/Q samples to stdout
+struct CaptureTool<F: Fn(Option<f64>) -> (tokio::process::Command, Option<f64>)> {
+    name: &'static str,
+    format: SampleFormat,
+    /// Build the command line for a gain override from the AGC or gain
+    /// sweep (if any); also returns the gain in dB the command applies,
+    /// when it's a definite value
+    command: F,
+}
+
+/// Run RTL-SDR using the rtl_sdr command
 async fn run_rtlsdr_command(
     config: &Config,
+    demodulator: &mut Demodulator,
     msg_tx: &Sender<ModesMessage>,
+    agc_stats: &SharedAgcStats,
+    health: &SharedDeviceHealth,
+    recorder: &mut Option<IqRecorder>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    use std::process::Stdio;
-    use tokio::io::AsyncReadExt;
-    use tokio::process::Command;
-
-    let mut demodulator = Demodulator::new(config.clone());
-
-    // Build rtl_sdr command
-    let mut cmd = Command::new("rtl_sdr");
-    cmd.arg("-f")
-        .arg(config.freq.to_string())
-        .arg("-s")
-        .arg("2000000")
-        .arg("-g")
-        .arg(if config.gain < 0 {
-            "0".to_string()
-        } else {
-            (config.gain / 10).to_string()
-        })
-        .arg("-")
-        .stdout(Stdio::piped())
-        .stderr(Stdio::null());
+    let tool = CaptureTool {
+        name: "rtl_sdr",
+        format: SampleFormat::Cu8,
+        command: |gain_db: Option<f64>| {
+            let (gain, gain_db) = match gain_db {
+                Some(db) => (format!("{:.1}", db), Some(db)),
+                None if config.gain < 0 => ("0".to_string(), None),
+                None if config.gain >= 999999 => ((config.gain / 10).to_string(), None),
+                None => ((config.gain / 10).to_string(), Some(config.gain as f64 / 10.0)),
+            };
+            let device = config
+                .device_serial
+                .clone()
+                .unwrap_or_else(|| config.dev_index.to_string());
+
+            let mut cmd = tokio::process::Command::new("rtl_sdr");
+            cmd.arg("-d")
+                .arg(device)
+                .arg("-f")
+                .arg(config.freq.to_string())
+                .arg("-s")
+                .arg("2000000")
+                .arg("-g")
+                .arg(gain)
+                .arg("-p")
+                .arg(config.ppm.to_string())
+                .arg("-");
+            (cmd, gain_db)
+        },
+    };
+    run_capture_tool(config, demodulator, msg_tx, agc_stats, health, recorder, tool).await
+}
+
+/// Run HackRF One using hackrf_transfer command
+async fn run_hackrf_command(
+    config: &Config,
+    demodulator: &mut Demodulator,
+    msg_tx: &Sender<ModesMessage>,
+    agc_stats: &SharedAgcStats,
+    health: &SharedDeviceHealth,
+    recorder: &mut Option<IqRecorder>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if config.device_serial.is_none() && config.dev_index != 0 {
+        warn!("hackrf_transfer selects devices by serial number; use --device-serial");
+    }
 
-    let mut child = cmd.spawn()?;
-    let mut stdout = child.stdout.take().ok_or("Failed to get stdout")?;
+    let tool = CaptureTool {
+        name: "hackrf_transfer",
+        format: SampleFormat::Cs8,
+        command: |gain_db: Option<f64>| {
+            let settings = match gain_db {
+                Some(db) => config.hackrf.with_total_gain(db),
+                None => config.hackrf.clone(),
+            };
+
+            // -r - : receive to stdout
+            // -f : frequency in Hz
+            // -s : sample rate (2M for ADS-B)
+            // -d : device serial number
+            // plus amp/LNA/VGA/filter settings
+            let mut cmd = tokio::process::Command::new("hackrf_transfer");
+            cmd.arg("-r")
+                .arg("-")  // Output to stdout
+                .arg("-f")
+                .arg(config.freq.to_string())
+                .arg("-s")
+                .arg("2000000")
+                .args(settings.args());
+            if let Some(serial) = &config.device_serial {
+                cmd.arg("-d").arg(serial);
+            }
+            (cmd, Some(settings.total_gain_db()))
+        },
+    };
+    run_capture_tool(config, demodulator, msg_tx, agc_stats, health, recorder, tool).await
+}
+
+/// Run a capture tool under supervision. It is restarted with backoff when
+/// it exits or stalls, and immediately when the AGC picks a new gain (the
+/// tools can't change gain while running).
+async fn run_capture_tool<F: Fn(Option<f64>) -> (tokio::process::Command, Option<f64>)>(
+    config: &Config,
+    demodulator: &mut Demodulator,
+    msg_tx: &Sender<ModesMessage>,
+    agc_stats: &SharedAgcStats,
+    health: &SharedDeviceHealth,
+    recorder: &mut Option<IqRecorder>,
+    tool: CaptureTool<F>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut sweep = config.gain_sweep.map(|dwell| GainSweep::for_config(config, dwell));
+    let mut agc = (config.enable_agc && sweep.is_none())
+        .then(|| Agc::for_config(config, Arc::clone(agc_stats)));
+    let mut supervisor = Supervisor::new(
+        tool.name,
+        Duration::from_secs(config.stall_timeout),
+        Arc::clone(health),
+    );
 
-    let buffer_len = 16 * 16384 + (8 + 112 - 1) * 4;
-    let mut data = vec![127u8; buffer_len];
     let read_size = 16 * 16384;
+    let overlap = MODES_OVERLAP_SAMPLES * tool.format.bytes_per_sample();
+    let mut data = vec![tool.format.zero_byte(); read_size + overlap];
 
     loop {
-        let overlap = (8 + 112 - 1) * 4;
-        data.copy_within(read_size..read_size + overlap, 0);
+        let gain_override = match (&sweep, &agc) {
+            (Some(sweep), _) => Some(sweep.gain_db()),
+            (None, Some(agc)) => Some(agc.gain_db()),
+            (None, None) => None,
+        };
+        let (mut cmd, gain_db) = (tool.command)(gain_override);
 
-        let mut total_read = 0;
-        while total_read < read_size {
-            match stdout
-                .read(&mut data[overlap + total_read..overlap + read_size])
-                .await
+        // Tag recordings with the gain when it's a definite value
+        if let Some(rec) = recorder.as_mut()
+            && let Some(gain_db) = gain_db
+        {
+            rec.set_gain(gain_db)?;
+        }
+
+        let mut child = match supervisor.spawn(&mut cmd) {
+            Ok(child) => child,
+            // Not installed: retrying won't help
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(e.into()),
+            Err(e) => {
+                supervisor.restart_after(&format!("failed to start: {}", e), Duration::ZERO).await;
+                continue;
+            }
+        };
+
+        loop {
+            data.copy_within(read_size..read_size + overlap, 0);
+
+            match child.read_full(&mut data[overlap..overlap + read_size]).await {
+                Ok(ReadOutcome::Data) => {}
+                Ok(ReadOutcome::Eof) => {
+                    let ran_for = child.ran_for();
+                    let status = child.stop().await;
+                    supervisor.restart_after(&status, ran_for).await;
+                    break;
+                }
+                Ok(ReadOutcome::Stalled) => {
+                    let ran_for = child.ran_for();
+                    child.stop().await;
+                    supervisor.note_stall();
+                    let reason = format!("no samples for {} s", config.stall_timeout);
+                    supervisor.restart_after(&reason, ran_for).await;
+                    break;
+                }
+                Err(e) => {
+                    let ran_for = child.ran_for();
+                    child.stop().await;
+                    supervisor.restart_after(&format!("read error: {}", e), ran_for).await;
+                    break;
+                }
+            }
+
+            // Process the data
+            demodulator.process_samples(&data[..overlap + read_size], tool.format, msg_tx);
+
+            if let Some(rec) = recorder.as_mut()
+                && let Err(e) = rec.write(&data[overlap..overlap + read_size])
             {
-                Ok(0) => return Ok(()), // EOF
-                Ok(n) => total_read += n,
-                Err(e) => return Err(e.into()),
+                error!("I/Q recording stopped: {}", e);
+                *recorder = None;
             }
-        }
 
-        // Process the data
-        let magnitude = crate::magnitude::compute_magnitude_vector(
-            &data[..overlap + read_size],
-            &demodulator.mag_lut,
-        );
-        demodulator.detect_modes_external(&magnitude, msg_tx);
+            if let Some(agc) = agc.as_mut()
+                && let Some(gain) = agc.observe(
+                    &data[overlap..overlap + read_size],
+                    tool.format,
+                    demodulator.noise_floor(),
+                )
+            {
+                info!("AGC: setting {} gain to {:.1} dB", tool.name, gain);
+                child.stop().await;
+                break;
+            }
+
+            if let Some(sweep) = sweep.as_mut() {
+                match sweep.observe(read_size, demodulator.message_count()) {
+                    SweepStep::Continue => {}
+                    SweepStep::Next(gain) => {
+                        info!("Gain sweep: trying {:.1} dB", gain);
+                        child.stop().await;
+                        break;
+                    }
+                    SweepStep::Done => {
+                        child.stop().await;
+                        println!("{}", sweep.report());
+                        return Ok(());
+                    }
+                }
+            }
+        }
     }
 }
 
-/// Run HackRF One using hackrf_transfer command
-async fn run_hackrf_command(
+/// Read from a remote dongle through an rtl_tcp server
+async fn run_rtltcp(
     config: &Config,
+    demodulator: &mut Demodulator,
     msg_tx: &Sender<ModesMessage>,
+    agc_stats: &SharedAgcStats,
+    recorder: &mut Option<IqRecorder>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    use std::process::Stdio;
-    use tokio::io::AsyncReadExt;
-    use tokio::process::Command;
-
-    let mut demodulator = Demodulator::new(config.clone());
-
-    // Build hackrf_transfer command
-    // -r - : receive to stdout
-    // -f : frequency in Hz
-    // -s : sample rate (2M for ADS-B)
-    // -a : amp enable (0 or 1)
-    // -l : LNA gain (0-40 dB)
-    // -g : VGA gain (0-62 dB)
-    let mut cmd = Command::new("hackrf_transfer");
-    cmd.arg("-r")
-        .arg("-")  // Output to stdout
-        .arg("-f")
-        .arg(config.freq.to_string())
-        .arg("-s")
-        .arg("2000000")
-        .arg("-a")
-        .arg("1")  // Enable amp
-        .arg("-l")
-        .arg("32") // LNA gain
-        .arg("-g")
-        .arg("20") // VGA gain
-        .stdout(Stdio::piped())
-        .stderr(Stdio::null());
-
-    let mut child = cmd.spawn()?;
-    let mut stdout = child.stdout.take().ok_or("Failed to get stdout")?;
-
-    let buffer_len = 16 * 16384 + (8 + 112 - 1) * 4;
-    let mut raw_data = vec![0i8; buffer_len];
-    let mut data = vec![127u8; buffer_len];
+    use crate::agc::RTLSDR_GAINS_DB;
+    use crate::rtltcp::RtlTcpClient;
+
+    let mut sweep = config.gain_sweep.map(|dwell| GainSweep::for_config(config, dwell));
+    let mut agc = (config.enable_agc && sweep.is_none())
+        .then(|| Agc::for_config(config, Arc::clone(agc_stats)));
+
+    let mut client = RtlTcpClient::connect(&config.rtltcp_addr).await?;
+    let info = client.info();
+    info!("rtl_tcp: {} tuner, {} gain steps", info.tuner, info.gain_count);
+
+    client.set_sample_rate(2_000_000).await?;
+    client.set_frequency(config.freq).await?;
+    if config.ppm != 0 {
+        client.set_freq_correction(config.ppm).await?;
+    }
+
+    // Fixed or software-AGC gain is set manually; -10 selects tuner auto-gain
+    let gain_db = match (&sweep, &agc) {
+        (Some(sweep), _) => Some(sweep.gain_db()),
+        (None, Some(agc)) => Some(agc.gain_db()),
+        _ if config.gain < 0 => None,
+        _ => Some((config.gain as f64 / 10.0).min(RTLSDR_GAINS_DB[RTLSDR_GAINS_DB.len() - 1])),
+    };
+    client.set_manual_gain(gain_db.is_some()).await?;
+    client.set_agc_mode(gain_db.is_none()).await?;
+    if let Some(gain_db) = gain_db {
+        client.set_gain(gain_db).await?;
+        if let Some(rec) = recorder.as_mut() {
+            rec.set_gain(gain_db)?;
+        }
+    }
+
     let read_size = 16 * 16384;
+    let overlap = MODES_OVERLAP_SAMPLES * SampleFormat::Cu8.bytes_per_sample();
+    let mut data = vec![SampleFormat::Cu8.zero_byte(); read_size + overlap];
 
     loop {
-        let overlap = (8 + 112 - 1) * 4;
-        
-        // Copy overlap region
-        for i in 0..overlap {
-            raw_data[i] = raw_data[read_size + i];
-            data[i] = data[read_size + i];
+        data.copy_within(read_size..read_size + overlap, 0);
+
+        if !client.read_samples(&mut data[overlap..overlap + read_size]).await? {
+            return Ok(()); // Server closed the connection
         }
 
-        let mut total_read = 0;
-        while total_read < read_size {
-            // Read as bytes, then interpret as signed
-            let slice = unsafe {
-                std::slice::from_raw_parts_mut(
-                    raw_data[overlap + total_read..].as_mut_ptr() as *mut u8,
-                    read_size - total_read,
-                )
-            };
-            match stdout.read(slice).await {
-                Ok(0) => return Ok(()), // EOF
-                Ok(n) => total_read += n,
-                Err(e) => return Err(e.into()),
-            }
+        demodulator.process_samples(&data[..overlap + read_size], SampleFormat::Cu8, msg_tx);
+
+        if let Some(rec) = recorder.as_mut()
+            && let Err(e) = rec.write(&data[overlap..overlap + read_size])
+        {
+            error!("I/Q recording stopped: {}", e);
+            *recorder = None;
         }
 
-        // Convert signed 8-bit (HackRF) to unsigned 8-bit (RTL-SDR format)
-        // HackRF: -128 to 127, centered at 0
-        // RTL-SDR: 0 to 255, centered at 127
-        for i in 0..overlap + read_size {
-            data[i] = (raw_data[i] as i16 + 128) as u8;
+        // rtl_tcp can change gain on the fly, no restart needed
+        if let Some(agc) = agc.as_mut()
+            && let Some(gain) = agc.observe(
+                &data[overlap..overlap + read_size],
+                SampleFormat::Cu8,
+                demodulator.noise_floor(),
+            )
+        {
+            info!("AGC: setting rtl_tcp gain to {:.1} dB", gain);
+            client.set_gain(gain).await?;
+            if let Some(rec) = recorder.as_mut() {
+                rec.set_gain(gain)?;
+            }
         }
 
-        // Process the data (now in RTL-SDR format)
-        let magnitude = crate::magnitude::compute_magnitude_vector(
-            &data[..overlap + read_size],
-            &demodulator.mag_lut,
-        );
-        demodulator.detect_modes_external(&magnitude, msg_tx);
+        if let Some(sweep) = sweep.as_mut() {
+            match sweep.observe(read_size, demodulator.message_count()) {
+                SweepStep::Continue => {}
+                SweepStep::Next(gain) => {
+                    info!("Gain sweep: trying {:.1} dB", gain);
+                    client.set_gain(gain).await?;
+                }
+                SweepStep::Done => {
+                    println!("{}", sweep.report());
+                    return Ok(());
+                }
+            }
+        }
     }
 }
 
-async fn process_messages(
+fn process_messages(
     rx: Receiver<ModesMessage>,
     store: Arc<RwLock<AircraftStore>>,
+    stats: SharedReceiverStats,
     config: Config,
+    trigger_tx: Option<Sender<String>>,
+    net_tx: Option<tokio::sync::broadcast::Sender<ModesMessage>>,
 ) {
     while let Ok(msg) = rx.recv() {
+        // Fire recording triggers
+        if let Some(tx) = &trigger_tx
+            && let Some(event) = config.record_trigger.check(&msg)
+        {
+            tx.send(event).ok();
+        }
+
+        stats.write().record(MessageOrigin::Local, msg.is_mode_ac(), msg.crc_ok || !config.check_crc);
+
         // Update aircraft tracking
         if msg.crc_ok || ! config.check_crc {
             let mut store = store.write();
             store.update_from_message(&msg);
         }
 
+        // Forward to the network output servers (no receivers is fine)
+        if let Some(tx) = &net_tx
+            && (msg.crc_ok || !config.check_crc)
+        {
+            let _ = tx.send(msg.clone());
+        }
+
         // Display in non-interactive mode
         if !config.interactive {
             if config.raw {
                 println!("{}", msg. to_raw_string());
             } else if config.onlyaddr {
-                println!("{:06X}", msg.icao_address());
+                if !msg.is_mode_ac() {
+                    println!("{:06X}", msg.icao_address());
+                }
             } else {
                 println!("{}", msg);
             }
@@ -346,12 +726,20 @@ async fn process_messages(
     }
 }
 
+/// Receiver status shown in the interactive footer; `None` when not in use
+struct StatusHandles {
+    agc: Option<SharedAgcStats>,
+    device_health: Option<SharedDeviceHealth>,
+    connectors: Vec<SharedConnectorStatus>,
+}
+
 async fn interactive_display(
     store: Arc<RwLock<AircraftStore>>,
     max_rows: usize,
     metric: bool,
     receiver_lat: Option<f64>,
     receiver_lon: Option<f64>,
+    status: StatusHandles,
 ) {
     let refresh_interval = Duration::from_millis(250);
 
@@ -391,7 +779,7 @@ async fn interactive_display(
 
         let mut aircraft: Vec<_> = store.all().collect();
         // Sort by most recently seen
-        aircraft.sort_by(|a, b| b.seen.cmp(&a.seen));
+        aircraft.sort_by_key(|a| std::cmp::Reverse(a.seen));
 
         let count = aircraft.len();
         for ac in aircraft.iter().take(max_rows) {
@@ -557,11 +945,36 @@ async fn interactive_display(
         } else {
             String::new()
         };
+        let gain_info = status
+            .agc
+            .as_ref()
+            .map(|agc| format!(" | AGC: {:.1} dB", agc.read().gain_db))
+            .unwrap_or_default();
+        let device_info = status
+            .device_health
+            .as_ref()
+            .map(|health| {
+                let health = health.read();
+                match health.restarts {
+                    0 => format!(" | SDR: {}", health.state),
+                    n => format!(" | SDR: {} ({} restarts)", health.state, n),
+                }
+            })
+            .unwrap_or_default();
+        let feed_info = if status.connectors.is_empty() {
+            String::new()
+        } else {
+            let up = status.connectors.iter().filter(|c| c.read().state == ConnectorState::Connected).count();
+            format!(" | Feeds: {}/{} up", up, status.connectors.len())
+        };
         println!(
-            "Aircraft: {} | {} mode{} | Ctrl+C to exit",
+            "Aircraft: {} | {} mode{}{}{}{} | Ctrl+C to exit",
             count,
             if metric { "Metric" } else { "Imperial" },
-            pos_info
+            pos_info,
+            gain_info,
+            device_info,
+            feed_info
         );
 
         io::stdout().flush().ok();
@@ -0,0 +1,231 @@
+//! Prometheus metrics
+//!
+//! `/metrics` on the HTTP port, in the Prometheus text exposition format.
+//! Counters are kept where they happen (`DemodStats` in the demodulator,
+//! `NetStats` in the network servers, the aircraft store and receiver
+//! stats) and only rendered here, at scrape time.
+
+use std::fmt::Write;
+
+use crossbeam_channel::Receiver;
+use tokio::sync::broadcast;
+
+use crate::aircraft::AircraftStore;
+use crate::decoder::ModesMessage;
+use crate::demodulator::SharedDemodStats;
+use crate::network::SharedNetStats;
+use crate::stats::ReceiverStats;
+
+/// Content type of the text exposition format
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Upper bucket bounds for signal and noise levels in dBFS
+pub const DBFS_BUCKETS: [f64; 10] = [-40.0, -35.0, -30.0, -25.0, -20.0, -15.0, -10.0, -6.0, -3.0, 0.0];
+
+/// Fixed-bucket histogram
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    bounds: &'static [f64],
+    /// Observations per bucket (not cumulative), the last one for +Inf
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new(&DBFS_BUCKETS)
+    }
+}
+
+impl Histogram {
+    pub fn new(bounds: &'static [f64]) -> Self {
+        Self { bounds, counts: vec![0; bounds.len() + 1], sum: 0.0, count: 0 }
+    }
+
+    pub fn observe(&mut self, value: f64) {
+        let bucket = self.bounds.iter().position(|&b| value <= b).unwrap_or(self.bounds.len());
+        self.counts[bucket] += 1;
+        self.sum += value;
+        self.count += 1;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    fn write(&self, out: &mut String, name: &str, help: &str) {
+        family(out, name, "histogram", help);
+        let mut cumulative = 0;
+        for (i, &n) in self.counts.iter().enumerate() {
+            cumulative += n;
+            let le = self.bounds.get(i).map_or("+Inf".to_string(), |b| b.to_string());
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, le, cumulative);
+        }
+        let _ = writeln!(out, "{}_sum {}", name, self.sum);
+        let _ = writeln!(out, "{}_count {}", name, self.count);
+    }
+}
+
+/// Where the metrics come from, besides the store and receiver stats
+pub struct MetricsSources {
+    pub demod: SharedDemodStats,
+    pub net: SharedNetStats,
+    /// Demodulator → message processor channel
+    pub decoder_queue: Receiver<ModesMessage>,
+    /// Message processor → network outputs channel
+    pub net_queue: broadcast::Sender<ModesMessage>,
+}
+
+/// `# HELP` and `# TYPE` lines of a metric family
+fn family(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// A family with a single unlabelled sample
+fn single(out: &mut String, name: &str, kind: &str, help: &str, value: impl std::fmt::Display) {
+    family(out, name, kind, help);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+/// All metrics in the text exposition format
+pub fn render(sources: &MetricsSources, store: &AircraftStore, receiver: &ReceiverStats) -> String {
+    let mut out = String::new();
+
+    let demod = sources.demod.read().clone();
+    single(&mut out, "adsb_preambles_total", "counter", "Mode S preambles detected", demod.preambles);
+    family(&mut out, "adsb_demod_messages_total", "counter", "Mode S messages demodulated, by downlink format");
+    for (df, &n) in demod.messages_by_df.iter().enumerate().filter(|(_, n)| **n > 0) {
+        let _ = writeln!(out, "adsb_demod_messages_total{{df=\"{}\"}} {}", df, n);
+    }
+    single(&mut out, "adsb_mode_ac_total", "counter", "Mode A/C replies demodulated", demod.mode_ac);
+    family(&mut out, "adsb_crc_total", "counter", "Mode S decode results by CRC outcome");
+    for (result, n) in [
+        ("good", demod.crc_good),
+        ("fixed_1bit", demod.crc_fixed_1bit),
+        ("fixed_2bit", demod.crc_fixed_2bit),
+        ("bad", demod.crc_bad),
+    ] {
+        let _ = writeln!(out, "adsb_crc_total{{result=\"{}\"}} {}", result, n);
+    }
+    family(
+        &mut out,
+        "adsb_address_parity_total",
+        "counter",
+        "DF0/4/5/16/20/21 address/parity checks by outcome",
+    );
+    let ap = &demod.address_parity;
+    for (result, n) in [
+        ("exact", ap.exact),
+        ("corrected", ap.corrected),
+        ("ambiguous", ap.ambiguous),
+        ("unknown", ap.unknown),
+    ] {
+        let _ = writeln!(out, "adsb_address_parity_total{{result=\"{}\"}} {}", result, n);
+    }
+    single(
+        &mut out,
+        "adsb_phase_corrected_total",
+        "counter",
+        "Messages recovered by phase correction",
+        demod.phase_corrected,
+    );
+    single(&mut out, "adsb_noise_floor_dbfs", "gauge", "Current noise floor estimate", demod.noise_floor_dbfs);
+    demod.noise.write(&mut out, "adsb_noise_level_dbfs", "Noise floor estimate per sample block");
+    demod.signal.write(&mut out, "adsb_signal_level_dbfs", "Signal level of demodulated messages");
+
+    let tracked = store.all().count();
+    let with_position = store.all().filter(|ac| ac.sources.position.is_some()).count();
+    single(&mut out, "adsb_aircraft_tracked", "gauge", "Aircraft currently tracked", tracked);
+    single(&mut out, "adsb_aircraft_with_position", "gauge", "Tracked aircraft with a position", with_position);
+
+    let total = receiver.total();
+    family(&mut out, "adsb_messages_total", "counter", "Messages accepted, by origin");
+    let _ = writeln!(out, "adsb_messages_total{{origin=\"local\"}} {}", total.local.modes + total.local.modeac);
+    let _ = writeln!(out, "adsb_messages_total{{origin=\"remote\"}} {}", total.remote.modes + total.remote.modeac);
+
+    let net = sources.net.read();
+    let ports: Vec<_> = net.ports().collect();
+    for (name, kind, help, value) in [
+        ("adsb_net_clients", "gauge", "Clients connected, by port", 0),
+        ("adsb_net_connections_total", "counter", "Client connections accepted, by port", 1),
+        ("adsb_net_sent_bytes_total", "counter", "Bytes sent to clients, by port", 2),
+        ("adsb_net_received_bytes_total", "counter", "Bytes received from clients, by port", 3),
+    ] {
+        family(&mut out, name, kind, help);
+        for (port, p) in &ports {
+            let v = [p.clients, p.connections, p.bytes_sent, p.bytes_received][value];
+            let _ = writeln!(out, "{}{{port=\"{}\",service=\"{}\"}} {}", name, port, p.service, v);
+        }
+    }
+
+    family(&mut out, "adsb_channel_backlog", "gauge", "Messages queued between pipeline stages");
+    let _ = writeln!(out, "adsb_channel_backlog{{channel=\"decoder\"}} {}", sources.decoder_queue.len());
+    let _ = writeln!(out, "adsb_channel_backlog{{channel=\"network\"}} {}", sources.net_queue.len());
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::{NetStats, PortCounters};
+    use crate::demodulator::DemodStats;
+    use parking_lot::RwLock;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_histogram_buckets() {
+        let mut h = Histogram::new(&[-10.0, 0.0]);
+        for v in [-20.0, -10.0, -5.0, 3.0] {
+            h.observe(v);
+        }
+        let mut out = String::new();
+        h.write(&mut out, "x", "help");
+        assert!(out.contains("x_bucket{le=\"-10\"} 2\n"));
+        assert!(out.contains("x_bucket{le=\"0\"} 3\n"));
+        assert!(out.contains("x_bucket{le=\"+Inf\"} 4\n"));
+        assert!(out.contains("x_sum -32\nx_count 4\n"));
+    }
+
+    #[test]
+    fn test_render() {
+        let demod: SharedDemodStats = Arc::new(RwLock::new(DemodStats::default()));
+        {
+            let mut d = demod.write();
+            d.messages_by_df[17] = 5;
+            d.crc_fixed_1bit = 2;
+            d.address_parity.corrected = 3;
+            d.signal.observe(-12.0);
+        }
+        let net = NetStats::shared();
+        let counters = PortCounters::register(&net, 30005, "beast_out");
+        counters.connected();
+        counters.sent(1200);
+
+        let (_tx, rx) = crossbeam_channel::unbounded();
+        let (net_queue, _) = broadcast::channel(4);
+        let sources = MetricsSources { demod, net, decoder_queue: rx, net_queue };
+        let text = render(&sources, &AircraftStore::new(60), &ReceiverStats::new(0.0));
+
+        assert!(text.contains("# TYPE adsb_demod_messages_total counter\nadsb_demod_messages_total{df=\"17\"} 5\n"));
+        assert!(!text.contains("df=\"11\""));
+        assert!(text.contains("adsb_crc_total{result=\"fixed_1bit\"} 2\n"));
+        assert!(text.contains("adsb_address_parity_total{result=\"corrected\"} 3\n"));
+        assert!(text.contains("adsb_signal_level_dbfs_bucket{le=\"-10\"} 1\n"));
+        assert!(text.contains("adsb_net_clients{port=\"30005\",service=\"beast_out\"} 1\n"));
+        assert!(text.contains("adsb_net_sent_bytes_total{port=\"30005\",service=\"beast_out\"} 1200\n"));
+        assert!(text.contains("adsb_channel_backlog{channel=\"decoder\"} 0\n"));
+        // Every sample belongs to a declared family
+        for line in text.lines().filter(|l| !l.starts_with('#')) {
+            let name = line.split(['{', ' ']).next().unwrap();
+            let base = name.trim_end_matches("_bucket").trim_end_matches("_sum").trim_end_matches("_count");
+            assert!(text.contains(&format!("# TYPE {} ", base)), "{}", line);
+        }
+    }
+}
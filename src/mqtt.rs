@@ -0,0 +1,538 @@
+//! MQTT publisher
+//!
+//! Publishes aircraft state and events to an MQTT 3.1.1 broker (plain
+//! TCP), for home-automation and alerting setups:
+//!
+//! - `<prefix>/status`: `online` (retained); the broker replaces it with
+//!   `offline` when we go away
+//! - `<prefix>/aircraft/<icao>`: the aircraft in the `aircraft.json`
+//!   schema (retained) when it changes, at most once per `--mqtt-interval`;
+//!   an empty retained message clears it when the aircraft is gone
+//! - `<prefix>/events/new`: an aircraft that just appeared
+//! - `<prefix>/events/emergency`: an aircraft that started squawking
+//!   7500, 7600 or 7700
+//!
+//! Sessions are clean: after a reconnect every aircraft is published again
+//! instead of redelivering what was in flight.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::RwLock;
+use serde_json::{Map, Value};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::net::tcp::OwnedReadHalf;
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+use crate::aircraft::AircraftStore;
+use crate::backoff::Backoff;
+use crate::websocket::{Snapshot, VOLATILE_FIELDS};
+
+const DEFAULT_PORT: u16 = 1883;
+/// How often the store is checked for changes
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// Keep-alive announced to the broker; we ping at half of it
+const KEEP_ALIVE_SECS: u16 = 60;
+/// Time allowed for the broker to accept the connection
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Largest packet accepted from the broker
+const MAX_PACKET_LEN: usize = 64 * 1024;
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const PUBREC: u8 = 0x50;
+/// PUBREL carries fixed flags 0b0010
+const PUBREL: u8 = 0x62;
+const PINGREQ: u8 = 0xC0;
+
+/// Broker connection and publishing options
+#[derive(Clone, PartialEq)]
+pub struct MqttSettings {
+    /// Broker `host[:port]`; nothing is published without one
+    pub broker: Option<String>,
+    /// Topic prefix, without a trailing `/`
+    pub prefix: String,
+    /// QoS of every message: 0, 1 or 2
+    pub qos: u8,
+    pub username: Option<String>,
+    /// Only sent along with a user name, as MQTT 3.1.1 requires
+    pub password: Option<String>,
+    pub client_id: String,
+    /// Minimum seconds between state updates of one aircraft
+    pub interval: u64,
+}
+
+impl Default for MqttSettings {
+    fn default() -> Self {
+        Self {
+            broker: None,
+            prefix: "adsb".to_string(),
+            qos: 0,
+            username: None,
+            password: None,
+            client_id: format!("adsb-rx-{}", std::process::id()),
+            interval: 5,
+        }
+    }
+}
+
+/// Keeps the password out of the configuration log line
+impl fmt::Debug for MqttSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MqttSettings")
+            .field("broker", &self.broker)
+            .field("prefix", &self.prefix)
+            .field("qos", &self.qos)
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| "***"))
+            .field("client_id", &self.client_id)
+            .field("interval", &self.interval)
+            .finish()
+    }
+}
+
+impl MqttSettings {
+    /// Broker address with the default port filled in
+    pub fn broker_addr(&self) -> Option<String> {
+        self.broker.as_ref().map(|broker| {
+            if broker.rsplit_once(':').is_some_and(|(_, port)| port.parse::<u16>().is_ok()) {
+                broker.clone()
+            } else {
+                format!("{}:{}", broker, DEFAULT_PORT)
+            }
+        })
+    }
+
+    fn topic(&self, suffix: &str) -> String {
+        format!("{}/{}", self.prefix.trim_end_matches('/'), suffix)
+    }
+}
+
+/// A packet: type and flags, remaining length, then `body`
+fn packet(kind: u8, body: &[u8]) -> Vec<u8> {
+    let mut out = vec![kind];
+    let mut len = body.len();
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        out.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+    out.extend_from_slice(body);
+    out
+}
+
+/// Length-prefixed string or binary field
+fn put_field(buf: &mut Vec<u8>, data: &[u8]) {
+    buf.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    buf.extend_from_slice(data);
+}
+
+/// CONNECT with a clean session and `offline` on the status topic as will
+fn connect_packet(settings: &MqttSettings) -> Vec<u8> {
+    let mut body = Vec::new();
+    put_field(&mut body, b"MQTT");
+    body.push(4); // protocol level 3.1.1
+    // Clean session, will flag, will QoS, will retain
+    let mut flags = 0x02 | 0x04 | (settings.qos.min(2) << 3) | 0x20;
+    if settings.username.is_some() {
+        flags |= 0x80;
+        if settings.password.is_some() {
+            flags |= 0x40;
+        }
+    }
+    body.push(flags);
+    body.extend_from_slice(&KEEP_ALIVE_SECS.to_be_bytes());
+    put_field(&mut body, settings.client_id.as_bytes());
+    put_field(&mut body, settings.topic("status").as_bytes());
+    put_field(&mut body, b"offline");
+    if let Some(user) = &settings.username {
+        put_field(&mut body, user.as_bytes());
+        if let Some(password) = &settings.password {
+            put_field(&mut body, password.as_bytes());
+        }
+    }
+    packet(CONNECT, &body)
+}
+
+/// PUBLISH; `packet_id` is only sent for QoS 1 and 2
+fn publish_packet(topic: &str, payload: &[u8], qos: u8, retain: bool, packet_id: u16) -> Vec<u8> {
+    let mut body = Vec::with_capacity(topic.len() + payload.len() + 4);
+    put_field(&mut body, topic.as_bytes());
+    if qos > 0 {
+        body.extend_from_slice(&packet_id.to_be_bytes());
+    }
+    body.extend_from_slice(payload);
+    packet(PUBLISH | qos.min(2) << 1 | retain as u8, &body)
+}
+
+fn protocol_error(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Read one packet: the first header byte and the body
+async fn read_packet<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<(u8, Vec<u8>)> {
+    let kind = reader.read_u8().await?;
+    let mut len = 0usize;
+    for i in 0..4 {
+        let byte = reader.read_u8().await?;
+        len |= ((byte & 0x7F) as usize) << (7 * i);
+        if byte & 0x80 == 0 {
+            break;
+        }
+        if i == 3 {
+            return Err(protocol_error("malformed remaining length"));
+        }
+    }
+    if len > MAX_PACKET_LEN {
+        return Err(protocol_error("packet too large"));
+    }
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body).await?;
+    Ok((kind, body))
+}
+
+/// Forward packets from the broker until the connection ends
+async fn read_packets(mut reader: OwnedReadHalf, tx: mpsc::Sender<io::Result<(u8, Vec<u8>)>>) {
+    loop {
+        let packet = read_packet(&mut reader).await;
+        let failed = packet.is_err();
+        if tx.send(packet).await.is_err() || failed {
+            return;
+        }
+    }
+}
+
+/// Why the broker refused a connection, from the CONNACK return code
+fn connack_error(code: u8) -> &'static str {
+    match code {
+        1 => "unacceptable protocol version",
+        2 => "client identifier rejected",
+        3 => "server unavailable",
+        4 => "bad user name or password",
+        5 => "not authorized",
+        _ => "connection refused",
+    }
+}
+
+/// A message for the broker
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outgoing {
+    pub topic: String,
+    pub payload: Vec<u8>,
+    pub retain: bool,
+}
+
+/// What was published per aircraft, to work out what to publish next
+#[derive(Debug)]
+struct AircraftTracker {
+    settings: MqttSettings,
+    /// Last state published per ICAO, and when (`None`: due at once)
+    published: HashMap<String, (Map<String, Value>, Option<Instant>)>,
+    /// Emergency each aircraft is squawking
+    emergencies: HashMap<String, Value>,
+}
+
+/// Whether anything but the volatile fields differs
+fn changed(prev: &Map<String, Value>, ac: &Map<String, Value>) -> bool {
+    ac.iter().any(|(k, v)| !VOLATILE_FIELDS.contains(&k.as_str()) && prev.get(k) != Some(v))
+        || prev.keys().any(|k| !ac.contains_key(k))
+}
+
+impl AircraftTracker {
+    fn new(settings: MqttSettings) -> Self {
+        Self { settings, published: HashMap::new(), emergencies: HashMap::new() }
+    }
+
+    /// Publish every aircraft again on the next poll, e.g. after a reconnect
+    fn resync(&mut self) {
+        for (state, at) in self.published.values_mut() {
+            state.clear();
+            *at = None;
+        }
+    }
+
+    fn message(&self, suffix: &str, ac: &Map<String, Value>, retain: bool) -> Outgoing {
+        Outgoing {
+            topic: self.settings.topic(suffix),
+            payload: serde_json::to_vec(ac).unwrap_or_default(),
+            retain,
+        }
+    }
+
+    /// Messages for the current aircraft list
+    fn poll(&mut self, aircraft: &[Map<String, Value>], now: Instant) -> Vec<Outgoing> {
+        let interval = Duration::from_secs(self.settings.interval);
+        let mut out = Vec::new();
+        let mut current = Vec::with_capacity(aircraft.len());
+        for ac in aircraft {
+            let Some(hex) = ac.get("hex").and_then(Value::as_str) else {
+                continue;
+            };
+            current.push(hex);
+
+            match ac.get("emergency") {
+                Some(emergency) if self.emergencies.get(hex) != Some(emergency) => {
+                    out.push(self.message("events/emergency", ac, false));
+                    self.emergencies.insert(hex.to_string(), emergency.clone());
+                }
+                Some(_) => {}
+                None => {
+                    self.emergencies.remove(hex);
+                }
+            }
+
+            let state_topic = format!("aircraft/{}", hex);
+            match self.published.get(hex) {
+                None => {
+                    out.push(self.message("events/new", ac, false));
+                    out.push(self.message(&state_topic, ac, true));
+                }
+                Some((prev, at)) => {
+                    let due = at.is_none_or(|at| now.saturating_duration_since(at) >= interval);
+                    if !due || !changed(prev, ac) {
+                        continue;
+                    }
+                    out.push(self.message(&state_topic, ac, true));
+                }
+            }
+            self.published.insert(hex.to_string(), (ac.clone(), Some(now)));
+        }
+
+        let removed: Vec<String> =
+            self.published.keys().filter(|hex| !current.contains(&hex.as_str())).cloned().collect();
+        for hex in removed {
+            self.published.remove(&hex);
+            self.emergencies.remove(&hex);
+            out.push(Outgoing { topic: self.settings.topic(&format!("aircraft/{}", hex)), payload: Vec::new(), retain: true });
+        }
+        out
+    }
+}
+
+/// Open a session: TCP connect, CONNECT, and wait for the broker's CONNACK
+async fn connect(addr: &str, settings: &MqttSettings) -> io::Result<TcpStream> {
+    let mut socket = tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(addr))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "connect timed out"))??;
+    socket.set_nodelay(true).ok();
+    socket.write_all(&connect_packet(settings)).await?;
+    let (kind, body) = tokio::time::timeout(CONNECT_TIMEOUT, read_packet(&mut socket))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "no CONNACK from broker"))??;
+    if kind != CONNACK || body.len() != 2 {
+        return Err(protocol_error("expected CONNACK"));
+    }
+    if body[1] != 0 {
+        return Err(io::Error::new(io::ErrorKind::ConnectionRefused, connack_error(body[1])));
+    }
+    Ok(socket)
+}
+
+/// Publish until the connection fails
+async fn serve(
+    socket: TcpStream,
+    settings: &MqttSettings,
+    store: &RwLock<AircraftStore>,
+    tracker: &mut AircraftTracker,
+) -> io::Error {
+    let (reader, mut writer) = socket.into_split();
+    let (tx, mut rx) = mpsc::channel(16);
+    let reader_task = tokio::spawn(read_packets(reader, tx));
+    let keep_alive = Duration::from_secs(KEEP_ALIVE_SECS as u64);
+
+    let result: io::Result<()> = async {
+        let mut packet_id: u16 = 0;
+        let mut next_id = || {
+            packet_id = packet_id.wrapping_add(1).max(1);
+            packet_id
+        };
+        let status = settings.topic("status");
+        writer.write_all(&publish_packet(&status, b"online", settings.qos, true, next_id())).await?;
+
+        let mut poll = tokio::time::interval(POLL_INTERVAL);
+        let mut ping = tokio::time::interval_at(tokio::time::Instant::now() + keep_alive / 2, keep_alive / 2);
+        let mut last_heard = Instant::now();
+        loop {
+            tokio::select! {
+                _ = poll.tick() => {
+                    let snap = Snapshot::capture(&store.read());
+                    for msg in tracker.poll(snap.aircraft(), Instant::now()) {
+                        debug!("MQTT publish {} ({} bytes)", msg.topic, msg.payload.len());
+                        let packet = publish_packet(&msg.topic, &msg.payload, settings.qos, msg.retain, next_id());
+                        writer.write_all(&packet).await?;
+                    }
+                }
+                _ = ping.tick() => {
+                    if last_heard.elapsed() > keep_alive {
+                        return Err(io::Error::new(io::ErrorKind::TimedOut, "broker stopped responding"));
+                    }
+                    writer.write_all(&packet(PINGREQ, &[])).await?;
+                }
+                received = rx.recv() => {
+                    let (kind, body) = received.unwrap_or_else(|| Err(io::ErrorKind::UnexpectedEof.into()))?;
+                    last_heard = Instant::now();
+                    // QoS 2: release each message the broker has received
+                    if kind & 0xF0 == PUBREC && body.len() >= 2 {
+                        writer.write_all(&packet(PUBREL, &body[..2])).await?;
+                    }
+                }
+            }
+        }
+    }
+    .await;
+
+    reader_task.abort();
+    match result {
+        Ok(()) => io::ErrorKind::UnexpectedEof.into(),
+        Err(e) => e,
+    }
+}
+
+/// Publish to the configured broker, reconnecting with backoff, until aborted
+pub async fn run_publisher(settings: MqttSettings, store: Arc<RwLock<AircraftStore>>) {
+    let Some(addr) = settings.broker_addr() else {
+        return;
+    };
+    let mut tracker = AircraftTracker::new(settings.clone());
+    let mut backoff = Backoff::new();
+
+    loop {
+        match connect(&addr, &settings).await {
+            Ok(socket) => {
+                info!("MQTT connected to {} as {}", addr, settings.client_id);
+                backoff.reset();
+                tracker.resync();
+                let error = serve(socket, &settings, &store, &mut tracker).await;
+                warn!("MQTT disconnected from {}: {}", addr, error);
+            }
+            Err(e) => warn!("MQTT cannot connect to {}: {}", addr, e),
+        }
+        backoff.wait().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::decode_hex_message;
+    use serde_json::json;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_packet_encoding() {
+        assert_eq!(packet(PINGREQ, &[]), [0xC0, 0]);
+        assert_eq!(packet(PUBLISH, &[0; 321])[..3], [0x30, 0xC1, 0x02]);
+
+        let settings = MqttSettings {
+            username: Some("u".into()),
+            password: Some("pw".into()),
+            client_id: "id".into(),
+            qos: 1,
+            ..Default::default()
+        };
+        let connect = connect_packet(&settings);
+        assert_eq!(connect[2..10], [0, 4, b'M', b'Q', b'T', b'T', 4, 0x80 | 0x40 | 0x20 | 0x08 | 0x04 | 0x02]);
+        assert!(connect.ends_with(b"\0\x0badsb/status\0\x07offline\0\x01u\0\x02pw"));
+
+        let publish = publish_packet("a/b", b"{}", 1, true, 0x0102);
+        assert_eq!(publish, [0x33, 9, 0, 3, b'a', b'/', b'b', 1, 2, b'{', b'}']);
+        assert_eq!(publish_packet("a", b"", 0, false, 7), [0x30, 3, 0, 1, b'a']);
+
+        assert_eq!(MqttSettings { broker: Some("pi.local".into()), ..Default::default() }.broker_addr().unwrap(), "pi.local:1883");
+        assert_eq!(MqttSettings { broker: Some("[::1]:1884".into()), ..Default::default() }.broker_addr().unwrap(), "[::1]:1884");
+    }
+
+    #[test]
+    fn test_tracker() {
+        let mut tracker = AircraftTracker::new(MqttSettings { interval: 5, ..Default::default() });
+        let t0 = Instant::now();
+        let topics = |out: Vec<Outgoing>| out.into_iter().map(|m| (m.topic, m.retain, m.payload.is_empty())).collect::<Vec<_>>();
+
+        let first = [json!({"hex": "4840d6", "alt_baro": 38000, "seen": 0.1}).as_object().unwrap().clone()];
+        assert_eq!(
+            topics(tracker.poll(&first, t0)),
+            [("adsb/events/new".into(), false, false), ("adsb/aircraft/4840d6".into(), true, false)]
+        );
+
+        // Only a volatile field changed; then a change inside the interval waits
+        let seen = [json!({"hex": "4840d6", "alt_baro": 38000, "seen": 3.0}).as_object().unwrap().clone()];
+        assert!(tracker.poll(&seen, t0 + Duration::from_secs(2)).is_empty());
+        let climbed = [json!({"hex": "4840d6", "alt_baro": 38100, "seen": 0.0}).as_object().unwrap().clone()];
+        assert!(tracker.poll(&climbed, t0 + Duration::from_secs(3)).is_empty());
+        let out = tracker.poll(&climbed, t0 + Duration::from_secs(5));
+        assert_eq!(topics(out.clone()), [("adsb/aircraft/4840d6".into(), true, false)]);
+        assert_eq!(serde_json::from_slice::<Value>(&out[0].payload).unwrap()["alt_baro"], 38100);
+
+        // Emergencies are announced once, whatever the rate limit
+        let emergency = json!({"hex": "4840d6", "alt_baro": 38100, "squawk": "7700", "emergency": "general"});
+        let emergency = [emergency.as_object().unwrap().clone()];
+        assert_eq!(topics(tracker.poll(&emergency, t0 + Duration::from_secs(6))), [("adsb/events/emergency".into(), false, false)]);
+        assert!(tracker.poll(&emergency, t0 + Duration::from_secs(7)).is_empty());
+
+        // A reconnect republishes; a departed aircraft's retained state is cleared
+        tracker.resync();
+        assert_eq!(topics(tracker.poll(&emergency, t0 + Duration::from_secs(8))), [("adsb/aircraft/4840d6".into(), true, false)]);
+        assert_eq!(topics(tracker.poll(&[], t0 + Duration::from_secs(9))), [("adsb/aircraft/4840d6".into(), true, true)]);
+    }
+
+    #[tokio::test]
+    async fn test_publishes_to_broker() {
+        let mut store = AircraftStore::with_min_messages(60, 1);
+        let mm = decode_hex_message("*8D4840D6202CC371C32CE0576098;", true, true, false).unwrap();
+        store.update_from_message(&mm);
+        let store = Arc::new(RwLock::new(store));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let settings = MqttSettings {
+            broker: Some(listener.local_addr().unwrap().to_string()),
+            prefix: "test".into(),
+            qos: 2,
+            username: Some("adsb".into()),
+            ..Default::default()
+        };
+        let publisher = tokio::spawn(run_publisher(settings, Arc::clone(&store)));
+
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let (kind, body) = read_packet(&mut socket).await.unwrap();
+        assert_eq!(kind, CONNECT);
+        assert!(body.ends_with(b"\0\x04adsb"));
+        socket.write_all(&[CONNACK, 2, 0, 0]).await.unwrap();
+
+        let mut published = Vec::new();
+        let mut released = false;
+        while published.len() < 3 || !released {
+            let (kind, body) = tokio::time::timeout(Duration::from_secs(5), read_packet(&mut socket)).await.unwrap().unwrap();
+            match kind {
+                k if k & 0xF0 == PUBLISH => {
+                    assert_eq!(k & 0x06, 2 << 1);
+                    let topic_len = u16::from_be_bytes([body[0], body[1]]) as usize;
+                    let topic = String::from_utf8(body[2..2 + topic_len].to_vec()).unwrap();
+                    let id = &body[2 + topic_len..4 + topic_len];
+                    socket.write_all(&[PUBREC, 2, id[0], id[1]]).await.unwrap();
+                    published.push((topic, k & 1 == 1, body[4 + topic_len..].to_vec()));
+                }
+                PUBREL => released = true,
+                _ => {}
+            }
+        }
+        publisher.abort();
+
+        assert_eq!(published[0], ("test/status".into(), true, b"online".to_vec()));
+        assert_eq!((published[1].0.as_str(), published[1].1), ("test/events/new", false));
+        assert_eq!((published[2].0.as_str(), published[2].1), ("test/aircraft/4840d6", true));
+        let state: Value = serde_json::from_slice(&published[2].2).unwrap();
+        assert_eq!(state["flight"], "KLM1023 ");
+    }
+}
@@ -2,33 +2,142 @@
 //!
 //!  Mirrors the original dump1090 networking approach.
 
-use std::fs;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::pin::Pin;
 use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::SystemTime;
 
 use parking_lot::RwLock;
-use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader, ReadBuf};
 use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::broadcast;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
 use crate::aircraft::AircraftStore;
+use crate::backoff::Backoff;
+use crate::beast::{BeastDecoder, BeastFrame};
 use crate::config::Config;
-use crate::decoder;
+use crate::connector::{self, SharedConnectorStatus};
+use crate::decoder::{self, ApCorrection, AvrFormat, ModesMessage};
+use crate::http::{self, HttpContext};
+use crate::metrics::MetricsSources;
+use crate::readsb::ReceiverJson;
+use crate::sbs::{self, SbsRecord};
+use crate::stats::{MessageOrigin, SharedReceiverStats};
+
+pub const BROADCAST_CAPACITY: usize = 1024;
+
+/// Traffic counters for one listening port
+#[derive(Debug, Clone, Default)]
+pub struct PortStats {
+    pub service: &'static str,
+    /// Clients connected now
+    pub clients: u64,
+    pub connections: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+/// Traffic counters of all listening ports
+#[derive(Debug, Default)]
+pub struct NetStats {
+    ports: BTreeMap<u16, PortStats>,
+}
+
+pub type SharedNetStats = Arc<RwLock<NetStats>>;
+
+impl NetStats {
+    pub fn shared() -> SharedNetStats {
+        Arc::new(RwLock::new(Self::default()))
+    }
+
+    pub fn ports(&self) -> impl Iterator<Item = (u16, &PortStats)> {
+        self.ports.iter().map(|(&port, stats)| (port, stats))
+    }
+}
+
+/// Handle on one port's counters, for its server
+#[derive(Debug, Clone)]
+pub struct PortCounters {
+    stats: SharedNetStats,
+    port: u16,
+}
+
+impl PortCounters {
+    pub fn register(stats: &SharedNetStats, port: u16, service: &'static str) -> Self {
+        stats.write().ports.insert(port, PortStats { service, ..PortStats::default() });
+        Self { stats: Arc::clone(stats), port }
+    }
+
+    fn update(&self, f: impl FnOnce(&mut PortStats)) {
+        if let Some(p) = self.stats.write().ports.get_mut(&self.port) {
+            f(p);
+        }
+    }
+
+    pub fn connected(&self) {
+        self.update(|p| {
+            p.clients += 1;
+            p.connections += 1;
+        });
+    }
+
+    pub fn disconnected(&self) {
+        self.update(|p| p.clients = p.clients.saturating_sub(1));
+    }
+
+    pub fn sent(&self, n: usize) {
+        self.update(|p| p.bytes_sent += n as u64);
+    }
+
+    pub fn received(&self, n: usize) {
+        self.update(|p| p.bytes_received += n as u64);
+    }
+}
+
+/// Counts bytes read from a client into its port's counters
+struct CountingReader<R> {
+    inner: R,
+    counters: PortCounters,
+}
 
-const BROADCAST_CAPACITY: usize = 1024;
+impl<R: AsyncRead + Unpin> AsyncRead for CountingReader<R> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        let before = buf.filled().len();
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+        let n = buf.filled().len() - before;
+        if n > 0 {
+            self.counters.received(n);
+        }
+        result
+    }
+}
 
+/// Run the network servers. `msg_tx` carries every accepted message, from
+/// the demodulator and from network inputs, to the output servers and
+/// outbound connectors (one status per `config.net_connectors` entry).
+/// Network input is counted in `receiver_stats` as remote messages, and
+/// traffic per port in `metrics.net`.
 pub async fn run_servers(
     config: Config,
     aircraft_store: Arc<RwLock<AircraftStore>>,
+    receiver_stats: SharedReceiverStats,
+    msg_tx: broadcast::Sender<ModesMessage>,
+    connector_status: Vec<SharedConnectorStatus>,
+    metrics: MetricsSources,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let (raw_tx, _) = broadcast::channel::<String>(BROADCAST_CAPACITY);
     let (sbs_tx, _) = broadcast::channel::<String>(BROADCAST_CAPACITY);
+    let net_stats = Arc::clone(&metrics.net);
 
     let raw_out_handle = {
-        let tx = raw_tx.clone();
+        let tx = msg_tx.clone();
         let port = config.net_ro_port;
+        let format = config.net_ro_format;
+        let counters = PortCounters::register(&net_stats, port, "raw_out");
         tokio::spawn(async move {
-            if let Err(e) = run_raw_output_server(port, tx).await {
+            if let Err(e) = run_raw_output_server(port, format, tx, counters).await {
                 error!("Raw output server error:  {}", e);
             }
         })
@@ -38,19 +147,109 @@ pub async fn run_servers(
         let port = config.net_ri_port;
         let store = Arc::clone(&aircraft_store);
         let cfg = config.clone();
-        let tx = raw_tx.clone();
+        let stats = Arc::clone(&receiver_stats);
+        let tx = msg_tx.clone();
+        let counters = PortCounters::register(&net_stats, port, "raw_in");
         tokio::spawn(async move {
-            if let Err(e) = run_raw_input_server(port, store, cfg, tx).await {
+            if let Err(e) = run_raw_input_server(port, store, stats, cfg, tx, counters).await {
                 error!("Raw input server error: {}", e);
             }
         })
     };
 
+    let beast_out_handle = {
+        let tx = msg_tx.clone();
+        let port = config.net_bo_port;
+        let counters = PortCounters::register(&net_stats, port, "beast_out");
+        tokio::spawn(async move {
+            if let Err(e) = run_beast_output_server(port, tx, counters).await {
+                error!("Beast output server error: {}", e);
+            }
+        })
+    };
+
+    let beast_in_handle = {
+        let port = config.net_bi_port;
+        let store = Arc::clone(&aircraft_store);
+        let cfg = config.clone();
+        let stats = Arc::clone(&receiver_stats);
+        let tx = msg_tx.clone();
+        let counters = PortCounters::register(&net_stats, port, "beast_in");
+        tokio::spawn(async move {
+            if let Err(e) = run_beast_input_server(port, store, stats, cfg, tx, counters).await {
+                error!("Beast input server error: {}", e);
+            }
+        })
+    };
+
+    for addr in &config.net_beast_connect {
+        let addr = addr.clone();
+        let store = Arc::clone(&aircraft_store);
+        let cfg = config.clone();
+        let stats = Arc::clone(&receiver_stats);
+        let tx = msg_tx.clone();
+        tokio::spawn(run_feed_connector(FeedFormat::Beast, addr, store, stats, cfg, tx));
+    }
+
+    for (spec, status) in config.net_connectors.iter().zip(connector_status) {
+        let rx = msg_tx.subscribe();
+        let store = Arc::clone(&aircraft_store);
+        tokio::spawn(connector::run_connector(spec.clone(), rx, store, status));
+    }
+
+    if config.net_sbs_in_port != 0 {
+        let port = config.net_sbs_in_port;
+        let store = Arc::clone(&aircraft_store);
+        let counters = PortCounters::register(&net_stats, port, "sbs_in");
+        tokio::spawn(async move {
+            if let Err(e) = run_sbs_input_server(port, store, counters).await {
+                error!("SBS input server error: {}", e);
+            }
+        });
+    }
+
+    for addr in &config.net_sbs_connect {
+        let addr = addr.clone();
+        let store = Arc::clone(&aircraft_store);
+        let cfg = config.clone();
+        let stats = Arc::clone(&receiver_stats);
+        let tx = msg_tx.clone();
+        tokio::spawn(run_feed_connector(FeedFormat::Sbs, addr, store, stats, cfg, tx));
+    }
+
+    // Format each message once for all SBS clients
+    let sbs_feed_handle = {
+        let mut rx = msg_tx.subscribe();
+        let tx = sbs_tx.clone();
+        let store = Arc::clone(&aircraft_store);
+        tokio::spawn(async move {
+            loop {
+                match rx.recv().await {
+                    Ok(mm) => {
+                        if mm.is_mode_ac() {
+                            continue;
+                        }
+                        let line = {
+                            let store = store.read();
+                            sbs::format_message(&mm, store.get(mm.icao_address()), SystemTime::now())
+                        };
+                        if let Some(line) = line {
+                            let _ = tx.send(line);
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(_) => break,
+                }
+            }
+        })
+    };
+
     let sbs_handle = {
         let tx = sbs_tx.clone();
         let port = config.net_sbs_port;
+        let counters = PortCounters::register(&net_stats, port, "sbs_out");
         tokio::spawn(async move {
-            if let Err(e) = run_sbs_server(port, tx).await {
+            if let Err(e) = run_sbs_server(port, tx, counters).await {
                 error!("SBS server error:  {}", e);
             }
         })
@@ -58,9 +257,17 @@ pub async fn run_servers(
 
     let http_handle = {
         let port = config.net_http_port;
-        let store = Arc::clone(&aircraft_store);
+        let ctx = HttpContext {
+            store: Arc::clone(&aircraft_store),
+            stats: Arc::clone(&receiver_stats),
+            receiver: ReceiverJson::new(config.receiver_lat, config.receiver_lon),
+            web_root: config.net_http_dir.as_ref().map(PathBuf::from),
+            log_requests: config.debug.net,
+            net: PortCounters::register(&net_stats, port, "http"),
+            metrics,
+        };
         tokio::spawn(async move {
-            if let Err(e) = run_http_server(port, store).await {
+            if let Err(e) = http::run_http_server(port, ctx).await {
                 error!("HTTP server error: {}", e);
             }
         })
@@ -69,7 +276,10 @@ pub async fn run_servers(
     tokio::select! {
         _ = raw_out_handle => {}
         _ = raw_in_handle => {}
+        _ = beast_out_handle => {}
+        _ = beast_in_handle => {}
         _ = sbs_handle => {}
+        _ = sbs_feed_handle => {}
         _ = http_handle => {}
     }
 
@@ -78,7 +288,9 @@ pub async fn run_servers(
 
 async fn run_raw_output_server(
     port: u16,
-    tx: broadcast::Sender<String>,
+    format: AvrFormat,
+    tx: broadcast::Sender<ModesMessage>,
+    counters: PortCounters,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let listener = TcpListener::bind(format!("0.0.0.0:{}", port)).await?;
     info!("Raw output server listening on port {}", port);
@@ -87,24 +299,69 @@ async fn run_raw_output_server(
         let (socket, addr) = listener.accept().await?;
         debug!("Raw output client connected:  {}", addr);
         let mut rx = tx.subscribe();
+        let counters = counters.clone();
+        counters.connected();
 
         tokio::spawn(async move {
             let mut socket = socket;
             loop {
                 match rx.recv().await {
                     Ok(msg) => {
-                        if socket.write_all(msg.as_bytes()).await.is_err() {
+                        let line = msg.to_avr_string(format) + "\n";
+                        if socket.write_all(line.as_bytes()).await.is_err() {
                             break;
                         }
-                        if socket.write_all(b"\n").await.is_err() {
+                        counters.sent(line.len());
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(_) => break,
+                }
+            }
+            counters.disconnected();
+            debug!("Raw output client disconnected: {}", addr);
+        });
+    }
+}
+
+/// Serve messages as Mode-S Beast binary frames (tar1090, mlat-client, VRS)
+async fn run_beast_output_server(
+    port: u16,
+    tx: broadcast::Sender<ModesMessage>,
+    counters: PortCounters,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let listener = TcpListener::bind(format!("0.0.0.0:{}", port)).await?;
+    info!("Beast output server listening on port {}", port);
+
+    loop {
+        let (socket, addr) = listener.accept().await?;
+        debug!("Beast output client connected: {}", addr);
+        socket.set_nodelay(true).ok();
+        let mut rx = tx.subscribe();
+        let counters = counters.clone();
+        counters.connected();
+
+        tokio::spawn(async move {
+            let mut socket = socket;
+            let mut buf = Vec::new();
+            loop {
+                match rx.recv().await {
+                    Ok(msg) => {
+                        let Some(frame) = BeastFrame::from_message(&msg) else {
+                            continue;
+                        };
+                        buf.clear();
+                        frame.encode_into(&mut buf);
+                        if socket.write_all(&buf).await.is_err() {
                             break;
                         }
+                        counters.sent(buf.len());
                     }
                     Err(broadcast::error::RecvError::Lagged(_)) => continue,
                     Err(_) => break,
                 }
             }
-            debug!("Raw output client disconnected: {}", addr);
+            counters.disconnected();
+            debug!("Beast output client disconnected: {}", addr);
         });
     }
 }
@@ -112,8 +369,10 @@ async fn run_raw_output_server(
 async fn run_raw_input_server(
     port: u16,
     store: Arc<RwLock<AircraftStore>>,
+    stats: SharedReceiverStats,
     config: Config,
-    broadcast_tx: broadcast::Sender<String>,
+    broadcast_tx: broadcast::Sender<ModesMessage>,
+    counters: PortCounters,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let listener = TcpListener::bind(format!("0.0.0.0:{}", port)).await?;
     info!("Raw input server listening on port {}", port);
@@ -123,11 +382,14 @@ async fn run_raw_input_server(
         debug!("Raw input client connected: {}", addr);
 
         let store = Arc::clone(&store);
+        let stats = Arc::clone(&stats);
         let config = config.clone();
         let tx = broadcast_tx.clone();
+        let counters = counters.clone();
+        counters.connected();
 
         tokio::spawn(async move {
-            let reader = BufReader::new(socket);
+            let reader = BufReader::new(CountingReader { inner: socket, counters: counters.clone() });
             let mut lines = reader.lines();
 
             while let Ok(Some(line)) = lines.next_line().await {
@@ -136,183 +398,286 @@ async fn run_raw_input_server(
                     continue;
                 }
 
-                if let Some(mm) =
-                    decoder::decode_hex_message(line, config.fix_errors, config.aggressive)
-                {
-                    if mm.crc_ok || !config.check_crc {
-                        {
-                            let mut store = store.write();
-                            store.update_from_message(&mm);
-                        }
-                        let _ = tx.send(mm.to_raw_string());
-                    }
+                if let Some(mm) = decoder::decode_hex_message(
+                    line,
+                    config.fix_errors,
+                    config.aggressive,
+                    config.fix_df,
+                ) {
+                    accept_message(mm, &store, &stats, &config, &tx);
                 }
             }
+            counters.disconnected();
             debug!("Raw input client disconnected: {}", addr);
         });
     }
 }
 
-async fn run_sbs_server(
+/// Address/parity frames carry no checkable CRC: accept one whose
+/// recovered address we already track, or repair a single-bit error
+/// against the tracked addresses
+fn validate_address_parity(mm: &ModesMessage, store: &AircraftStore, config: &Config) -> Option<ModesMessage> {
+    if store.get(mm.icao_address()).is_some() {
+        return Some(ModesMessage { crc_ok: true, ..mm.clone() });
+    }
+    if !config.fix_errors {
+        return None;
+    }
+    match decoder::fix_address_parity(mm, |a| store.get(a).is_some(), config.fix_df) {
+        ApCorrection::Fixed(fixed) => Some(fixed),
+        ApCorrection::Ambiguous | ApCorrection::NotFound => None,
+    }
+}
+
+/// Aggregate a message received from the network and pass it on to the
+/// output servers, unless it fails the CRC check
+fn accept_message(
+    mut mm: ModesMessage,
+    store: &Arc<RwLock<AircraftStore>>,
+    stats: &SharedReceiverStats,
+    config: &Config,
+    tx: &broadcast::Sender<ModesMessage>,
+) {
+    if mm.is_address_parity()
+        && !mm.crc_ok
+        && let Some(valid) = validate_address_parity(&mm, &store.read(), config)
+    {
+        mm = valid;
+    }
+    let accepted = mm.crc_ok || !config.check_crc;
+    stats.write().record(MessageOrigin::Remote, mm.is_mode_ac(), accepted);
+    if !accepted {
+        return;
+    }
+    store.write().update_from_message(&mm);
+    let _ = tx.send(mm);
+}
+
+/// Decode Beast frames from `reader` until it closes or fails
+async fn read_beast_stream<R: AsyncRead + Unpin>(
+    mut reader: R,
+    store: &Arc<RwLock<AircraftStore>>,
+    stats: &SharedReceiverStats,
+    config: &Config,
+    tx: &broadcast::Sender<ModesMessage>,
+) -> std::io::Result<()> {
+    let mut decoder = BeastDecoder::new();
+    let mut buf = vec![0u8; 16 * 1024];
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        decoder.push(&buf[..n]);
+        while let Some(frame) = decoder.next_frame() {
+            accept_message(frame.to_message(config), store, stats, config, tx);
+        }
+    }
+}
+
+/// Accept Beast binary feeds pushed to us (readsb --net-connector ...,beast_out)
+async fn run_beast_input_server(
     port: u16,
-    tx: broadcast::Sender<String>,
+    store: Arc<RwLock<AircraftStore>>,
+    stats: SharedReceiverStats,
+    config: Config,
+    broadcast_tx: broadcast::Sender<ModesMessage>,
+    counters: PortCounters,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let listener = TcpListener::bind(format!("0.0.0.0:{}", port)).await?;
-    info!("SBS server listening on port {}", port);
+    info!("Beast input server listening on port {}", port);
 
     loop {
         let (socket, addr) = listener.accept().await?;
-        debug!("SBS client connected: {}", addr);
-        let mut rx = tx.subscribe();
+        debug!("Beast input client connected: {}", addr);
+
+        let store = Arc::clone(&store);
+        let stats = Arc::clone(&stats);
+        let config = config.clone();
+        let tx = broadcast_tx.clone();
+        let counters = counters.clone();
+        counters.connected();
 
         tokio::spawn(async move {
-            let mut socket = socket;
-            loop {
-                match rx.recv().await {
-                    Ok(msg) => {
-                        if socket.write_all(msg.as_bytes()).await.is_err() {
-                            break;
-                        }
-                        if socket.write_all(b"\n").await.is_err() {
-                            break;
-                        }
-                    }
-                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
-                    Err(_) => break,
-                }
+            let reader = CountingReader { inner: socket, counters: counters.clone() };
+            if let Err(e) = read_beast_stream(reader, &store, &stats, &config, &tx).await {
+                debug!("Beast input client {} error: {}", addr, e);
             }
-            debug!("SBS client disconnected: {}", addr);
+            counters.disconnected();
+            debug!("Beast input client disconnected: {}", addr);
         });
     }
 }
 
-async fn run_http_server(
+/// Decode SBS/BaseStation lines from `reader` into the store until it
+/// closes or fails
+async fn read_sbs_stream<R: AsyncRead + Unpin>(
+    reader: R,
+    store: &Arc<RwLock<AircraftStore>>,
+) -> std::io::Result<()> {
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        if let Some(rec) = SbsRecord::parse(&line) {
+            store.write().update_from_sbs(&rec);
+        }
+    }
+    Ok(())
+}
+
+/// Accept SBS/BaseStation feeds pushed to us
+async fn run_sbs_input_server(
     port: u16,
     store: Arc<RwLock<AircraftStore>>,
+    counters: PortCounters,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let listener = TcpListener::bind(format!("0.0.0.0:{}", port)).await?;
-    info!("HTTP server listening on port {}", port);
+    info!("SBS input server listening on port {}", port);
 
     loop {
         let (socket, addr) = listener.accept().await?;
-        debug!("HTTP client connected: {}", addr);
-
+        debug!("SBS input client connected: {}", addr);
         let store = Arc::clone(&store);
+        let counters = counters.clone();
+        counters.connected();
 
         tokio::spawn(async move {
-            if let Err(e) = handle_http_request(socket, store).await {
-                debug!("HTTP error: {}", e);
+            let reader = CountingReader { inner: socket, counters: counters.clone() };
+            if let Err(e) = read_sbs_stream(reader, &store).await {
+                debug!("SBS input client {} error: {}", addr, e);
             }
+            counters.disconnected();
+            debug!("SBS input client disconnected: {}", addr);
         });
     }
 }
 
-/// Handle HTTP request exactly like original dump1090
-async fn handle_http_request(
-    mut socket: TcpStream,
-    store: Arc<RwLock<AircraftStore>>,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let mut buffer = vec![0u8; 8192];
-    let n = socket.read(&mut buffer).await?;
+/// Formats we can read from a remote receiver
+#[derive(Debug, Clone, Copy)]
+enum FeedFormat {
+    Beast,
+    Sbs,
+}
 
-    if n == 0 {
-        return Ok(());
+impl FeedFormat {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Beast => "Beast",
+            Self::Sbs => "SBS",
+        }
     }
+}
 
-    let request = String::from_utf8_lossy(&buffer[..n]);
-
-    // Parse HTTP request
-    let first_line = request.lines().next().unwrap_or("");
-    let parts: Vec<&str> = first_line.split_whitespace().collect();
-
-    if parts.len() < 2 {
-        return Ok(());
+/// Read a remote feed, reconnecting with exponential backoff
+async fn run_feed_connector(
+    format: FeedFormat,
+    addr: String,
+    store: Arc<RwLock<AircraftStore>>,
+    stats: SharedReceiverStats,
+    config: Config,
+    tx: broadcast::Sender<ModesMessage>,
+) {
+    let name = format.name();
+    let mut backoff = Backoff::new();
+    loop {
+        match TcpStream::connect(&addr).await {
+            Ok(socket) => {
+                info!("Connected to {} feed {}", name, addr);
+                backoff.reset();
+                let result = match format {
+                    FeedFormat::Beast => read_beast_stream(socket, &store, &stats, &config, &tx).await,
+                    FeedFormat::Sbs => read_sbs_stream(socket, &store).await,
+                };
+                match result {
+                    Ok(()) => warn!("{} feed {} closed the connection", name, addr),
+                    Err(e) => warn!("{} feed {} error: {}", name, addr, e),
+                }
+            }
+            Err(e) => warn!("Cannot connect to {} feed {}: {}", name, addr, e),
+        }
+        let delay = backoff.next_delay();
+        debug!("Reconnecting to {} in {:?}", addr, delay);
+        tokio::time::sleep(delay).await;
     }
+}
 
-    let url = parts[1];
-
-    // Check HTTP version for keep-alive
-    let http_version = if first_line.contains("HTTP/1.1") {
-        11
-    } else {
-        10
-    };
+async fn run_sbs_server(
+    port: u16,
+    tx: broadcast::Sender<String>,
+    counters: PortCounters,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let listener = TcpListener::bind(format!("0.0.0.0:{}", port)).await?;
+    info!("SBS server listening on port {}", port);
 
-    let keepalive = if http_version == 10 {
-        request.to_lowercase().contains("connection: keep-alive")
-    } else {
-        !request.to_lowercase().contains("connection: close")
-    };
+    loop {
+        let (socket, addr) = listener.accept().await?;
+        debug!("SBS client connected: {}", addr);
+        let mut rx = tx.subscribe();
+        let counters = counters.clone();
+        counters.connected();
 
-    // Serve content based on URL (matching original dump1090 behavior)
-    let (content_type, content) = if url.contains("/data.json") {
-        // Return aircraft data as JSON
-        let json = aircrafts_to_json(&store);
-        ("application/json;charset=utf-8", json)
-    } else {
-        // Serve gmap. html
-        match fs::read_to_string("gmap.html") {
-            Ok(html) => ("text/html;charset=utf-8", html),
-            Err(e) => {
-                let error_msg = format!("Error opening HTML file: {}", e);
-                ("text/html;charset=utf-8", error_msg)
+        tokio::spawn(async move {
+            let mut socket = socket;
+            loop {
+                match rx.recv().await {
+                    Ok(msg) => {
+                        let line = msg + "\r\n";
+                        if socket.write_all(line.as_bytes()).await.is_err() {
+                            break;
+                        }
+                        counters.sent(line.len());
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(_) => break,
+                }
             }
-        }
-    };
-
-    // Build response exactly like original dump1090
-    let header = format!(
-        "HTTP/1.1 200 OK\r\n\
-         Server:  Dump1090\r\n\
-         Content-Type: {}\r\n\
-         Connection: {}\r\n\
-         Content-Length:  {}\r\n\
-         Access-Control-Allow-Origin:  *\r\n\
-         \r\n",
-        content_type,
-        if keepalive { "keep-alive" } else { "close" },
-        content.len()
-    );
-
-    socket.write_all(header.as_bytes()).await?;
-    socket.write_all(content.as_bytes()).await?;
-
-    Ok(())
+            counters.disconnected();
+            debug!("SBS client disconnected: {}", addr);
+        });
+    }
 }
 
-/// Generate JSON for aircraft data - matches original dump1090 format exactly
-fn aircrafts_to_json(store: &Arc<RwLock<AircraftStore>>) -> String {
-    let store = store.read();
-    let aircraft: Vec<_> = store.all().collect();
-
-    if aircraft.is_empty() {
-        return "[\n]\n".to_string();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beast::BeastFrameType;
+    use crate::crc;
+    use crate::stats::ReceiverStats;
+
+    /// Beast stream with a DF17 identification for 4840D6, then a DF4
+    /// altitude reply (38000 ft) whose parity is overlaid with `ap_addr`
+    fn beast_stream(ap_addr: u32) -> Vec<u8> {
+        let df17 = decoder::decode_hex_message("*8D4840D6202CC371C32CE0576098;", false, false, false).unwrap();
+        let mut df4 = [0x20, 0x00, 0x18, 0x38, 0, 0, 0];
+        let parity = crc::address_parity(&df4, 56, ap_addr);
+        crc::write_parity_field(&mut df4, 56, parity);
+
+        let mut stream = BeastFrame::from_message(&df17).unwrap().encode();
+        let frame = BeastFrame { frame_type: BeastFrameType::Short, timestamp: 0, signal: 100, payload: df4.to_vec() };
+        stream.extend(frame.encode());
+        stream
     }
 
-    let mut json = String::from("[\n");
+    async fn feed(stream: &[u8]) -> AircraftStore {
+        let store = Arc::new(RwLock::new(AircraftStore::new(60)));
+        let (tx, _rx) = broadcast::channel(16);
+        read_beast_stream(stream, &store, &ReceiverStats::shared(), &Config::default(), &tx)
+            .await
+            .unwrap();
+        Arc::into_inner(store).unwrap().into_inner()
+    }
 
-    let mut first = true;
-    for ac in aircraft {
-        // Only include aircraft with position (matching original behavior)
-        if ac.lat != 0.0 && ac.lon != 0.0 {
-            if !first {
-                json.push_str(",\n");
-            }
-            first = false;
-
-            json.push_str(&format!(
-                "{{\"hex\":\"{}\", \"flight\":\"{}\", \"lat\":{}, \"lon\":{}, \"altitude\":{}, \"track\":{}, \"speed\": {}}}",
-                ac.hex_addr,
-                ac.flight. trim(),
-                ac.lat,
-                ac.lon,
-                ac.altitude,
-                ac.track,
-                ac.speed
-            ));
-        }
+    #[tokio::test]
+    async fn test_beast_address_parity_known_aircraft() {
+        let store = feed(&beast_stream(0x4840D6)).await;
+        let aircraft = store.get(0x4840D6).unwrap();
+        assert_eq!(aircraft.messages, 2);
+        assert_eq!(aircraft.altitude, 38000);
     }
 
-    json.push_str("\n]\n");
-    json
+    #[tokio::test]
+    async fn test_beast_address_parity_unknown_aircraft() {
+        let store = feed(&beast_stream(0x3C6586)).await;
+        assert_eq!(store.get(0x4840D6).unwrap().messages, 1);
+        assert!(store.get(0x3C6586).is_none());
+    }
 }
@@ -0,0 +1,307 @@
+//! readsb/tar1090-compatible JSON documents
+//!
+//! `aircraft.json` and `receiver.json` in the schema readsb writes to
+//! `/run/readsb`, so tar1090 and other readsb consumers can be pointed at
+//! our `/data/` URL unmodified. Fields we have no data for are left out,
+//! as readsb does.
+
+use std::time::Instant;
+
+use serde::Serialize;
+
+use crate::aircraft::{Aircraft, AircraftStore, ModeAcCode};
+use crate::decoder::NavModes;
+use crate::sample_format::MAG_FULL_SCALE;
+
+/// Rounded to one decimal, as readsb prints most values
+fn round1(v: f64) -> f64 {
+    (v * 10.0).round() / 10.0
+}
+
+/// One entry of `aircraft.json`
+#[derive(Debug, Serialize)]
+pub struct AircraftJson {
+    pub hex: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flight: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alt_baro: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alt_geom: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gs: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ias: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tas: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mach: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub track: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub roll: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mag_heading: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub baro_rate: Option<i16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub geom_rate: Option<i16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub squawk: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub emergency: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nav_qnh: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nav_altitude_mcp: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nav_altitude_fms: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nav_modes: Option<Vec<&'static str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lat: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lon: Option<f64>,
+    /// Seconds since the last position
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seen_pos: Option<f64>,
+    pub messages: u64,
+    /// Mode A/C replies attributed to this aircraft by squawk
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode_ac: Option<u64>,
+    /// Seconds since the last message
+    pub seen: f64,
+    /// Average signal level in dBFS
+    pub rssi: f64,
+}
+
+/// Emergency state as readsb names it, from the squawk
+fn emergency(squawk: u16) -> Option<&'static str> {
+    match squawk {
+        7500 => Some("unlawful"),
+        7600 => Some("nordo"),
+        7700 => Some("general"),
+        _ => None,
+    }
+}
+
+/// Active autopilot modes under readsb's names
+fn nav_modes(modes: NavModes) -> Vec<&'static str> {
+    [(modes.vnav, "vnav"), (modes.alt_hold, "althold"), (modes.approach, "approach")]
+        .into_iter()
+        .filter_map(|(on, name)| on.then_some(name))
+        .collect()
+}
+
+/// Signal level (average magnitude) in dBFS; -49.5 when nothing was
+/// measured, the floor readsb reports
+pub fn rssi_dbfs(signal_level: u16) -> f64 {
+    if signal_level == 0 {
+        return -49.5;
+    }
+    round1((20.0 * (signal_level as f64 / MAG_FULL_SCALE as f64).log10()).max(-49.5))
+}
+
+impl AircraftJson {
+    pub fn from_aircraft(ac: &Aircraft, now: Instant) -> Self {
+        let age = |t: Instant| round1(now.saturating_duration_since(t).as_secs_f64());
+        let has_position = ac.sources.position.is_some();
+        let has_velocity = ac.sources.velocity.is_some();
+        Self {
+            hex: ac.hex_addr.to_lowercase(),
+            flight: Some(ac.flight.trim()).filter(|f| !f.is_empty()).map(|f| format!("{:<8}", f)),
+            alt_baro: ac.sources.altitude.map(|_| ac.altitude),
+            alt_geom: ac.geom_altitude,
+            gs: has_velocity.then_some(ac.speed as f64),
+            ias: ac.indicated_airspeed,
+            tas: ac.true_airspeed,
+            mach: ac.mach.map(|m| (m as f64 * 1000.0).round() / 1000.0),
+            track: has_velocity.then_some(ac.track as f64),
+            roll: ac.roll_angle.map(|r| round1(r as f64)),
+            mag_heading: ac.magnetic_heading.map(|h| round1(h as f64)),
+            baro_rate: ac.baro_altitude_rate,
+            geom_rate: ac.geom_altitude_rate,
+            squawk: (ac.squawk != 0).then(|| format!("{:04}", ac.squawk)),
+            emergency: emergency(ac.squawk),
+            category: ac.category.map(|c| format!("{:02X}", c)),
+            nav_qnh: ac.baro_setting.map(|q| round1(q as f64)),
+            nav_altitude_mcp: ac.selected_altitude,
+            nav_altitude_fms: ac.fms_altitude,
+            nav_modes: ac.nav_modes.map(nav_modes),
+            lat: has_position.then_some((ac.lat * 1e6).round() / 1e6),
+            lon: has_position.then_some((ac.lon * 1e6).round() / 1e6),
+            seen_pos: ac.sources.position.map(|p| age(p.at)),
+            messages: ac.messages + ac.sbs_messages,
+            mode_ac: (ac.mode_ac_messages > 0).then_some(ac.mode_ac_messages),
+            seen: age(ac.seen),
+            rssi: rssi_dbfs(ac.signal_level),
+        }
+    }
+}
+
+/// A Mode A/C code no single Mode S aircraft is squawking, in the
+/// `mode_ac` array of `aircraft.json`
+#[derive(Debug, Serialize)]
+pub struct ModeAcJson {
+    pub squawk: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alt_baro: Option<i32>,
+    pub messages: u64,
+    /// Seconds since the last reply
+    pub seen: f64,
+}
+
+impl ModeAcJson {
+    pub fn from_code(code: &ModeAcCode, now: Instant) -> Self {
+        Self {
+            squawk: format!("{:04}", code.squawk),
+            alt_baro: (code.altitude != 0).then_some(code.altitude),
+            messages: code.messages,
+            seen: round1(now.saturating_duration_since(code.seen).as_secs_f64()),
+        }
+    }
+}
+
+/// The `aircraft.json` document
+#[derive(Debug, Serialize)]
+pub struct AircraftFile {
+    /// Unix time of the snapshot
+    pub now: f64,
+    /// Mode S messages processed since start
+    pub messages: u64,
+    pub aircraft: Vec<AircraftJson>,
+    /// Unattributed Mode A/C codes (not in readsb's schema)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub mode_ac: Vec<ModeAcJson>,
+}
+
+impl AircraftFile {
+    pub fn from_store(store: &AircraftStore, now: f64) -> Self {
+        let instant = Instant::now();
+        let mut aircraft: Vec<AircraftJson> =
+            store.all().map(|ac| AircraftJson::from_aircraft(ac, instant)).collect();
+        aircraft.sort_by(|a, b| a.hex.cmp(&b.hex));
+        let mut mode_ac: Vec<ModeAcJson> = store
+            .mode_ac_codes()
+            .filter(|code| code.addr.is_none())
+            .map(|code| ModeAcJson::from_code(code, instant))
+            .collect();
+        mode_ac.sort_by(|a, b| a.squawk.cmp(&b.squawk));
+        Self { now: round1(now), messages: store.messages(), aircraft, mode_ac }
+    }
+}
+
+/// The `receiver.json` document
+#[derive(Debug, Serialize)]
+pub struct ReceiverJson {
+    pub version: String,
+    /// How often `aircraft.json` changes, in milliseconds
+    pub refresh: u32,
+    /// History files available (we keep none)
+    pub history: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lat: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lon: Option<f64>,
+}
+
+impl ReceiverJson {
+    pub fn new(lat: Option<f64>, lon: Option<f64>) -> Self {
+        Self {
+            version: format!("adsb-rx {}", env!("CARGO_PKG_VERSION")),
+            refresh: 1000,
+            history: 0,
+            lat,
+            lon,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crc;
+    use crate::decoder::{decode_hex_message, decode_mode_ac, decode_modes_message};
+    use serde_json::Value;
+
+    #[test]
+    fn test_aircraft_json_fields() {
+        let mut store = AircraftStore::with_min_messages(60, 1);
+        for hex in [
+            "*8D4840D6202CC371C32CE0576098;",
+            "*8D4840D658C382D690C8AC2863A7;",
+            "*8D4840D6994409940838175B284F;",
+        ] {
+            let mut mm = decode_hex_message(hex, true, true, false).unwrap();
+            mm.signal_level = 4608;
+            store.update_from_message(&mm);
+        }
+
+        // DF20 at 38000 ft with BDS 4,0: MCP and FMS 3008 ft, QNH 1020 hPa,
+        // VNAV and altitude hold
+        let mut msg = [0xA0, 0x00, 0x18, 0x38, 0x85, 0xE4, 0x2F, 0x31, 0x30, 0x01, 0xC0, 0, 0, 0];
+        let parity = crc::address_parity(&msg, 112, 0x4840D6);
+        crc::write_parity_field(&mut msg, 112, parity);
+        let mut mm = decode_modes_message(&msg, false, false, false);
+        mm.signal_level = 4608;
+        store.update_from_message(&mm);
+
+        let file = AircraftFile::from_store(&store, 1_760_000_000.04);
+        let v: Value = serde_json::to_value(&file).unwrap();
+        assert_eq!(v["now"], 1_760_000_000.0);
+        assert_eq!(v["messages"], 4);
+        let ac = &v["aircraft"][0];
+        assert_eq!(ac["hex"], "4840d6");
+        assert_eq!(ac["flight"], "KLM1023 ");
+        assert_eq!(ac["category"], "A0");
+        assert_eq!(ac["alt_baro"], 38000);
+        // Baro plus the velocity message's GNSS-baro difference of 550 ft
+        assert_eq!(ac["alt_geom"], 38550);
+        assert_eq!(ac["gs"], 159.0);
+        assert_eq!(ac["geom_rate"], -832);
+        assert_eq!(ac["rssi"], -20.0);
+        assert_eq!(ac["nav_altitude_mcp"], 3008);
+        assert_eq!(ac["nav_altitude_fms"], 3008);
+        assert_eq!(ac["nav_qnh"], 1020.0);
+        assert_eq!(ac["nav_modes"], serde_json::json!(["vnav", "althold"]));
+        assert_eq!(ac["messages"], 4);
+        // One CPR frame gives no position; no Mode S altitude rate was sent
+        for missing in ["lat", "lon", "seen_pos", "baro_rate", "squawk", "mode_ac"] {
+            assert!(ac.get(missing).is_none(), "{missing}");
+        }
+        assert!(v.get("mode_ac").is_none());
+    }
+
+    #[test]
+    fn test_aircraft_json_mode_ac() {
+        let mut store = AircraftStore::with_min_messages(60, 1);
+        // DF5 squawk 1200 from 4840D6, then 1200 twice and 7700 once on Mode A/C
+        let mut msg = [0x28, 0x00, 0x08, 0x08, 0, 0, 0];
+        let parity = crc::address_parity(&msg, 56, 0x4840D6);
+        crc::write_parity_field(&mut msg, 56, parity);
+        let mut mm = decode_modes_message(&msg, false, false, false);
+        mm.crc_ok = true;
+        store.update_from_message(&mm);
+        for code in [0x1200, 0x1200, 0x7700] {
+            store.update_from_message(&decode_mode_ac(code));
+        }
+
+        let v: Value = serde_json::to_value(AircraftFile::from_store(&store, 0.0)).unwrap();
+        assert_eq!(v["aircraft"][0]["squawk"], "1200");
+        assert_eq!(v["aircraft"][0]["mode_ac"], 2);
+        assert_eq!(v["mode_ac"].as_array().unwrap().len(), 1);
+        assert_eq!(v["mode_ac"][0]["squawk"], "7700");
+        assert_eq!(v["mode_ac"][0]["messages"], 1);
+    }
+
+    #[test]
+    fn test_receiver_json() {
+        let v = serde_json::to_value(ReceiverJson::new(Some(6.9271), Some(79.8612))).unwrap();
+        assert_eq!(v["lat"], 6.9271);
+        assert_eq!(v["refresh"], 1000);
+        assert!(v["version"].as_str().unwrap().starts_with("adsb-rx "));
+        assert!(serde_json::to_value(ReceiverJson::new(None, None)).unwrap().get("lat").is_none());
+    }
+}
@@ -0,0 +1,394 @@
+//! Raw I/Q recording
+//!
+//! Tees the sample stream from the SDR capture loop to disk. Files rotate
+//! by size or duration and can carry SigMF metadata. In trigger mode a
+//! rolling pre-buffer is kept in memory and only written out, followed by
+//! a post-trigger tail, when an event (emergency squawk, watched ICAO)
+//! fires.
+
+use std::collections::{HashSet, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crossbeam_channel::Receiver;
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::date::iso8601_utc;
+use crate::decoder::ModesMessage;
+use crate::sample_format::SampleFormat;
+use crate::sigmf;
+
+/// Recorder settings, taken from the command line
+#[derive(Debug, Clone)]
+pub struct RecorderConfig {
+    /// Directory recordings are written to
+    pub dir: PathBuf,
+    /// Rotate after this many bytes (0 = never)
+    pub max_bytes: u64,
+    /// Rotate after this many seconds of samples (0 = never)
+    pub max_seconds: u64,
+    /// Write a `.sigmf-meta` next to each recording
+    pub sigmf: bool,
+    /// Only record around trigger events
+    pub triggered: bool,
+    /// Seconds of samples kept from before a trigger
+    pub pre_seconds: u64,
+    /// Seconds of samples recorded after the last trigger
+    pub post_seconds: u64,
+}
+
+impl RecorderConfig {
+    /// Build the recorder settings, or `None` if recording is disabled
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let dir = config.record_dir.as_ref()?;
+        Some(Self {
+            dir: PathBuf::from(dir),
+            max_bytes: config.record_max_size_mb * 1024 * 1024,
+            max_seconds: config.record_max_time,
+            sigmf: config.record_sigmf,
+            triggered: config.record_trigger.is_active(),
+            pre_seconds: config.record_pre,
+            post_seconds: config.record_post,
+        })
+    }
+}
+
+/// Events that start a triggered recording
+#[derive(Debug, Clone, Default)]
+pub struct RecordTrigger {
+    /// Fire on squawk 7500, 7600 or 7700
+    pub emergency: bool,
+    /// Fire on any message from these addresses
+    pub icaos: HashSet<u32>,
+}
+
+impl RecordTrigger {
+    /// Whether any trigger condition is configured
+    pub fn is_active(&self) -> bool {
+        self.emergency || !self.icaos.is_empty()
+    }
+
+    /// Add a trigger from its command line form: `emergency` or a hex ICAO
+    pub fn add(&mut self, spec: &str) -> Result<(), String> {
+        for item in spec.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            if item.eq_ignore_ascii_case("emergency") {
+                self.emergency = true;
+            } else {
+                let addr = u32::from_str_radix(item, 16)
+                    .ok()
+                    .filter(|&a| a <= 0xffffff)
+                    .ok_or_else(|| format!("invalid trigger: {}", item))?;
+                self.icaos.insert(addr);
+            }
+        }
+        Ok(())
+    }
+
+    /// Check a decoded message, returning a description of the event if
+    /// it should trigger a recording
+    pub fn check(&self, mm: &ModesMessage) -> Option<String> {
+        if !mm.crc_ok {
+            return None;
+        }
+        if self.emergency && matches!(mm.identity, 7500 | 7600 | 7700) {
+            return Some(format!("squawk {:04}", mm.identity));
+        }
+        if !mm.is_mode_ac() && self.icaos.contains(&mm.icao_address()) {
+            return Some(format!("ICAO {:06X}", mm.icao_address()));
+        }
+        None
+    }
+}
+
+/// An open recording file
+struct Recording {
+    writer: BufWriter<File>,
+    path: PathBuf,
+    bytes: u64,
+}
+
+/// Writes the I/Q stream to rotating files
+pub struct IqRecorder {
+    config: RecorderConfig,
+    format: SampleFormat,
+    sample_rate: u32,
+    freq: u32,
+    device: String,
+    gain_db: Option<f64>,
+    current: Option<Recording>,
+    /// Recent chunks kept for the pre-trigger window
+    prebuffer: VecDeque<Vec<u8>>,
+    prebuffer_bytes: u64,
+    /// Bytes still to record after the last trigger
+    post_remaining: u64,
+    triggers: Option<Receiver<String>>,
+    sequence: u32,
+}
+
+impl IqRecorder {
+    /// Create a recorder for a stream of `format` samples at `sample_rate`.
+    ///
+    /// `triggers` carries event descriptions in trigger mode.
+    pub fn new(
+        config: RecorderConfig,
+        format: SampleFormat,
+        sample_rate: u32,
+        freq: u32,
+        device: &str,
+        triggers: Option<Receiver<String>>,
+    ) -> io::Result<Self> {
+        fs::create_dir_all(&config.dir)?;
+        Ok(Self {
+            config,
+            format,
+            sample_rate,
+            freq,
+            device: device.to_string(),
+            gain_db: None,
+            current: None,
+            prebuffer: VecDeque::new(),
+            prebuffer_bytes: 0,
+            post_remaining: 0,
+            triggers,
+            sequence: 0,
+        })
+    }
+
+    /// Record a gain change. An open file is rotated so its metadata
+    /// stays accurate.
+    pub fn set_gain(&mut self, gain_db: f64) -> io::Result<()> {
+        self.gain_db = Some(gain_db);
+        if self.current.is_some() {
+            self.close()?;
+            self.open()?;
+        }
+        Ok(())
+    }
+
+    /// Path of the file currently being written
+    #[allow(dead_code)]
+    pub fn current_path(&self) -> Option<&PathBuf> {
+        self.current.as_ref().map(|r| &r.path)
+    }
+
+    /// Bytes per second of sample data
+    fn byte_rate(&self) -> u64 {
+        self.sample_rate as u64 * self.format.bytes_per_sample() as u64
+    }
+
+    /// Feed the next chunk of raw samples
+    pub fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        if !self.config.triggered {
+            return self.write_rotating(data);
+        }
+
+        let mut fired = None;
+        if let Some(rx) = &self.triggers {
+            for event in rx.try_iter() {
+                fired = Some(event);
+            }
+        }
+
+        if let Some(event) = fired {
+            if self.current.is_none() {
+                info!("Recording triggered by {}", event);
+                self.open()?;
+                while let Some(chunk) = self.prebuffer.pop_front() {
+                    self.write_rotating(&chunk)?;
+                }
+                self.prebuffer_bytes = 0;
+            }
+            self.post_remaining = self.config.post_seconds * self.byte_rate();
+        }
+
+        if self.current.is_some() {
+            self.write_rotating(data)?;
+            self.post_remaining = self.post_remaining.saturating_sub(data.len() as u64);
+            if self.post_remaining == 0 {
+                self.close()?;
+            }
+            return Ok(());
+        }
+
+        // Idle: keep the pre-trigger window in memory
+        self.prebuffer.push_back(data.to_vec());
+        self.prebuffer_bytes += data.len() as u64;
+        let limit = self.config.pre_seconds * self.byte_rate();
+        while self.prebuffer_bytes > limit
+            && let Some(old) = self.prebuffer.pop_front()
+        {
+            self.prebuffer_bytes -= old.len() as u64;
+        }
+        Ok(())
+    }
+
+    /// Append to the current file, opening or rotating as needed
+    fn write_rotating(&mut self, data: &[u8]) -> io::Result<()> {
+        let max_bytes = [self.config.max_bytes, self.config.max_seconds * self.byte_rate()]
+            .into_iter()
+            .filter(|&b| b > 0)
+            .min();
+
+        if let Some(max) = max_bytes
+            && self.current.as_ref().is_some_and(|r| r.bytes >= max)
+        {
+            self.close()?;
+        }
+        if self.current.is_none() {
+            self.open()?;
+        }
+
+        let recording = self.current.as_mut().expect("recording is open");
+        recording.writer.write_all(data)?;
+        recording.bytes += data.len() as u64;
+        Ok(())
+    }
+
+    fn open(&mut self) -> io::Result<()> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let stem = format!("iq-{}-{:04}", now.as_secs(), self.sequence);
+        self.sequence += 1;
+
+        let ext = if self.config.sigmf { "sigmf-data".to_string() } else { self.format.to_string() };
+        let path = self.config.dir.join(format!("{}.{}", stem, ext));
+
+        if self.config.sigmf {
+            let hw = match self.gain_db {
+                Some(gain) => format!("{}, gain {:.1} dB", self.device, gain),
+                None => self.device.clone(),
+            };
+            sigmf::write_meta(
+                &path.with_extension("sigmf-meta"),
+                self.format,
+                self.sample_rate,
+                self.freq,
+                &hw,
+                &iso8601_utc(now.as_secs()),
+            )?;
+        }
+
+        info!("Recording I/Q to {}", path.display());
+        self.current = Some(Recording {
+            writer: BufWriter::new(File::create(&path)?),
+            path,
+            bytes: 0,
+        });
+        Ok(())
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        if let Some(mut recording) = self.current.take() {
+            recording.writer.flush()?;
+            info!("Closed recording {} ({} bytes)", recording.path.display(), recording.bytes);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for IqRecorder {
+    fn drop(&mut self) {
+        if let Err(e) = self.close() {
+            warn!("Failed to close recording: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("adsb-rec-{}-{}", name, std::process::id()))
+    }
+
+    fn test_config(dir: &Path) -> RecorderConfig {
+        RecorderConfig {
+            dir: dir.to_path_buf(),
+            max_bytes: 0,
+            max_seconds: 0,
+            sigmf: false,
+            triggered: false,
+            pre_seconds: 0,
+            post_seconds: 0,
+        }
+    }
+
+    fn files(dir: &Path, ext: &str) -> Vec<PathBuf> {
+        let mut files: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| p.extension().is_some_and(|e| e == ext))
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn test_rotation_by_size() {
+        let dir = temp_dir("rotate");
+        let mut config = test_config(&dir);
+        config.max_bytes = 1000;
+        config.sigmf = true;
+
+        let mut rec = IqRecorder::new(config, SampleFormat::Cu8, 2_000_000, 1_090_000_000, "RTL-SDR", None).unwrap();
+        for _ in 0..5 {
+            rec.write(&[127u8; 400]).unwrap();
+        }
+        drop(rec);
+
+        let data = files(&dir, "sigmf-data");
+        let sizes: Vec<u64> = data.iter().map(|p| fs::metadata(p).unwrap().len()).collect();
+        assert_eq!(sizes, vec![1200, 800]);
+        assert_eq!(files(&dir, "sigmf-meta").len(), 2);
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_trigger_flushes_prebuffer() {
+        let dir = temp_dir("trigger");
+        let mut config = test_config(&dir);
+        config.triggered = true;
+        config.pre_seconds = 1;
+        config.post_seconds = 1;
+
+        // 100 samples/s of cu8 = 200 bytes/s
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let mut rec = IqRecorder::new(config, SampleFormat::Cu8, 100, 1_090_000_000, "RTL-SDR", Some(rx)).unwrap();
+        for _ in 0..10 {
+            rec.write(&[1u8; 100]).unwrap();
+        }
+        assert!(rec.current_path().is_none());
+
+        tx.send("squawk 7700".to_string()).unwrap();
+        for _ in 0..5 {
+            rec.write(&[2u8; 100]).unwrap();
+        }
+        assert!(rec.current_path().is_none());
+        drop(rec);
+
+        let data = files(&dir, "cu8");
+        assert_eq!(data.len(), 1);
+        let content = fs::read(&data[0]).unwrap();
+        // 1 s of pre-buffer followed by 1 s after the trigger
+        assert_eq!(content.len(), 400);
+        assert!(content[..200].iter().all(|&b| b == 1));
+        assert!(content[200..].iter().all(|&b| b == 2));
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_trigger_matching() {
+        let mut trigger = RecordTrigger::default();
+        trigger.add("emergency,4840D6").unwrap();
+        assert!(trigger.add("xyz").is_err());
+
+        let mut mm = ModesMessage { crc_ok: true, msg_type: 5, identity: 7700, ..Default::default() };
+        assert_eq!(trigger.check(&mm).as_deref(), Some("squawk 7700"));
+        mm.identity = 1200;
+        assert_eq!(trigger.check(&mm), None);
+    }
+}
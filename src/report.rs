@@ -0,0 +1,456 @@
+//! Statistics reports
+//!
+//! Summaries of demodulation and decoding across the pipeline: for each
+//! interval of `--stats-every` seconds in live mode, and for the whole run
+//! at exit with `--stats`. Reports are printed, and appended as JSON lines
+//! to `--stats-history` so runs can be compared later.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::RwLock;
+use serde::Serialize;
+use tracing::error;
+
+use crate::agc::{AgcStats, SharedAgcStats};
+use crate::aircraft::AircraftStore;
+use crate::connector::SharedConnectorStatus;
+use crate::demodulator::{ApStats, DemodStats, SharedDemodStats};
+use crate::iq_correction::{IqCorrectionStats, SharedIqCorrectionStats};
+use crate::stats::{SharedReceiverStats, SourceStats, StatsPeriod, unix_now};
+use crate::supervisor::SharedDeviceHealth;
+
+/// Rotated history files kept (`<file>.1` is the newest)
+const HISTORY_KEPT: usize = 5;
+
+/// Where the reports come from; `None` for parts not in use
+#[derive(Clone)]
+pub struct ReportSources {
+    pub demod: SharedDemodStats,
+    pub receiver: SharedReceiverStats,
+    pub store: Arc<RwLock<AircraftStore>>,
+    pub agc: Option<SharedAgcStats>,
+    pub iq: Option<SharedIqCorrectionStats>,
+    pub device: Option<SharedDeviceHealth>,
+    pub connectors: Vec<SharedConnectorStatus>,
+}
+
+/// Counters at one point in time; a report covers the span between two
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    pub at: f64,
+    demod: DemodStats,
+    messages: StatsPeriod,
+}
+
+impl Checkpoint {
+    /// All counters at zero, for the start of the run
+    pub fn start(at: f64) -> Self {
+        Self { at, demod: DemodStats::default(), messages: StatsPeriod::default() }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CrcCounts {
+    pub good: u64,
+    pub fixed_1bit: u64,
+    pub fixed_2bit: u64,
+    pub bad: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceReport {
+    pub state: String,
+    pub starts: u64,
+    pub restarts: u64,
+    pub stalls: u64,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectorReport {
+    pub addr: String,
+    pub format: &'static str,
+    pub state: String,
+    pub connects: u64,
+    pub messages_sent: u64,
+    pub dropped: u64,
+    /// The status line printed in the text report
+    #[serde(skip)]
+    pub summary: String,
+}
+
+/// Which span a report covers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportKind {
+    Interval,
+    Total,
+}
+
+/// One statistics report. Counters cover `start..end`; noise floor,
+/// aircraft, AGC, I/Q correction, device and connector values are the
+/// state at `end`.
+#[derive(Debug, Clone, Serialize)]
+pub struct StatsReport {
+    pub kind: ReportKind,
+    /// Unix times the report covers
+    pub start: f64,
+    pub end: f64,
+    pub samples: u64,
+    pub preambles: u64,
+    /// Mode S messages demodulated, by downlink format (formats seen only)
+    pub messages_by_df: BTreeMap<usize, u64>,
+    pub mode_ac: u64,
+    pub crc: CrcCounts,
+    pub phase_corrected: u64,
+    pub address_parity: ApStats,
+    pub noise_floor_dbfs: f64,
+    /// Mean signal level of the demodulated messages
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signal_dbfs: Option<f64>,
+    /// Messages accepted (or rejected) from our demodulator
+    pub local: SourceStats,
+    /// Messages from network inputs
+    pub remote: SourceStats,
+    pub aircraft_tracked: usize,
+    pub aircraft_with_position: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub agc: Option<AgcStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iq_correction: Option<IqCorrectionStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device: Option<DeviceReport>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub connectors: Vec<ConnectorReport>,
+}
+
+impl ReportSources {
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            at: unix_now(),
+            demod: self.demod.read().clone(),
+            messages: self.receiver.read().total().clone(),
+        }
+    }
+
+    /// Report on the span from `since` to `now`
+    pub fn report(&self, kind: ReportKind, since: &Checkpoint, now: &Checkpoint) -> StatsReport {
+        let (d, e) = (&now.demod, &since.demod);
+        let signal_count = d.signal.count().saturating_sub(e.signal.count());
+        let store = self.store.read();
+        StatsReport {
+            kind,
+            start: since.at,
+            end: now.at,
+            samples: d.samples.saturating_sub(e.samples),
+            preambles: d.preambles.saturating_sub(e.preambles),
+            messages_by_df: (0..32)
+                .map(|df| (df, d.messages_by_df[df].saturating_sub(e.messages_by_df[df])))
+                .filter(|&(_, n)| n > 0)
+                .collect(),
+            mode_ac: d.mode_ac.saturating_sub(e.mode_ac),
+            crc: CrcCounts {
+                good: d.crc_good.saturating_sub(e.crc_good),
+                fixed_1bit: d.crc_fixed_1bit.saturating_sub(e.crc_fixed_1bit),
+                fixed_2bit: d.crc_fixed_2bit.saturating_sub(e.crc_fixed_2bit),
+                bad: d.crc_bad.saturating_sub(e.crc_bad),
+            },
+            phase_corrected: d.phase_corrected.saturating_sub(e.phase_corrected),
+            address_parity: ApStats {
+                exact: d.address_parity.exact.saturating_sub(e.address_parity.exact),
+                corrected: d.address_parity.corrected.saturating_sub(e.address_parity.corrected),
+                ambiguous: d.address_parity.ambiguous.saturating_sub(e.address_parity.ambiguous),
+                unknown: d.address_parity.unknown.saturating_sub(e.address_parity.unknown),
+            },
+            noise_floor_dbfs: d.noise_floor_dbfs,
+            signal_dbfs: (signal_count > 0)
+                .then(|| ((d.signal.sum() - e.signal.sum()) / signal_count as f64 * 10.0).round() / 10.0),
+            local: now.messages.local.since(&since.messages.local),
+            remote: now.messages.remote.since(&since.messages.remote),
+            aircraft_tracked: store.all().count(),
+            aircraft_with_position: store.all().filter(|ac| ac.sources.position.is_some()).count(),
+            agc: self.agc.as_ref().map(|a| a.read().clone()),
+            iq_correction: self.iq.as_ref().map(|iq| iq.read().clone()),
+            device: self.device.as_ref().map(|h| {
+                let h = h.read();
+                DeviceReport {
+                    state: h.state.to_string(),
+                    starts: h.starts,
+                    restarts: h.restarts,
+                    stalls: h.stalls,
+                    bytes: h.bytes,
+                }
+            }),
+            connectors: self
+                .connectors
+                .iter()
+                .map(|c| {
+                    let c = c.read();
+                    ConnectorReport {
+                        addr: c.addr.clone(),
+                        format: c.format.name(),
+                        state: c.state.to_string(),
+                        connects: c.connects,
+                        messages_sent: c.messages_sent,
+                        dropped: c.dropped,
+                        summary: c.to_string(),
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Percentage of `n` in `total`, 0 when there is no total
+fn percent(n: u64, total: u64) -> f64 {
+    if total == 0 { 0.0 } else { 100.0 * n as f64 / total as f64 }
+}
+
+impl fmt::Display for StatsReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let seconds = (self.end - self.start).max(0.0);
+        match self.kind {
+            ReportKind::Interval => writeln!(f, "Statistics for the last {:.0} s:", seconds)?,
+            ReportKind::Total => writeln!(f, "Statistics for the whole run ({:.1} s):", seconds)?,
+        }
+        writeln!(f, "  {} samples processed", self.samples)?;
+        writeln!(f, "  {} Mode S preambles detected", self.preambles)?;
+        let modes: u64 = self.messages_by_df.values().sum();
+        let by_df: Vec<String> = self.messages_by_df.iter().map(|(df, n)| format!("DF{} {}", df, n)).collect();
+        if by_df.is_empty() {
+            writeln!(f, "  {} Mode S messages demodulated", modes)?;
+        } else {
+            writeln!(f, "  {} Mode S messages demodulated ({})", modes, by_df.join(", "))?;
+        }
+        writeln!(f, "  {} Mode A/C replies", self.mode_ac)?;
+        let crc = &self.crc;
+        writeln!(
+            f,
+            "  CRC: {} good, {} fixed 1-bit, {} fixed 2-bit, {} bad ({:.1}% of preambles decoded)",
+            crc.good,
+            crc.fixed_1bit,
+            crc.fixed_2bit,
+            crc.bad,
+            percent(modes, self.preambles)
+        )?;
+        writeln!(f, "  {} messages recovered by phase correction", self.phase_corrected)?;
+        let ap = &self.address_parity;
+        writeln!(
+            f,
+            "  Address/parity: {} matched, {} corrected, {} ambiguous, {} unknown",
+            ap.exact, ap.corrected, ap.ambiguous, ap.unknown
+        )?;
+        match self.signal_dbfs {
+            Some(signal) => writeln!(
+                f,
+                "  Noise floor {:.1} dBFS, mean signal {:.1} dBFS",
+                self.noise_floor_dbfs, signal
+            )?,
+            None => writeln!(f, "  Noise floor {:.1} dBFS", self.noise_floor_dbfs)?,
+        }
+        writeln!(
+            f,
+            "  Accepted: {} Mode S, {} Mode A/C local ({} bad); {} Mode S, {} Mode A/C from the network ({} bad)",
+            self.local.modes, self.local.modeac, self.local.bad, self.remote.modes, self.remote.modeac, self.remote.bad
+        )?;
+        writeln!(
+            f,
+            "  Aircraft: {} tracked, {} with position",
+            self.aircraft_tracked, self.aircraft_with_position
+        )?;
+        if let Some(agc) = &self.agc {
+            writeln!(
+                f,
+                "  AGC: gain {:.1} dB, {} changes, {:.2}% clipped",
+                agc.gain_db,
+                agc.changes,
+                percent(agc.clipped_samples, agc.samples)
+            )?;
+        }
+        if let Some(iq) = &self.iq_correction {
+            writeln!(f, "  I/Q correction: {}", iq)?;
+        }
+        if let Some(device) = &self.device {
+            writeln!(
+                f,
+                "  Capture: {}, {} starts, {} restarts ({} stalls), {} bytes",
+                device.state, device.starts, device.restarts, device.stalls, device.bytes
+            )?;
+        }
+        for c in &self.connectors {
+            writeln!(f, "  Connector {}", c.summary)?;
+        }
+        Ok(())
+    }
+}
+
+/// Size-rotated JSON lines file of reports
+#[derive(Debug, Clone)]
+pub struct StatsHistory {
+    path: PathBuf,
+    /// Rotate before a line would take the file past this; 0 = never
+    max_bytes: u64,
+}
+
+impl StatsHistory {
+    pub fn new(path: impl Into<PathBuf>, max_size_mb: u64) -> Self {
+        Self { path: path.into(), max_bytes: max_size_mb * 1024 * 1024 }
+    }
+
+    /// `<file>.<n>`
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        name.into()
+    }
+
+    /// Shift `<file>` to `<file>.1`, `<file>.1` to `<file>.2` and so on,
+    /// dropping the oldest
+    fn rotate(&self) -> io::Result<()> {
+        for n in (1..HISTORY_KEPT).rev() {
+            match fs::rename(self.rotated(n), self.rotated(n + 1)) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        fs::rename(&self.path, self.rotated(1))
+    }
+
+    pub fn append(&self, report: &StatsReport) -> io::Result<()> {
+        let mut line = serde_json::to_string(report)?;
+        line.push('\n');
+        let size = fs::metadata(&self.path).map(|m| m.len()).unwrap_or(0);
+        if self.max_bytes > 0 && size > 0 && size + line.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        OpenOptions::new().create(true).append(true).open(&self.path)?.write_all(line.as_bytes())
+    }
+}
+
+/// Write `report` to the history file, if any, and print it when asked
+pub fn publish(report: &StatsReport, history: Option<&StatsHistory>, print: bool) {
+    if print {
+        print!("{}", report);
+    }
+    if let Some(history) = history
+        && let Err(e) = history.append(report)
+    {
+        error!("Cannot write stats history {}: {}", history.path.display(), e);
+    }
+}
+
+/// Interval reports every `every`, until aborted
+pub async fn run_interval_reports(sources: ReportSources, every: Duration, history: Option<StatsHistory>, print: bool) {
+    let mut interval = tokio::time::interval(every);
+    interval.tick().await;
+    let mut last = sources.checkpoint();
+    loop {
+        interval.tick().await;
+        let now = sources.checkpoint();
+        publish(&sources.report(ReportKind::Interval, &last, &now), history.as_ref(), print);
+        last = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::{MessageOrigin, ReceiverStats};
+
+    fn sources() -> ReportSources {
+        ReportSources {
+            demod: Arc::new(RwLock::new(DemodStats::default())),
+            receiver: Arc::new(RwLock::new(ReceiverStats::new(0.0))),
+            store: Arc::new(RwLock::new(AircraftStore::new(60))),
+            agc: None,
+            iq: None,
+            device: None,
+            connectors: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_interval_report_counts_difference() {
+        let sources = sources();
+        {
+            let mut d = sources.demod.write();
+            d.preambles = 100;
+            d.messages_by_df[17] = 40;
+            d.crc_good = 40;
+            d.signal.observe(-10.0);
+        }
+        sources.receiver.write().record_at(1.0, MessageOrigin::Local, false, true);
+        let first = sources.checkpoint();
+        {
+            let mut d = sources.demod.write();
+            d.preambles = 250;
+            d.messages_by_df[11] = 10;
+            d.messages_by_df[17] = 70;
+            d.crc_good = 75;
+            d.crc_bad = 5;
+            d.signal.observe(-6.0);
+            d.signal.observe(-4.0);
+        }
+        sources.receiver.write().record_at(2.0, MessageOrigin::Remote, false, true);
+        let second = sources.checkpoint();
+
+        let report = sources.report(ReportKind::Interval, &first, &second);
+        assert_eq!(report.preambles, 150);
+        assert_eq!(report.messages_by_df.into_iter().collect::<Vec<_>>(), vec![(11, 10), (17, 30)]);
+        assert_eq!((report.crc.good, report.crc.bad), (35, 5));
+        assert_eq!(report.signal_dbfs, Some(-5.0));
+        assert_eq!((report.local.modes, report.remote.modes), (0, 1));
+
+        let total = sources.report(ReportKind::Total, &Checkpoint::start(0.0), &second);
+        assert_eq!(total.preambles, 250);
+        assert_eq!(total.local.modes, 1);
+        assert!(total.to_string().contains("80 Mode S messages demodulated (DF11 10, DF17 70)"));
+    }
+
+    #[test]
+    fn test_report_json() {
+        let sources = sources();
+        let now = sources.checkpoint();
+        let v = serde_json::to_value(sources.report(ReportKind::Total, &Checkpoint::start(now.at), &now)).unwrap();
+        assert_eq!(v["kind"], "total");
+        assert_eq!(v["crc"]["fixed_1bit"], 0);
+        assert_eq!(v["noise_floor_dbfs"], 0.0);
+        // Unused parts and empty averages are left out
+        for missing in ["signal_dbfs", "agc", "iq_correction", "device", "connectors"] {
+            assert!(v.get(missing).is_none(), "{missing}");
+        }
+    }
+
+    #[test]
+    fn test_history_rotation() {
+        let dir = std::env::temp_dir().join(format!("adsb-rx-stats-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("stats.jsonl");
+        let mut history = StatsHistory::new(&path, 0);
+        let sources = sources();
+        let now = sources.checkpoint();
+        let report = sources.report(ReportKind::Interval, &now, &now);
+        let line_len = serde_json::to_string(&report).unwrap().len() as u64 + 1;
+
+        // Room for two lines per file
+        history.max_bytes = line_len * 2;
+        for _ in 0..(2 * (HISTORY_KEPT + 2) + 1) {
+            history.append(&report).unwrap();
+        }
+        let lines = |p: PathBuf| fs::read_to_string(p).map(|s| s.lines().count()).unwrap_or(0);
+        assert_eq!(lines(path.clone()), 1);
+        for n in 1..=HISTORY_KEPT {
+            assert_eq!(lines(history.rotated(n)), 2, "{n}");
+        }
+        assert!(!history.rotated(HISTORY_KEPT + 1).exists());
+        let parsed: serde_json::Value = serde_json::from_str(fs::read_to_string(&path).unwrap().trim()).unwrap();
+        assert_eq!(parsed["kind"], "interval");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,212 @@
+//! rtl_tcp client
+//!
+//! Speaks the `rtl_tcp` protocol so a dongle on a remote machine can feed
+//! the demodulator over the network. The server opens with a 12-byte
+//! dongle info header and then streams unsigned 8-bit I/Q; the client
+//! controls the tuner with 5-byte commands (opcode + big-endian u32).
+
+use std::fmt;
+use std::io;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Magic at the start of the dongle info header
+const RTLTCP_MAGIC: &[u8; 4] = b"RTL0";
+
+/// rtl_tcp command opcodes
+const CMD_SET_FREQ: u8 = 0x01;
+const CMD_SET_SAMPLE_RATE: u8 = 0x02;
+const CMD_SET_GAIN_MODE: u8 = 0x03;
+const CMD_SET_GAIN: u8 = 0x04;
+const CMD_SET_FREQ_CORRECTION: u8 = 0x05;
+const CMD_SET_AGC_MODE: u8 = 0x08;
+
+/// Tuner chip reported in the dongle info header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TunerType {
+    Unknown,
+    E4000,
+    Fc0012,
+    Fc0013,
+    Fc2580,
+    R820T,
+    R828D,
+}
+
+impl TunerType {
+    fn from_u32(v: u32) -> Self {
+        match v {
+            1 => Self::E4000,
+            2 => Self::Fc0012,
+            3 => Self::Fc0013,
+            4 => Self::Fc2580,
+            5 => Self::R820T,
+            6 => Self::R828D,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+impl fmt::Display for TunerType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Unknown => "unknown",
+            Self::E4000 => "E4000",
+            Self::Fc0012 => "FC0012",
+            Self::Fc0013 => "FC0013",
+            Self::Fc2580 => "FC2580",
+            Self::R820T => "R820T",
+            Self::R828D => "R828D",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Dongle info header sent by the server on connect
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DongleInfo {
+    pub tuner: TunerType,
+    /// Number of discrete gain steps the tuner supports
+    pub gain_count: u32,
+}
+
+impl DongleInfo {
+    /// Parse the 12-byte header
+    pub fn parse(header: &[u8; 12]) -> io::Result<Self> {
+        if &header[0..4] != RTLTCP_MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not an rtl_tcp server"));
+        }
+        let tuner = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+        let gain_count = u32::from_be_bytes([header[8], header[9], header[10], header[11]]);
+        Ok(Self { tuner: TunerType::from_u32(tuner), gain_count })
+    }
+}
+
+/// Encode an rtl_tcp command
+fn command(opcode: u8, param: u32) -> [u8; 5] {
+    let p = param.to_be_bytes();
+    [opcode, p[0], p[1], p[2], p[3]]
+}
+
+/// Connection to an rtl_tcp server
+pub struct RtlTcpClient {
+    stream: TcpStream,
+    info: DongleInfo,
+}
+
+impl RtlTcpClient {
+    /// Connect and read the dongle info header
+    pub async fn connect(addr: &str) -> io::Result<Self> {
+        let mut stream = TcpStream::connect(addr).await?;
+        stream.set_nodelay(true).ok();
+
+        let mut header = [0u8; 12];
+        stream.read_exact(&mut header).await?;
+        let info = DongleInfo::parse(&header)?;
+
+        Ok(Self { stream, info })
+    }
+
+    /// Dongle info reported by the server
+    pub fn info(&self) -> DongleInfo {
+        self.info
+    }
+
+    async fn send(&mut self, opcode: u8, param: u32) -> io::Result<()> {
+        self.stream.write_all(&command(opcode, param)).await
+    }
+
+    /// Tune to `freq` Hz
+    pub async fn set_frequency(&mut self, freq: u32) -> io::Result<()> {
+        self.send(CMD_SET_FREQ, freq).await
+    }
+
+    /// Set the sample rate in Hz
+    pub async fn set_sample_rate(&mut self, rate: u32) -> io::Result<()> {
+        self.send(CMD_SET_SAMPLE_RATE, rate).await
+    }
+
+    /// Select tuner gain mode: manual (`true`) or tuner auto-gain
+    pub async fn set_manual_gain(&mut self, manual: bool) -> io::Result<()> {
+        self.send(CMD_SET_GAIN_MODE, manual as u32).await
+    }
+
+    /// Set the tuner gain in dB (applies in manual gain mode)
+    pub async fn set_gain(&mut self, gain_db: f64) -> io::Result<()> {
+        self.send(CMD_SET_GAIN, (gain_db * 10.0).round().max(0.0) as u32).await
+    }
+
+    /// Set the frequency correction in ppm
+    pub async fn set_freq_correction(&mut self, ppm: i32) -> io::Result<()> {
+        self.send(CMD_SET_FREQ_CORRECTION, ppm as u32).await
+    }
+
+    /// Enable or disable the RTL2832 digital AGC
+    pub async fn set_agc_mode(&mut self, on: bool) -> io::Result<()> {
+        self.send(CMD_SET_AGC_MODE, on as u32).await
+    }
+
+    /// Read I/Q bytes until `buf` is full. Returns `false` on EOF.
+    pub async fn read_samples(&mut self, buf: &mut [u8]) -> io::Result<bool> {
+        match self.stream.read_exact(buf).await {
+            Ok(_) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_dongle_info() {
+        let mut header = *b"RTL0\0\0\0\x05\0\0\0\x1d";
+        let info = DongleInfo::parse(&header).unwrap();
+        assert_eq!(info, DongleInfo { tuner: TunerType::R820T, gain_count: 29 });
+
+        header[0] = b'X';
+        assert!(DongleInfo::parse(&header).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_fake_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        // Fake rtl_tcp: send the header and some samples, record commands
+        let server = tokio::spawn(async move {
+            let (mut sock, _) = listener.accept().await.unwrap();
+            sock.write_all(b"RTL0\0\0\0\x05\0\0\0\x1d").await.unwrap();
+            sock.write_all(&[127u8; 64]).await.unwrap();
+
+            let mut commands = [0u8; 20];
+            sock.read_exact(&mut commands).await.unwrap();
+            commands
+        });
+
+        let mut client = RtlTcpClient::connect(&addr).await.unwrap();
+        assert_eq!(client.info().tuner, TunerType::R820T);
+
+        client.set_frequency(1_090_000_000).await.unwrap();
+        client.set_sample_rate(2_000_000).await.unwrap();
+        client.set_gain(49.6).await.unwrap();
+        client.set_freq_correction(-3).await.unwrap();
+
+        let mut buf = [0u8; 64];
+        assert!(client.read_samples(&mut buf).await.unwrap());
+        assert!(buf.iter().all(|&b| b == 127));
+
+        let commands = server.await.unwrap();
+        assert_eq!(&commands[0..5], &[0x01, 0x40, 0xf8, 0x14, 0x80]); // 1090000000
+        assert_eq!(&commands[5..10], &[0x02, 0x00, 0x1e, 0x84, 0x80]); // 2000000
+        assert_eq!(&commands[10..15], &[0x04, 0x00, 0x00, 0x01, 0xf0]); // 496
+        assert_eq!(&commands[15..20], &[0x05, 0xff, 0xff, 0xff, 0xfd]); // -3 ppm
+
+        // Server hung up
+        assert!(!client.read_samples(&mut buf).await.unwrap());
+    }
+}
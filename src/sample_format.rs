@@ -0,0 +1,262 @@
+//! I/Q sample formats
+//!
+//! Describes the interleaved I/Q encodings we can read and converts each
+//! of them straight to magnitude, without quantising through 8 bits.
+
+use std::fmt;
+
+use crate::magnitude::MagnitudeLut;
+use crate::simd;
+
+/// Magnitude units per unit of normalised amplitude (full scale = 1.0).
+/// Matches the 8-bit LUT, where full scale is 128 and each step is 360.
+pub const MAG_FULL_SCALE: f32 = 128.0 * 360.0;
+
+/// Interleaved I/Q sample encoding
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SampleFormat {
+    /// Unsigned 8-bit, centred at 127 (RTL-SDR)
+    #[default]
+    Cu8,
+    /// Signed 8-bit (HackRF)
+    Cs8,
+    /// Signed 16-bit little-endian (Airspy, SDRplay)
+    Cs16,
+    /// 32-bit float little-endian, full scale ±1.0 (GNU Radio)
+    Cf32,
+}
+
+impl SampleFormat {
+    /// Parse a format name as used on the command line
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "cu8" | "uc8" => Some(Self::Cu8),
+            "cs8" | "sc8" | "ci8" => Some(Self::Cs8),
+            "cs16" | "sc16" | "ci16" => Some(Self::Cs16),
+            "cf32" | "fc32" => Some(Self::Cf32),
+            _ => None,
+        }
+    }
+
+    /// Parse a SigMF `core:datatype` string
+    pub fn from_sigmf_datatype(datatype: &str) -> Option<Self> {
+        match datatype {
+            "cu8" => Some(Self::Cu8),
+            "ci8" => Some(Self::Cs8),
+            "ci16_le" => Some(Self::Cs16),
+            "cf32_le" => Some(Self::Cf32),
+            _ => None,
+        }
+    }
+
+    /// SigMF `core:datatype` string for this format
+    pub fn sigmf_datatype(&self) -> &'static str {
+        match self {
+            Self::Cu8 => "cu8",
+            Self::Cs8 => "ci8",
+            Self::Cs16 => "ci16_le",
+            Self::Cf32 => "cf32_le",
+        }
+    }
+
+    /// Bytes per complex (I+Q) sample
+    pub fn bytes_per_sample(&self) -> usize {
+        match self {
+            Self::Cu8 | Self::Cs8 => 2,
+            Self::Cs16 => 4,
+            Self::Cf32 => 8,
+        }
+    }
+
+    /// Byte value used to pad buffers with silence
+    pub fn zero_byte(&self) -> u8 {
+        match self {
+            Self::Cu8 => 127,
+            Self::Cs8 | Self::Cs16 | Self::Cf32 => 0,
+        }
+    }
+
+    /// Convert raw interleaved I/Q bytes to magnitudes (one per I/Q pair),
+    /// replacing the contents of `out` so its allocation can be reused.
+    /// Trailing bytes that don't form a whole sample are ignored.
+    pub fn compute_magnitude_into(&self, data: &[u8], lut: &MagnitudeLut, out: &mut Vec<u16>) {
+        out.clear();
+        match self {
+            Self::Cu8 => simd::magnitude_cu8(data, lut, out),
+            Self::Cs8 => out.extend(
+                data.chunks_exact(2)
+                    .map(|c| lut.lookup((c[0] as i8).unsigned_abs(), (c[1] as i8).unsigned_abs())),
+            ),
+            Self::Cs16 => out.extend(data.chunks_exact(4).map(|c| {
+                let i = i16::from_le_bytes([c[0], c[1]]) as f32 / 32768.0;
+                let q = i16::from_le_bytes([c[2], c[3]]) as f32 / 32768.0;
+                scale_magnitude(i, q)
+            })),
+            Self::Cf32 => out.extend(data.chunks_exact(8).map(|c| {
+                let i = f32::from_le_bytes([c[0], c[1], c[2], c[3]]);
+                let q = f32::from_le_bytes([c[4], c[5], c[6], c[7]]);
+                scale_magnitude(i, q)
+            })),
+        }
+    }
+
+    /// Decode raw interleaved I/Q bytes to normalised samples (full scale
+    /// ±1.0), replacing the contents of `out` so its allocation can be
+    /// reused. Trailing bytes that don't form a whole sample are ignored.
+    pub fn decode_into(&self, data: &[u8], out: &mut Vec<(f32, f32)>) {
+        out.clear();
+        match self {
+            Self::Cu8 => out.extend(
+                data.chunks_exact(2)
+                    .map(|c| ((c[0] as f32 - 127.0) / 128.0, (c[1] as f32 - 127.0) / 128.0)),
+            ),
+            Self::Cs8 => out.extend(
+                data.chunks_exact(2)
+                    .map(|c| (c[0] as i8 as f32 / 128.0, c[1] as i8 as f32 / 128.0)),
+            ),
+            Self::Cs16 => out.extend(data.chunks_exact(4).map(|c| {
+                (
+                    i16::from_le_bytes([c[0], c[1]]) as f32 / 32768.0,
+                    i16::from_le_bytes([c[2], c[3]]) as f32 / 32768.0,
+                )
+            })),
+            Self::Cf32 => out.extend(data.chunks_exact(8).map(|c| {
+                (
+                    f32::from_le_bytes([c[0], c[1], c[2], c[3]]),
+                    f32::from_le_bytes([c[4], c[5], c[6], c[7]]),
+                )
+            })),
+        }
+    }
+
+    /// Encode normalised I/Q samples (full scale ±1.0) as raw bytes,
+    /// saturating at full scale.
+    pub fn encode(&self, iq: &[(f32, f32)]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(iq.len() * self.bytes_per_sample());
+        for &(i, q) in iq {
+            for v in [i, q] {
+                match self {
+                    Self::Cu8 => out.push((127.0 + v * 128.0).round().clamp(0.0, 255.0) as u8),
+                    Self::Cs8 => out.push((v * 128.0).round().clamp(-128.0, 127.0) as i8 as u8),
+                    Self::Cs16 => out.extend_from_slice(
+                        &((v * 32768.0).round().clamp(-32768.0, 32767.0) as i16).to_le_bytes(),
+                    ),
+                    Self::Cf32 => out.extend_from_slice(&v.to_le_bytes()),
+                }
+            }
+        }
+        out
+    }
+
+    /// Count clipped I/Q components (at or beyond full scale).
+    ///
+    /// Returns `(clipped, components)`.
+    pub fn count_clipped(&self, data: &[u8]) -> (usize, usize) {
+        match self {
+            Self::Cu8 => (data.iter().filter(|&&s| s == 0 || s == 255).count(), data.len()),
+            Self::Cs8 => (data.iter().filter(|&&s| s == 0x80 || s == 0x7f).count(), data.len()),
+            Self::Cs16 => {
+                let clipped = data
+                    .chunks_exact(2)
+                    .filter(|c| matches!(i16::from_le_bytes([c[0], c[1]]), i16::MIN | i16::MAX))
+                    .count();
+                (clipped, data.len() / 2)
+            }
+            Self::Cf32 => {
+                let clipped = data
+                    .chunks_exact(4)
+                    .filter(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]).abs() >= 1.0)
+                    .count();
+                (clipped, data.len() / 4)
+            }
+        }
+    }
+}
+
+impl fmt::Display for SampleFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Cu8 => "cu8",
+            Self::Cs8 => "cs8",
+            Self::Cs16 => "cs16",
+            Self::Cf32 => "cf32",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Magnitude of a normalised I/Q pair in LUT units
+#[inline]
+pub fn scale_magnitude(i: f32, q: f32) -> u16 {
+    ((i * i + q * q).sqrt() * MAG_FULL_SCALE).min(u16::MAX as f32) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_formats_share_scale() {
+        let lut = MagnitudeLut::new();
+
+        // Full-scale I, zero Q in every format
+        let mut cs16 = Vec::new();
+        cs16.extend_from_slice(&i16::MIN.to_le_bytes());
+        cs16.extend_from_slice(&0i16.to_le_bytes());
+        let mut cf32 = Vec::new();
+        cf32.extend_from_slice(&1.0f32.to_le_bytes());
+        cf32.extend_from_slice(&0.0f32.to_le_bytes());
+
+        let mut mag = Vec::new();
+        for (format, data) in [
+            (SampleFormat::Cu8, vec![255, 127]),
+            (SampleFormat::Cs8, vec![0x80, 0]),
+            (SampleFormat::Cs16, cs16),
+            (SampleFormat::Cf32, cf32),
+        ] {
+            format.compute_magnitude_into(&data, &lut, &mut mag);
+            assert!((mag[0] as i32 - 46080).abs() < 400, "{} magnitude {}", format, mag[0]);
+        }
+    }
+
+    #[test]
+    fn test_wide_formats_keep_resolution() {
+        let lut = MagnitudeLut::new();
+
+        // Both would quantise to the same 8-bit value
+        let mut data = Vec::new();
+        for i in [100i16, 150] {
+            data.extend_from_slice(&i.to_le_bytes());
+            data.extend_from_slice(&0i16.to_le_bytes());
+        }
+        let mut mag = Vec::new();
+        SampleFormat::Cs16.compute_magnitude_into(&data, &lut, &mut mag);
+        assert!(mag[1] > mag[0]);
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        let lut = MagnitudeLut::new();
+        let iq = [(0.5f32, 0.0f32), (0.0, -0.25)];
+        let (mut mag, mut decoded) = (Vec::new(), Vec::new());
+        for format in [SampleFormat::Cu8, SampleFormat::Cs8, SampleFormat::Cs16, SampleFormat::Cf32] {
+            let data = format.encode(&iq);
+            assert_eq!(data.len(), 2 * format.bytes_per_sample());
+            format.compute_magnitude_into(&data, &lut, &mut mag);
+            assert!((mag[0] as i32 - 23040).abs() < 400, "{} magnitude {}", format, mag[0]);
+            assert!((mag[1] as i32 - 11520).abs() < 400, "{} magnitude {}", format, mag[1]);
+
+            format.decode_into(&data, &mut decoded);
+            assert!((decoded[0].0 - 0.5).abs() < 0.01 && decoded[0].1.abs() < 0.01, "{} {:?}", format, decoded);
+            assert!(decoded[1].0.abs() < 0.01 && (decoded[1].1 + 0.25).abs() < 0.01, "{} {:?}", format, decoded);
+        }
+    }
+
+    #[test]
+    fn test_format_names() {
+        assert_eq!(SampleFormat::from_name("sc8"), Some(SampleFormat::Cs8));
+        assert_eq!(SampleFormat::from_name("CS16"), Some(SampleFormat::Cs16));
+        assert_eq!(SampleFormat::from_sigmf_datatype("cf32_le"), Some(SampleFormat::Cf32));
+        assert_eq!(SampleFormat::from_sigmf_datatype("cf32_be"), None);
+    }
+}
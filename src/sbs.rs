@@ -0,0 +1,450 @@
+//! SBS/BaseStation output and input
+//!
+//! The comma-separated text format of Kinetic's BaseStation, served on port
+//! 30003 and read by Virtual Radar Server, PlanePlotter and many loggers.
+//! Each line has 22 fields:
+//!
+//! ```text
+//! MSG,type,session,aircraft,hex,flight,date gen,time gen,date log,time log,
+//! callsign,altitude,ground speed,track,lat,lon,vertical rate,squawk,
+//! alert,emergency,spi,on ground
+//! ```
+//!
+//! Flags are -1 for true and 0 for false; unknown fields are left empty.
+//! Besides MSG lines, BaseStation feeds carry STA (status), AIR (new
+//! aircraft), ID (callsign) and SEL (selection) records, which we parse
+//! when reading other receivers' feeds.
+
+use std::fmt::{self, Display};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::aircraft::Aircraft;
+use crate::date::civil_from_days;
+use crate::decoder::{AltitudeUnit, ModesMessage};
+
+/// BaseStation transmission types
+const ES_IDENTIFICATION: u8 = 1;
+const ES_SURFACE_POSITION: u8 = 2;
+const ES_AIRBORNE_POSITION: u8 = 3;
+const ES_VELOCITY: u8 = 4;
+const SURVEILLANCE_ALTITUDE: u8 = 5;
+const SURVEILLANCE_ID: u8 = 6;
+const AIR_TO_AIR: u8 = 7;
+const ALL_CALL_REPLY: u8 = 8;
+
+/// BaseStation record types
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SbsKind {
+    /// Transmission message
+    #[default]
+    Msg,
+    /// Status change of an aircraft
+    Sta,
+    /// New aircraft
+    Air,
+    /// Callsign change
+    Id,
+    /// Aircraft selected by the user
+    Sel,
+}
+
+impl SbsKind {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "MSG" => Some(Self::Msg),
+            "STA" => Some(Self::Sta),
+            "AIR" => Some(Self::Air),
+            "ID" => Some(Self::Id),
+            "SEL" => Some(Self::Sel),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Msg => "MSG",
+            Self::Sta => "STA",
+            Self::Air => "AIR",
+            Self::Id => "ID",
+            Self::Sel => "SEL",
+        }
+    }
+}
+
+/// One BaseStation record
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SbsRecord {
+    pub kind: SbsKind,
+    /// MSG transmission type, 1-8
+    pub transmission: u8,
+    pub hex: u32,
+    /// STA status: PL, SL, RM, AD or OK
+    pub status: Option<String>,
+    pub callsign: Option<String>,
+    /// Feet
+    pub altitude: Option<i32>,
+    /// Knots
+    pub ground_speed: Option<u16>,
+    /// Degrees
+    pub track: Option<u16>,
+    pub position: Option<(f64, f64)>,
+    /// Feet per minute
+    pub vertical_rate: Option<i32>,
+    pub squawk: Option<u16>,
+    pub alert: Option<bool>,
+    pub emergency: Option<bool>,
+    pub spi: Option<bool>,
+    pub on_ground: Option<bool>,
+}
+
+/// Empty for `None`
+struct Field<T>(Option<T>);
+
+impl<T: Display> Display for Field<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            Some(v) => v.fmt(f),
+            None => Ok(()),
+        }
+    }
+}
+
+fn flag(v: Option<bool>) -> Field<i32> {
+    Field(v.map(|b| if b { -1 } else { 0 }))
+}
+
+fn is_emergency(squawk: u16) -> bool {
+    matches!(squawk, 7500 | 7600 | 7700)
+}
+
+/// Altitude in feet, whatever unit the message used
+fn altitude_ft(mm: &ModesMessage) -> i32 {
+    match mm.unit {
+        AltitudeUnit::Feet => mm.altitude,
+        AltitudeUnit::Meters => (mm.altitude as f64 * 3.28084).round() as i32,
+    }
+}
+
+/// Ground speed in knots for a surface position movement code
+fn surface_speed(movement: u8) -> Option<f64> {
+    let speed = match movement {
+        1 => 0.0,
+        2 => 0.0625,
+        3..=8 => 0.125 + (movement - 3) as f64 * 0.125 + 0.0625,
+        9..=12 => 1.0 + (movement - 9) as f64 * 0.25 + 0.125,
+        13..=38 => 2.0 + (movement - 13) as f64 * 0.5 + 0.25,
+        39..=93 => 15.0 + (movement - 39) as f64 + 0.5,
+        94..=108 => 70.0 + (movement - 94) as f64 * 2.0 + 1.0,
+        109..=123 => 100.0 + (movement - 109) as f64 * 5.0 + 2.5,
+        124 => 175.0,
+        _ => return None,
+    };
+    Some(speed)
+}
+
+/// "YYYY/MM/DD" and "HH:MM:SS.mmm" in UTC
+fn date_time(now: SystemTime) -> (String, String) {
+    let since = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let rem = secs % 86400;
+    (
+        format!("{:04}/{:02}/{:02}", year, month, day),
+        format!(
+            "{:02}:{:02}:{:02}.{:03}",
+            rem / 3600,
+            (rem / 60) % 60,
+            rem % 60,
+            since.subsec_millis()
+        ),
+    )
+}
+
+impl SbsRecord {
+    fn new(transmission: u8, mm: &ModesMessage) -> Self {
+        Self { transmission, hex: mm.icao_address(), ..Self::default() }
+    }
+
+    /// Alert, emergency and SPI from the flight status field. An alert
+    /// means the squawk was changed.
+    fn with_flight_status(mut self, mm: &ModesMessage, squawk: u16) -> Self {
+        self.alert = Some(matches!(mm.fs, 2..=4));
+        self.emergency = Some(is_emergency(squawk));
+        self.spi = Some(matches!(mm.fs, 4 | 5));
+        self.on_ground = match mm.fs {
+            1 | 3 => Some(true),
+            0 | 2 => Some(false),
+            _ => None,
+        };
+        self
+    }
+
+    /// Flags for extended squitters, which carry no flight status
+    fn with_es_flags(mut self, squawk: u16, on_ground: Option<bool>) -> Self {
+        self.alert = Some(false);
+        self.emergency = Some(is_emergency(squawk));
+        self.spi = Some(false);
+        self.on_ground = on_ground;
+        self
+    }
+
+    fn write_line(&self, now: SystemTime) -> String {
+        let (date, time) = date_time(now);
+        format!(
+            "{},{},1,1,{:06X},1,{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            self.kind.as_str(),
+            self.transmission,
+            self.hex,
+            date,
+            time,
+            date,
+            time,
+            Field(self.callsign.as_deref()),
+            Field(self.altitude),
+            Field(self.ground_speed),
+            Field(self.track),
+            Field(self.position.map(|p| format!("{:.5}", p.0))),
+            Field(self.position.map(|p| format!("{:.5}", p.1))),
+            Field(self.vertical_rate),
+            Field(self.squawk.map(|s| format!("{:04}", s))),
+            flag(self.alert),
+            flag(self.emergency),
+            flag(self.spi),
+            flag(self.on_ground),
+        )
+    }
+}
+
+/// Parse a flag field: -1 (or 1) for true, 0 for false
+fn parse_flag(s: &str) -> Option<bool> {
+    match s {
+        "-1" | "1" => Some(true),
+        "0" => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_num<T: std::str::FromStr>(s: &str) -> Option<T> {
+    s.trim().parse().ok()
+}
+
+impl SbsRecord {
+    /// Parse a line of a BaseStation feed; `None` for other records, lines
+    /// without an ICAO address and non-ICAO (`~`) addresses
+    pub fn parse(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line.trim_end_matches(['\r', '\n']).split(',').collect();
+        let field = |i: usize| fields.get(i).copied().unwrap_or("").trim();
+
+        let kind = SbsKind::parse(field(0))?;
+        let hex = u32::from_str_radix(field(4), 16).ok().filter(|&h| h <= 0xFF_FFFF)?;
+        let mut rec = Self { kind, hex, ..Self::default() };
+
+        match kind {
+            SbsKind::Msg => {
+                rec.transmission = parse_num(field(1)).filter(|t| (1..=8).contains(t))?;
+                rec.callsign = Some(field(10)).filter(|c| !c.is_empty()).map(str::to_string);
+                rec.altitude = parse_num::<f64>(field(11)).map(|a| a.round() as i32);
+                rec.ground_speed = parse_num::<f64>(field(12)).map(|v| v.round() as u16);
+                rec.track = parse_num::<f64>(field(13)).map(|t| t.round() as u16 % 360);
+                rec.position = match (parse_num::<f64>(field(14)), parse_num::<f64>(field(15))) {
+                    (Some(lat), Some(lon))
+                        if (-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon) && (lat, lon) != (0.0, 0.0) =>
+                    {
+                        Some((lat, lon))
+                    }
+                    _ => None,
+                };
+                rec.vertical_rate = parse_num::<f64>(field(16)).map(|v| v.round() as i32);
+                rec.squawk = parse_num(field(17));
+                rec.alert = parse_flag(field(18));
+                rec.emergency = parse_flag(field(19));
+                rec.spi = parse_flag(field(20));
+                rec.on_ground = parse_flag(field(21));
+            }
+            SbsKind::Sta => rec.status = Some(field(10).to_string()),
+            SbsKind::Air | SbsKind::Id | SbsKind::Sel => {
+                rec.callsign = Some(field(10)).filter(|c| !c.is_empty()).map(str::to_string);
+            }
+        }
+        Some(rec)
+    }
+}
+
+/// Build the BaseStation record for a message; `None` for messages SBS has
+/// no transmission type for. `aircraft` supplies the resolved position and
+/// the last known squawk.
+fn record(mm: &ModesMessage, aircraft: Option<&Aircraft>) -> Option<SbsRecord> {
+    let squawk = aircraft.map(|a| a.squawk).unwrap_or(0);
+
+    let rec = match mm.msg_type {
+        0 | 16 => SbsRecord { altitude: Some(altitude_ft(mm)), ..SbsRecord::new(AIR_TO_AIR, mm) },
+        4 | 20 => SbsRecord { altitude: Some(altitude_ft(mm)), ..SbsRecord::new(SURVEILLANCE_ALTITUDE, mm) }
+            .with_flight_status(mm, squawk),
+        5 | 21 => SbsRecord { squawk: Some(mm.identity), ..SbsRecord::new(SURVEILLANCE_ID, mm) }
+            .with_flight_status(mm, mm.identity),
+        11 => SbsRecord {
+            on_ground: match mm.ca {
+                4 => Some(true),
+                5 => Some(false),
+                _ => None,
+            },
+            ..SbsRecord::new(ALL_CALL_REPLY, mm)
+        },
+        17 | 18 => match mm.me_type {
+            1..=4 => SbsRecord { callsign: Some(mm.flight.clone()), ..SbsRecord::new(ES_IDENTIFICATION, mm) }
+                .with_es_flags(squawk, None),
+            5..=8 => {
+                // Surface positions need a reference position to resolve;
+                // send the movement and ground track
+                let movement = ((mm.msg[4] & 0x07) << 4) | (mm.msg[5] >> 4);
+                let track_valid = mm.msg[5] & 0x08 != 0;
+                let track = ((((mm.msg[5] & 0x07) << 4) | (mm.msg[6] >> 4)) as f64 * 360.0 / 128.0).round();
+                SbsRecord {
+                    ground_speed: surface_speed(movement).map(|s| s.round() as u16),
+                    track: track_valid.then_some(track as u16 % 360),
+                    ..SbsRecord::new(ES_SURFACE_POSITION, mm)
+                }
+                .with_es_flags(squawk, Some(true))
+            }
+            9..=18 | 20..=22 => SbsRecord {
+                // GNSS height for type codes 20-22
+                altitude: if mm.me_type >= 20 { mm.geom_altitude } else { Some(altitude_ft(mm)) },
+                position: aircraft.filter(|a| a.lat != 0.0 || a.lon != 0.0).map(|a| (a.lat, a.lon)),
+                ..SbsRecord::new(ES_AIRBORNE_POSITION, mm)
+            }
+            .with_es_flags(squawk, Some(false)),
+            19 if (1..=4).contains(&mm.me_sub) => {
+                let ground = mm.me_sub <= 2;
+                // Airspeed subtypes carry heading rather than track and no
+                // ground speed; BaseStation has no fields for either
+                let track = if ground { mm.velocity > 0 } else { mm.heading_is_valid };
+                SbsRecord {
+                    ground_speed: ground.then_some(mm.velocity),
+                    track: track.then_some(mm.heading.round() as u16 % 360),
+                    vertical_rate: mm.vertical_rate(),
+                    ..SbsRecord::new(ES_VELOCITY, mm)
+                }
+                .with_es_flags(squawk, Some(false))
+            }
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(rec)
+}
+
+/// Format a message as a BaseStation MSG line, without the line ending
+pub fn format_message(mm: &ModesMessage, aircraft: Option<&Aircraft>, now: SystemTime) -> Option<String> {
+    record(mm, aircraft).map(|r| r.write_line(now))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crc;
+    use crate::decoder::{decode_hex_message, decode_modes_message};
+    use std::time::Duration;
+
+    fn decode(hex: &str) -> ModesMessage {
+        decode_hex_message(hex, true, true, false).unwrap()
+    }
+
+    fn now() -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(1_760_000_000_123)
+    }
+
+    #[test]
+    fn test_identification_line() {
+        let line = format_message(&decode("*8D4840D6202CC371C32CE0576098;"), None, now()).unwrap();
+        assert_eq!(
+            line,
+            "MSG,1,1,1,4840D6,1,2025/10/09,08:53:20.123,2025/10/09,08:53:20.123,KLM1023,,,,,,,,0,0,0,"
+        );
+        assert_eq!(line.split(',').count(), 22);
+    }
+
+    #[test]
+    fn test_position_uses_aircraft() {
+        let mm = decode("*8D40621D58C382D690C8AC2863A7;");
+        let mut aircraft = Aircraft::new(0x40621D);
+        aircraft.squawk = 7700;
+        let line = format_message(&mm, Some(&aircraft), now()).unwrap();
+        assert!(line.starts_with("MSG,3,1,1,40621D,"));
+        assert!(line.ends_with(",38000,,,,,,,0,-1,0,0"), "{}", line);
+
+        aircraft.lat = 52.25720;
+        aircraft.lon = 3.91937;
+        let line = format_message(&mm, Some(&aircraft), now()).unwrap();
+        assert!(line.ends_with(",38000,,,52.25720,3.91937,,,0,-1,0,0"), "{}", line);
+    }
+
+    #[test]
+    fn test_df18_and_gnss_position() {
+        let with_parity = |mut msg: [u8; 14]| {
+            let parity = crc::parity(&msg, 112);
+            crc::write_parity_field(&mut msg, 112, parity);
+            decode_modes_message(&msg, false, false, false)
+        };
+
+        // The airborne position above, sent as DF18 (non-transponder ADS-B)
+        let mut msg = [0x95, 0x40, 0x62, 0x1D, 0x58, 0xC3, 0x82, 0xD6, 0x90, 0xC8, 0xAC, 0, 0, 0];
+        let line = format_message(&with_parity(msg), None, now()).unwrap();
+        assert!(line.starts_with("MSG,3,1,1,40621D,"), "{}", line);
+        assert!(line.ends_with(",38000,,,,,,,0,0,0,0"), "{}", line);
+
+        // TC 20 position reports GNSS height
+        msg[0] = 0x8D;
+        msg[4] = 0xA0;
+        let line = format_message(&with_parity(msg), None, now()).unwrap();
+        assert!(line.starts_with("MSG,3,"), "{}", line);
+        assert!(line.ends_with(",38000,,,,,,,0,0,0,0"), "{}", line);
+    }
+
+    #[test]
+    fn test_velocity_subtypes() {
+        // Ground speed subtype: speed, track and vertical rate
+        let line = format_message(&decode("*8D485020994409940838175B284F;"), None, now()).unwrap();
+        assert!(line.starts_with("MSG,4,"));
+        assert!(line.ends_with(",,,159,183,,,-832,,0,0,0,0"), "{}", line);
+
+        // Airspeed subtype: heading in the track field, no ground speed
+        let line = format_message(&decode("*8DA05F219B06B6AF189400CBC33F;"), None, now()).unwrap();
+        assert!(line.ends_with(",,,,244,,,-2304,,0,0,0,0"), "{}", line);
+    }
+
+    #[test]
+    fn test_parse_round_trip() {
+        let mm = decode("*8D485020994409940838175B284F;");
+        let line = format_message(&mm, None, now()).unwrap();
+        let rec = SbsRecord::parse(&(line.clone() + "\r\n")).unwrap();
+        assert_eq!(rec, record(&mm, None).unwrap());
+        assert_eq!(rec.write_line(now()), line);
+
+        let rec = SbsRecord::parse("MSG,3,111,11111,3C49CC,111111,2015/01/01,12:00:00.000,2015/01/01,12:00:00.000,,37000,,,51.45735,-1.02826,,,0,0,0,0").unwrap();
+        assert_eq!(rec.altitude, Some(37000));
+        assert_eq!(rec.position, Some((51.45735, -1.02826)));
+        assert_eq!(rec.on_ground, Some(false));
+
+        let rec = SbsRecord::parse("ID,111,11111,111111,3C49CC,111111,2015/01/01,12:00:00.000,2015/01/01,12:00:00.000,EZY123").unwrap();
+        assert_eq!((rec.kind, rec.callsign.as_deref()), (SbsKind::Id, Some("EZY123")));
+        let rec = SbsRecord::parse("STA,,5,179,400AE7,10103,2008/11/28,14:58:51.153,2008/11/28,14:58:51.153,RM").unwrap();
+        assert_eq!(rec.status.as_deref(), Some("RM"));
+
+        assert!(SbsRecord::parse("CLK,,,,,,2015/01/01,12:00:00.000").is_none());
+        assert!(SbsRecord::parse("MSG,3,1,1,~4840D6,1,,,,,,1000").is_none());
+        assert!(SbsRecord::parse("MSG,9,1,1,4840D6,1").is_none());
+    }
+
+    #[test]
+    fn test_surveillance_flags() {
+        // DF5, squawk 0356
+        let line = format_message(&decode("*2A00516D492B80;"), None, now()).unwrap();
+        assert!(line.starts_with("MSG,6,"));
+        assert!(line.ends_with(",,,,,,,,0356,-1,0,0,0"), "{}", line);
+
+        // DF11 all-call reply, airborne
+        let line = format_message(&decode("*5D4840D6F8740F;"), None, now()).unwrap();
+        assert!(line.starts_with("MSG,8,1,1,4840D6,"));
+        assert!(line.ends_with(",,,,,,,,,,0"), "{}", line);
+    }
+}
@@ -0,0 +1,159 @@
+//! SigMF recording metadata
+//!
+//! Reads the `.sigmf-meta` JSON that accompanies a `.sigmf-data` I/Q file
+//! so recordings can be replayed without spelling out their format, and
+//! writes it for recordings we make ourselves.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde_json::{Value, json};
+
+use crate::sample_format::SampleFormat;
+
+/// The parts of a SigMF recording's metadata we care about
+#[derive(Debug, Clone, PartialEq)]
+pub struct SigmfMeta {
+    /// Sample encoding (`core:datatype`)
+    pub format: SampleFormat,
+    /// Sample rate in Hz (`core:sample_rate`)
+    pub sample_rate: Option<u32>,
+    /// Centre frequency of the first capture segment (`core:frequency`)
+    pub frequency: Option<u32>,
+}
+
+/// Path of the metadata file belonging to a data file, if one exists.
+///
+/// `rec.sigmf-data` and `rec.sigmf-meta` both resolve to `rec.sigmf-meta`.
+pub fn meta_path_for(data_path: &str) -> Option<PathBuf> {
+    if data_path == "-" {
+        return None;
+    }
+    let meta = Path::new(data_path).with_extension("sigmf-meta");
+    meta.is_file().then_some(meta)
+}
+
+/// Path of the data file belonging to a metadata file
+pub fn data_path_for(meta_path: &Path) -> PathBuf {
+    meta_path.with_extension("sigmf-data")
+}
+
+/// Parse SigMF metadata JSON
+pub fn parse_meta(json: &str) -> io::Result<SigmfMeta> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+    let root: Value = serde_json::from_str(json).map_err(|e| invalid(e.to_string()))?;
+    let global = root
+        .get("global")
+        .ok_or_else(|| invalid("SigMF metadata has no global object".to_string()))?;
+
+    let datatype = global
+        .get("core:datatype")
+        .and_then(Value::as_str)
+        .ok_or_else(|| invalid("SigMF metadata has no core:datatype".to_string()))?;
+    let format = SampleFormat::from_sigmf_datatype(datatype)
+        .ok_or_else(|| invalid(format!("unsupported SigMF datatype: {}", datatype)))?;
+
+    let sample_rate = global
+        .get("core:sample_rate")
+        .and_then(Value::as_f64)
+        .map(|r| r.round() as u32);
+
+    let frequency = root
+        .get("captures")
+        .and_then(Value::as_array)
+        .and_then(|c| c.first())
+        .and_then(|c| c.get("core:frequency"))
+        .and_then(Value::as_f64)
+        .map(|f| f.round() as u32);
+
+    Ok(SigmfMeta { format, sample_rate, frequency })
+}
+
+/// Read SigMF metadata from a file
+pub fn read_meta(path: &Path) -> io::Result<SigmfMeta> {
+    parse_meta(&fs::read_to_string(path)?)
+}
+
+/// Write SigMF metadata for a recording.
+///
+/// `hw` is a free-text hardware description (device and gain);
+/// `datetime` is the ISO 8601 start time of the first sample.
+pub fn write_meta(
+    path: &Path,
+    format: SampleFormat,
+    sample_rate: u32,
+    frequency: u32,
+    hw: &str,
+    datetime: &str,
+) -> io::Result<()> {
+    let meta = json!({
+        "global": {
+            "core:datatype": format.sigmf_datatype(),
+            "core:sample_rate": sample_rate,
+            "core:version": "1.0.0",
+            "core:recorder": "adsb-rx",
+            "core:hw": hw,
+        },
+        "captures": [{
+            "core:sample_start": 0,
+            "core:frequency": frequency,
+            "core:datetime": datetime,
+        }],
+        "annotations": [],
+    });
+    let text = serde_json::to_string_pretty(&meta).map_err(io::Error::other)?;
+    fs::write(path, text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_meta() {
+        let json = r#"{
+            "global": {
+                "core:datatype": "ci16_le",
+                "core:sample_rate": 2000000.0,
+                "core:version": "1.0.0"
+            },
+            "captures": [{"core:sample_start": 0, "core:frequency": 1090000000}],
+            "annotations": []
+        }"#;
+        let meta = parse_meta(json).unwrap();
+        assert_eq!(meta.format, SampleFormat::Cs16);
+        assert_eq!(meta.sample_rate, Some(2_000_000));
+        assert_eq!(meta.frequency, Some(1_090_000_000));
+    }
+
+    #[test]
+    fn test_parse_meta_rejects_unsupported() {
+        let json = r#"{"global": {"core:datatype": "ri16_le"}}"#;
+        assert!(parse_meta(json).is_err());
+        assert!(parse_meta(r#"{"captures": []}"#).is_err());
+    }
+
+    #[test]
+    fn test_meta_path_for() {
+        let dir = std::env::temp_dir().join(format!("adsb-sigmf-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let meta = dir.join("rec.sigmf-meta");
+        fs::write(&meta, "{}").unwrap();
+
+        let data = dir.join("rec.sigmf-data");
+        assert_eq!(meta_path_for(data.to_str().unwrap()), Some(meta.clone()));
+        assert_eq!(data_path_for(&meta), data);
+        assert_eq!(meta_path_for(dir.join("other.bin").to_str().unwrap()), None);
+
+        // Round trip through our own writer
+        write_meta(&meta, SampleFormat::Cs8, 2_000_000, 1_090_000_000, "HackRF", "2024-01-01T00:00:00Z").unwrap();
+        let read = read_meta(&meta).unwrap();
+        assert_eq!(read.format, SampleFormat::Cs8);
+        assert_eq!(read.sample_rate, Some(2_000_000));
+        assert_eq!(read.frequency, Some(1_090_000_000));
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -143,7 +143,7 @@ impl SignalProcessor {
         let snr = self.calculate_snr_db(signal_level);
         // Try phase correction for signals between 2-8 dB SNR
         // Below 2 dB is too weak, above 8 dB should decode fine
-        snr >= 2.0 && snr <= 8.0
+        (2.0..=8.0).contains(&snr)
     }
 }
 
@@ -197,7 +197,7 @@ mod tests {
 
         // Signal at 1000 should give ~20 dB SNR
         let snr = sp.calculate_snr_db(1000);
-        assert!(snr >= 19.0 && snr <= 21.0);
+        assert!((19.0..=21.0).contains(&snr));
 
         // Signal at noise floor should give 0 dB
         let snr = sp.calculate_snr_db(100);
@@ -211,6 +211,6 @@ mod tests {
         
         // Threshold should be noise_floor * multiplier
         let threshold = sp.adaptive_threshold();
-        assert!(threshold >= 200 && threshold <= 300);
+        assert!((200..=300).contains(&threshold));
     }
 }
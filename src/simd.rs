@@ -0,0 +1,243 @@
+//! Vectorised magnitude conversion and preamble search
+//!
+//! The two per-sample hot loops of the receiver. On x86_64 they use SSE2
+//! (always present there); elsewhere they are written as fixed-width lane
+//! loops that the compiler vectorises for the target (NEON on aarch64),
+//! with a scalar tail. The scalar versions are kept as the reference.
+
+use crate::magnitude::MagnitudeLut;
+
+/// Lanes processed per step by the portable paths
+const LANES: usize = 8;
+/// Samples past a candidate position the preamble pattern looks at
+pub const PREAMBLE_SPAN: usize = 10;
+
+/// Convert unsigned 8-bit I/Q to magnitudes, replacing the contents of `out`.
+///
+/// Matches the LUT to within one unit (the LUT rounds in f64).
+pub fn magnitude_cu8(data: &[u8], lut: &MagnitudeLut, out: &mut Vec<u16>) {
+    out.clear();
+    out.resize(data.len() / 2, 0);
+
+    #[cfg(target_arch = "x86_64")]
+    let done = sse2::magnitude_cu8(data, out);
+    #[cfg(not(target_arch = "x86_64"))]
+    let done = magnitude_cu8_lanes(data, out);
+
+    for (m, c) in out[done..].iter_mut().zip(data[done * 2..].chunks_exact(2)) {
+        *m = lut.lookup(c[0].abs_diff(127).min(128), c[1].abs_diff(127).min(128));
+    }
+}
+
+/// Portable lane version of [`magnitude_cu8`]; returns the samples written
+#[cfg_attr(target_arch = "x86_64", allow(dead_code))]
+pub fn magnitude_cu8_lanes(data: &[u8], out: &mut [u16]) -> usize {
+    let blocks = (data.len() / 2 / LANES).min(out.len() / LANES);
+    for (o, d) in out.chunks_exact_mut(LANES).zip(data.chunks_exact(LANES * 2)).take(blocks) {
+        let mut power = [0f32; LANES];
+        for k in 0..LANES {
+            let i = d[2 * k] as i32 - 127;
+            let q = d[2 * k + 1] as i32 - 127;
+            power[k] = (i * i + q * q) as f32;
+        }
+        // Values are non-negative, so + 0.5 rounds (and vectorises, unlike round())
+        for k in 0..LANES {
+            o[k] = (power[k].sqrt() * 360.0 + 0.5) as u16;
+        }
+    }
+    blocks * LANES
+}
+
+/// Whether position `j` of `m` starts a Mode S preamble pattern: pulses
+/// at 0, 2, 7 and 9 (0.5 µs samples) with quieter samples around them
+#[inline]
+pub fn is_preamble(m: &[u16], j: usize) -> bool {
+    m[j] > m[j + 1]
+        && m[j + 1] < m[j + 2]
+        && m[j + 2] > m[j + 3]
+        && m[j + 3] < m[j]
+        && m[j + 4] < m[j]
+        && m[j + 5] < m[j]
+        && m[j + 6] < m[j]
+        && m[j + 7] > m[j + 8]
+        && m[j + 8] < m[j + 9]
+        && m[j + 9] > m[j + 6]
+}
+
+/// Scalar preamble search: positions below `end` that match the pattern,
+/// replacing the contents of `out`. `m` must extend `PREAMBLE_SPAN - 1`
+/// samples past `end`.
+#[allow(dead_code)]
+pub fn preamble_candidates_scalar(m: &[u16], end: usize, out: &mut Vec<usize>) {
+    out.clear();
+    out.extend((0..end).filter(|&j| is_preamble(m, j)));
+}
+
+/// Vectorised preamble search, same contract as [`preamble_candidates_scalar`]
+pub fn preamble_candidates(m: &[u16], end: usize, out: &mut Vec<usize>) {
+    out.clear();
+    let end = end.min(m.len().saturating_sub(PREAMBLE_SPAN - 1));
+
+    #[cfg(target_arch = "x86_64")]
+    let done = sse2::preamble_candidates(m, end, out);
+    #[cfg(not(target_arch = "x86_64"))]
+    let done = preamble_candidates_lanes(m, end, out);
+
+    out.extend((done..end).filter(|&j| is_preamble(m, j)));
+}
+
+/// Portable lane version of [`preamble_candidates`]; returns the positions
+/// searched
+#[cfg_attr(target_arch = "x86_64", allow(dead_code))]
+pub fn preamble_candidates_lanes(m: &[u16], end: usize, out: &mut Vec<usize>) -> usize {
+    let blocks = end / LANES;
+    for b in 0..blocks {
+        let base = b * LANES;
+        let w = &m[base..base + LANES + PREAMBLE_SPAN - 1];
+        let mut hit = [false; LANES];
+        for k in 0..LANES {
+            // Non-short-circuit so each comparison is a lane-wide operation
+            hit[k] = (w[k] > w[k + 1])
+                & (w[k + 1] < w[k + 2])
+                & (w[k + 2] > w[k + 3])
+                & (w[k + 3] < w[k])
+                & (w[k + 4] < w[k])
+                & (w[k + 5] < w[k])
+                & (w[k + 6] < w[k])
+                & (w[k + 7] > w[k + 8])
+                & (w[k + 8] < w[k + 9])
+                & (w[k + 9] > w[k + 6]);
+        }
+        if hit.iter().any(|&h| h) {
+            out.extend((0..LANES).filter(|&k| hit[k]).map(|k| base + k));
+        }
+    }
+    blocks * LANES
+}
+
+#[cfg(target_arch = "x86_64")]
+mod sse2 {
+    use std::arch::x86_64::*;
+
+    /// 8 magnitudes per 16 input bytes; returns the samples written
+    pub fn magnitude_cu8(data: &[u8], out: &mut [u16]) -> usize {
+        let blocks = (data.len() / 16).min(out.len() / 8);
+        // SAFETY: SSE2 is part of the x86_64 baseline, and every load and
+        // store stays within the `blocks` whole chunks of `data` and `out`.
+        unsafe {
+            let zero = _mm_setzero_si128();
+            let bias = _mm_set1_epi16(127);
+            let scale = _mm_set1_ps(360.0);
+            let offset = _mm_set1_epi32(32768);
+            let sign = _mm_set1_epi16(i16::MIN);
+            for b in 0..blocks {
+                let v = _mm_loadu_si128(data.as_ptr().add(b * 16) as *const __m128i);
+                // Interleaved (i, q) as i16, then i² + q² per pair
+                let lo = _mm_sub_epi16(_mm_unpacklo_epi8(v, zero), bias);
+                let hi = _mm_sub_epi16(_mm_unpackhi_epi8(v, zero), bias);
+                let plo = _mm_cvtepi32_ps(_mm_madd_epi16(lo, lo));
+                let phi = _mm_cvtepi32_ps(_mm_madd_epi16(hi, hi));
+                let mlo = _mm_cvtps_epi32(_mm_mul_ps(_mm_sqrt_ps(plo), scale));
+                let mhi = _mm_cvtps_epi32(_mm_mul_ps(_mm_sqrt_ps(phi), scale));
+                // No unsigned pack in SSE2: shift into i16 range, pack, shift back
+                let packed = _mm_packs_epi32(_mm_sub_epi32(mlo, offset), _mm_sub_epi32(mhi, offset));
+                _mm_storeu_si128(out.as_mut_ptr().add(b * 8) as *mut __m128i, _mm_xor_si128(packed, sign));
+            }
+        }
+        blocks * 8
+    }
+
+    /// 8 positions per step; returns the positions searched
+    pub fn preamble_candidates(m: &[u16], end: usize, out: &mut Vec<usize>) -> usize {
+        let blocks = end / 8;
+        // SAFETY: SSE2 is part of the x86_64 baseline; the caller limits
+        // `end` so that loads at base + 9 (8 lanes) stay inside `m`.
+        unsafe {
+            let sign = _mm_set1_epi16(i16::MIN);
+            let load = |j: usize| _mm_xor_si128(_mm_loadu_si128(m.as_ptr().add(j) as *const __m128i), sign);
+            // Unsigned a > b via the sign-flipped signed compare
+            let gt = |a: __m128i, b: __m128i| _mm_cmpgt_epi16(a, b);
+            for b in 0..blocks {
+                let base = b * 8;
+                let w: [__m128i; 10] = std::array::from_fn(|k| load(base + k));
+                let mut hit = _mm_and_si128(gt(w[0], w[1]), gt(w[2], w[1]));
+                hit = _mm_and_si128(hit, gt(w[2], w[3]));
+                hit = _mm_and_si128(hit, gt(w[0], w[3]));
+                hit = _mm_and_si128(hit, gt(w[0], w[4]));
+                hit = _mm_and_si128(hit, gt(w[0], w[5]));
+                hit = _mm_and_si128(hit, gt(w[0], w[6]));
+                hit = _mm_and_si128(hit, gt(w[7], w[8]));
+                hit = _mm_and_si128(hit, gt(w[9], w[8]));
+                hit = _mm_and_si128(hit, gt(w[9], w[6]));
+
+                // Two mask bits per 16-bit lane
+                let mut mask = _mm_movemask_epi8(hit) as u32;
+                while mask != 0 {
+                    let bit = mask.trailing_zeros();
+                    out.push(base + bit as usize / 2);
+                    mask &= !(0b11 << bit);
+                }
+            }
+        }
+        blocks * 8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::magnitude::compute_magnitude_vector;
+
+    /// Deterministic pseudo-random bytes
+    fn noise(len: usize, mut seed: u32) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                seed as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_magnitude_matches_lut() {
+        let lut = MagnitudeLut::new();
+        // Every (I, Q) byte pair, plus an odd tail
+        let mut data: Vec<u8> = (0..=255u8).flat_map(|i| (0..=255u8).flat_map(move |q| [i, q])).collect();
+        data.extend_from_slice(&[3, 250, 127, 127, 0, 255]);
+
+        let expected = compute_magnitude_vector(&data, &lut);
+        let mut out = vec![1u16; 3];
+        magnitude_cu8(&data, &lut, &mut out);
+        assert_eq!(out.len(), expected.len());
+        assert!(out.iter().zip(&expected).all(|(&a, &b)| a.abs_diff(b) <= 1));
+
+        let mut lanes = vec![0u16; expected.len()];
+        let done = magnitude_cu8_lanes(&data, &mut lanes);
+        assert!(lanes[..done].iter().zip(&expected).all(|(&a, &b)| a.abs_diff(b) <= 1));
+    }
+
+    #[test]
+    fn test_preamble_candidates_match_scalar() {
+        let lut = MagnitudeLut::new();
+        let mut m = compute_magnitude_vector(&noise(200_000, 7), &lut);
+        // Plant a clean preamble and a pattern using the full u16 range
+        m[1000..1010].copy_from_slice(&[9000, 100, 9000, 100, 50, 50, 80, 9000, 100, 9000]);
+        m[2001..2011].copy_from_slice(&[65000, 40000, 65000, 40000, 0, 0, 30000, 65000, 40000, 65000]);
+        let end = m.len() - PREAMBLE_SPAN + 1;
+
+        let mut expected = Vec::new();
+        preamble_candidates_scalar(&m, end, &mut expected);
+        assert!(expected.contains(&1000) && expected.contains(&2001));
+
+        let mut fast = vec![42];
+        preamble_candidates(&m, end, &mut fast);
+        assert_eq!(fast, expected);
+
+        let mut lanes = Vec::new();
+        let done = preamble_candidates_lanes(&m, end, &mut lanes);
+        lanes.extend((done..end).filter(|&j| is_preamble(&m, j)));
+        assert_eq!(lanes, expected);
+    }
+}
@@ -0,0 +1,229 @@
+//! Message statistics over time
+//!
+//! Counts accepted and rejected messages from the local receiver and from
+//! network inputs in one-minute periods, kept for the last 15 minutes plus
+//! a running total, in the shape readsb writes to `stats.json`.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use parking_lot::RwLock;
+use serde::Serialize;
+
+/// Length of one statistics period in seconds
+const PERIOD_SECS: f64 = 60.0;
+/// Completed periods kept
+const PERIODS_KEPT: usize = 15;
+
+/// Seconds since the Unix epoch
+pub fn unix_now() -> f64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64()
+}
+
+/// Counts for messages from one origin
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SourceStats {
+    /// Mode S messages accepted
+    pub modes: u64,
+    /// Mode A/C replies accepted
+    pub modeac: u64,
+    /// Messages rejected by the CRC check
+    pub bad: u64,
+}
+
+impl SourceStats {
+    fn add(&mut self, other: &SourceStats) {
+        self.modes += other.modes;
+        self.modeac += other.modeac;
+        self.bad += other.bad;
+    }
+
+    /// Counts added since the `earlier` snapshot of the same counters
+    pub fn since(&self, earlier: &SourceStats) -> SourceStats {
+        SourceStats {
+            modes: self.modes.saturating_sub(earlier.modes),
+            modeac: self.modeac.saturating_sub(earlier.modeac),
+            bad: self.bad.saturating_sub(earlier.bad),
+        }
+    }
+}
+
+/// Counts over one period of time
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StatsPeriod {
+    /// Unix time the period started
+    pub start: f64,
+    /// Unix time the period ended (or now, for an open period)
+    pub end: f64,
+    /// Accepted messages from all origins
+    pub messages: u64,
+    /// Our own demodulator
+    pub local: SourceStats,
+    /// Raw, Beast and other network inputs
+    pub remote: SourceStats,
+}
+
+impl StatsPeriod {
+    fn starting(start: f64) -> Self {
+        Self { start, end: start, ..Self::default() }
+    }
+
+    /// Combine consecutive periods
+    fn merge<'a>(periods: impl Iterator<Item = &'a StatsPeriod>) -> Option<StatsPeriod> {
+        let mut merged: Option<StatsPeriod> = None;
+        for p in periods {
+            let m = merged.get_or_insert_with(|| StatsPeriod::starting(p.start));
+            m.start = m.start.min(p.start);
+            m.end = m.end.max(p.end);
+            m.messages += p.messages;
+            m.local.add(&p.local);
+            m.remote.add(&p.remote);
+        }
+        merged
+    }
+}
+
+/// Where a message came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageOrigin {
+    Local,
+    Remote,
+}
+
+/// Rolling message statistics
+#[derive(Debug, Clone)]
+pub struct ReceiverStats {
+    current: StatsPeriod,
+    /// Completed periods, newest last
+    history: VecDeque<StatsPeriod>,
+    total: StatsPeriod,
+}
+
+pub type SharedReceiverStats = Arc<RwLock<ReceiverStats>>;
+
+/// The `stats.json` document
+#[derive(Debug, Serialize)]
+pub struct StatsJson {
+    pub now: f64,
+    /// The period in progress
+    pub latest: StatsPeriod,
+    pub last1min: StatsPeriod,
+    pub last5min: StatsPeriod,
+    pub last15min: StatsPeriod,
+    pub total: StatsPeriod,
+}
+
+impl ReceiverStats {
+    pub fn new(now: f64) -> Self {
+        Self {
+            current: StatsPeriod::starting(now),
+            history: VecDeque::new(),
+            total: StatsPeriod::starting(now),
+        }
+    }
+
+    pub fn shared() -> SharedReceiverStats {
+        Arc::new(RwLock::new(Self::new(unix_now())))
+    }
+
+    /// Close finished periods up to `now`
+    fn advance(&mut self, now: f64) {
+        while now - self.current.start >= PERIOD_SECS {
+            let next = self.current.start + PERIOD_SECS;
+            let mut done = std::mem::replace(&mut self.current, StatsPeriod::starting(next));
+            done.end = next;
+            self.history.push_back(done);
+            if self.history.len() > PERIODS_KEPT {
+                self.history.pop_front();
+            }
+        }
+        self.current.end = now;
+        self.total.end = now;
+    }
+
+    /// Count one message; `accepted` is false for CRC failures
+    pub fn record_at(&mut self, now: f64, origin: MessageOrigin, mode_ac: bool, accepted: bool) {
+        self.advance(now);
+        for period in [&mut self.current, &mut self.total] {
+            let source = match origin {
+                MessageOrigin::Local => &mut period.local,
+                MessageOrigin::Remote => &mut period.remote,
+            };
+            if !accepted {
+                source.bad += 1;
+                continue;
+            }
+            if mode_ac {
+                source.modeac += 1;
+            } else {
+                source.modes += 1;
+            }
+            period.messages += 1;
+        }
+    }
+
+    pub fn record(&mut self, origin: MessageOrigin, mode_ac: bool, accepted: bool) {
+        self.record_at(unix_now(), origin, mode_ac, accepted);
+    }
+
+    /// The last `n` completed periods combined; empty before the first ends
+    pub fn last(&self, n: usize) -> StatsPeriod {
+        let skip = self.history.len().saturating_sub(n);
+        StatsPeriod::merge(self.history.iter().skip(skip))
+            .unwrap_or_else(|| StatsPeriod::starting(self.current.start))
+    }
+
+    pub fn total(&self) -> &StatsPeriod {
+        &self.total
+    }
+
+    /// Snapshot for `stats.json` at `now`
+    pub fn snapshot_at(&mut self, now: f64) -> StatsJson {
+        self.advance(now);
+        StatsJson {
+            now,
+            latest: self.current.clone(),
+            last1min: self.last(1),
+            last5min: self.last(5),
+            last15min: self.last(15),
+            total: self.total.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_periods_roll_over() {
+        let mut stats = ReceiverStats::new(1000.0);
+        stats.record_at(1010.0, MessageOrigin::Local, false, true);
+        stats.record_at(1020.0, MessageOrigin::Local, false, false);
+        stats.record_at(1070.0, MessageOrigin::Remote, true, true);
+        stats.record_at(1130.0, MessageOrigin::Local, false, true);
+
+        let json = stats.snapshot_at(1140.0);
+        assert_eq!(json.total.messages, 3);
+        assert_eq!(json.total.local.bad, 1);
+        assert_eq!(json.latest.messages, 1);
+        assert_eq!((json.latest.start, json.latest.end), (1120.0, 1140.0));
+
+        // Last complete minute is 1060-1120 with the remote Mode A/C reply
+        assert_eq!(json.last1min.remote.modeac, 1);
+        assert_eq!((json.last1min.start, json.last1min.end), (1060.0, 1120.0));
+        assert_eq!(json.last5min.messages, 2);
+        assert_eq!(json.last5min.start, 1000.0);
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let mut stats = ReceiverStats::new(0.0);
+        stats.record_at(0.0, MessageOrigin::Local, false, true);
+        stats.snapshot_at(PERIOD_SECS * 100.0);
+        assert_eq!(stats.history.len(), PERIODS_KEPT);
+        assert_eq!(stats.last(15).messages, 0);
+        assert_eq!(stats.total().messages, 1);
+    }
+}
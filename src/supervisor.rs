@@ -0,0 +1,258 @@
+//! SDR subprocess supervision
+//!
+//! Runs capture tools such as `rtl_sdr` and `hackrf_transfer` as child
+//! processes, logs their stderr, notices when they exit or stop producing
+//! samples, and restarts them with exponential backoff. Device health is
+//! published for the display and stats consumers.
+
+use std::fmt;
+use std::io;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::RwLock;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::process::{Child, ChildStdout, Command};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::backoff::Backoff;
+
+/// A child that delivered samples for this long resets the backoff
+const BACKOFF_RESET_AFTER: Duration = Duration::from_secs(60);
+
+/// Current state of the supervised device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DeviceState {
+    #[default]
+    Starting,
+    Running,
+    Restarting,
+}
+
+impl fmt::Display for DeviceState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Starting => "starting",
+            Self::Running => "running",
+            Self::Restarting => "restarting",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Device health shared with the display and stats consumers
+#[derive(Debug, Clone, Default)]
+pub struct DeviceHealth {
+    pub state: DeviceState,
+    /// Times the capture process was started
+    pub starts: u64,
+    /// Restarts after an exit, stall or error
+    pub restarts: u64,
+    /// Restarts caused by the stall timeout
+    pub stalls: u64,
+    /// Sample bytes received across all runs
+    pub bytes: u64,
+    /// Why the last restart happened
+    pub last_error: Option<String>,
+    /// Last line the capture tool wrote to stderr
+    pub last_stderr: Option<String>,
+    /// When the current run started
+    pub running_since: Option<Instant>,
+}
+
+pub type SharedDeviceHealth = Arc<RwLock<DeviceHealth>>;
+
+/// Result of reading a buffer from a supervised child
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadOutcome {
+    /// The buffer was filled
+    Data,
+    /// The child closed its stdout
+    Eof,
+    /// No data arrived within the stall timeout
+    Stalled,
+}
+
+/// Restarts a capture tool with backoff and tracks its health
+pub struct Supervisor {
+    name: &'static str,
+    stall_timeout: Duration,
+    backoff: Backoff,
+    health: SharedDeviceHealth,
+}
+
+impl Supervisor {
+    pub fn new(name: &'static str, stall_timeout: Duration, health: SharedDeviceHealth) -> Self {
+        Self {
+            name,
+            stall_timeout,
+            backoff: Backoff::new(),
+            health,
+        }
+    }
+
+    /// Spawn `cmd` with stdout and stderr piped; stderr lines are logged
+    pub fn spawn(&mut self, cmd: &mut Command) -> io::Result<SupervisedChild> {
+        cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).kill_on_drop(true);
+        let mut child = cmd.spawn()?;
+        let stdout = child.stdout.take().ok_or_else(|| io::Error::other("no stdout"))?;
+        let stderr = child.stderr.take().ok_or_else(|| io::Error::other("no stderr"))?;
+
+        let name = self.name;
+        let health = Arc::clone(&self.health);
+        let stderr_task = tokio::spawn(async move {
+            let mut lines = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let line = line.trim().to_string();
+                if line.is_empty() {
+                    continue;
+                }
+                info!("{}: {}", name, line);
+                health.write().last_stderr = Some(line);
+            }
+        });
+
+        {
+            let mut health = self.health.write();
+            health.state = DeviceState::Running;
+            health.starts += 1;
+            health.running_since = Some(Instant::now());
+        }
+
+        Ok(SupervisedChild {
+            child,
+            stdout,
+            stderr_task,
+            stall_timeout: self.stall_timeout,
+            started: Instant::now(),
+            health: Arc::clone(&self.health),
+        })
+    }
+
+    /// Delay before the next start, doubling on each consecutive failure
+    fn next_delay(&mut self, ran_for: Duration) -> Duration {
+        if ran_for >= BACKOFF_RESET_AFTER {
+            self.backoff.reset();
+        }
+        self.backoff.next_delay()
+    }
+
+    /// Record a failure and wait out the backoff before the next start
+    pub async fn restart_after(&mut self, reason: &str, ran_for: Duration) {
+        let delay = self.next_delay(ran_for);
+
+        warn!("{}: {}; restarting in {:?}", self.name, reason, delay);
+        {
+            let mut health = self.health.write();
+            health.state = DeviceState::Restarting;
+            health.restarts += 1;
+            health.last_error = Some(reason.to_string());
+            health.running_since = None;
+        }
+        tokio::time::sleep(delay).await;
+    }
+
+    /// Record a restart caused by a stall
+    pub fn note_stall(&self) {
+        self.health.write().stalls += 1;
+    }
+}
+
+/// A running capture process
+pub struct SupervisedChild {
+    child: Child,
+    stdout: ChildStdout,
+    stderr_task: JoinHandle<()>,
+    stall_timeout: Duration,
+    started: Instant,
+    health: SharedDeviceHealth,
+}
+
+impl SupervisedChild {
+    /// Fill `buf` from the child's stdout
+    pub async fn read_full(&mut self, buf: &mut [u8]) -> io::Result<ReadOutcome> {
+        let mut total = 0;
+        while total < buf.len() {
+            match tokio::time::timeout(self.stall_timeout, self.stdout.read(&mut buf[total..])).await {
+                Err(_) => return Ok(ReadOutcome::Stalled),
+                Ok(Ok(0)) => return Ok(ReadOutcome::Eof),
+                Ok(Ok(n)) => total += n,
+                Ok(Err(e)) => return Err(e),
+            }
+        }
+        self.health.write().bytes += total as u64;
+        Ok(ReadOutcome::Data)
+    }
+
+    /// How long this child has been running
+    pub fn ran_for(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Kill the child and describe how it ended
+    pub async fn stop(mut self) -> String {
+        self.child.kill().await.ok();
+        let status = self.child.wait().await;
+        // Let the stderr logger drain what the tool printed before exiting
+        tokio::time::timeout(Duration::from_millis(200), &mut self.stderr_task).await.ok();
+        self.stderr_task.abort();
+        match status {
+            Ok(status) => format!("exited with {}", status),
+            Err(e) => format!("wait failed: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn health() -> SharedDeviceHealth {
+        Arc::new(RwLock::new(DeviceHealth::default()))
+    }
+
+    #[tokio::test]
+    async fn test_reads_and_eof() {
+        let health = health();
+        let mut supervisor = Supervisor::new("sh", Duration::from_secs(5), Arc::clone(&health));
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg("printf abcd; echo oops >&2");
+
+        let mut child = supervisor.spawn(&mut cmd).unwrap();
+        let mut buf = [0u8; 4];
+        assert_eq!(child.read_full(&mut buf).await.unwrap(), ReadOutcome::Data);
+        assert_eq!(&buf, b"abcd");
+        assert_eq!(child.read_full(&mut buf).await.unwrap(), ReadOutcome::Eof);
+        child.stop().await;
+
+        let health = health.read();
+        assert_eq!(health.starts, 1);
+        assert_eq!(health.bytes, 4);
+        assert_eq!(health.last_stderr.as_deref(), Some("oops"));
+    }
+
+    #[tokio::test]
+    async fn test_stall_detection() {
+        let mut supervisor = Supervisor::new("sleep", Duration::from_millis(100), health());
+        let mut cmd = Command::new("sleep");
+        cmd.arg("10");
+
+        let mut child = supervisor.spawn(&mut cmd).unwrap();
+        let mut buf = [0u8; 4];
+        assert_eq!(child.read_full(&mut buf).await.unwrap(), ReadOutcome::Stalled);
+        child.stop().await;
+    }
+
+    #[test]
+    fn test_backoff() {
+        let mut supervisor = Supervisor::new("x", Duration::from_secs(5), health());
+        let delays: Vec<u64> = (0..8).map(|_| supervisor.next_delay(Duration::ZERO).as_secs()).collect();
+        assert_eq!(delays, vec![1, 2, 4, 8, 16, 32, 60, 60]);
+
+        // A long healthy run resets the backoff
+        assert_eq!(supervisor.next_delay(Duration::from_secs(120)), Duration::from_secs(1));
+        assert_eq!(supervisor.next_delay(Duration::ZERO), Duration::from_secs(2));
+    }
+}
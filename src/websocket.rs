@@ -0,0 +1,576 @@
+//! WebSocket push of aircraft updates
+//!
+//! Clients connect to `/ws` on the HTTP port and get a full snapshot, then
+//! every `PUSH_INTERVAL` the fields that changed per aircraft and the
+//! aircraft that went away. Aircraft use the readsb `aircraft.json` schema.
+//!
+//! Server → client (text frames):
+//!
+//! ```text
+//! {"type":"snapshot","now":..,"messages":..,"aircraft":[{...}, ...]}
+//! {"type":"update","now":..,"aircraft":[{"hex":"4840d6","alt_baro":38025}],"removed":["40621d"]}
+//! ```
+//!
+//! Client → server, to change filters (answered with a new snapshot):
+//!
+//! ```text
+//! {"type":"subscribe","bbox":[south,west,north,east],"icao":["4840d6"]}
+//! ```
+//!
+//! The same filters can be given on connect as `/ws?bbox=s,w,n,e&icao=a,b`.
+//! Only what browsers need of RFC 6455 is implemented: unfragmented text
+//! frames out, masked (possibly fragmented) frames in, ping and close.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::RwLock;
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, watch};
+use tracing::debug;
+
+use crate::aircraft::AircraftStore;
+use crate::readsb::AircraftFile;
+use crate::stats;
+
+/// URL path of the push endpoint
+pub const WS_PATH: &str = "/ws";
+
+/// How often changes are pushed
+const PUSH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Appended to the client key for `Sec-WebSocket-Accept` (RFC 6455 1.3)
+const WS_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Largest client message we accept
+const MAX_MESSAGE_LEN: usize = 64 * 1024;
+
+/// Fields that age on every push; sent only alongside a real change
+pub const VOLATILE_FIELDS: [&str; 2] = ["seen", "seen_pos"];
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xA;
+
+/// SHA-1 digest, needed only for the handshake
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    let mut msg = data.to_vec();
+    msg.push(0x80);
+    while msg.len() % 64 != 56 {
+        msg.push(0);
+    }
+    msg.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in msg.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &wi) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let t = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(wi);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = t;
+        }
+        for (hi, v) in h.iter_mut().zip([a, b, c, d, e]) {
+            *hi = hi.wrapping_add(v);
+        }
+    }
+
+    let mut out = [0u8; 20];
+    for (chunk, v) in out.chunks_mut(4).zip(h) {
+        chunk.copy_from_slice(&v.to_be_bytes());
+    }
+    out
+}
+
+/// Standard base64 with padding
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let n = (chunk[0] as u32) << 16
+            | (*chunk.get(1).unwrap_or(&0) as u32) << 8
+            | *chunk.get(2).unwrap_or(&0) as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// `Sec-WebSocket-Accept` value for a client's `Sec-WebSocket-Key`
+pub fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key.trim(), WS_GUID).as_bytes()))
+}
+
+/// The `101 Switching Protocols` response completing the handshake
+pub fn handshake_response(key: &str) -> String {
+    format!(
+        "HTTP/1.1 101 Switching Protocols\r\n\
+         Upgrade: websocket\r\n\
+         Connection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\n\
+         \r\n",
+        accept_key(key)
+    )
+}
+
+/// An unmasked, unfragmented server frame
+fn encode_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(payload.len() + 10);
+    out.push(0x80 | opcode);
+    match payload.len() {
+        n if n < 126 => out.push(n as u8),
+        n if n <= u16::MAX as usize => {
+            out.push(126);
+            out.extend_from_slice(&(n as u16).to_be_bytes());
+        }
+        n => {
+            out.push(127);
+            out.extend_from_slice(&(n as u64).to_be_bytes());
+        }
+    }
+    out.extend_from_slice(payload);
+    out
+}
+
+/// One frame from the client
+#[derive(Debug, PartialEq, Eq)]
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+fn protocol_error(msg: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Read and unmask one client frame
+async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> std::io::Result<Frame> {
+    let mut head = [0u8; 2];
+    reader.read_exact(&mut head).await?;
+    let fin = head[0] & 0x80 != 0;
+    let opcode = head[0] & 0x0F;
+    if head[1] & 0x80 == 0 {
+        return Err(protocol_error("unmasked client frame"));
+    }
+    let len = match head[1] & 0x7F {
+        126 => reader.read_u16().await? as usize,
+        127 => reader.read_u64().await? as usize,
+        n => n as usize,
+    };
+    if len > MAX_MESSAGE_LEN {
+        return Err(protocol_error("client frame too large"));
+    }
+    let mut mask = [0u8; 4];
+    reader.read_exact(&mut mask).await?;
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload).await?;
+    for (i, b) in payload.iter_mut().enumerate() {
+        *b ^= mask[i % 4];
+    }
+    Ok(Frame { fin, opcode, payload })
+}
+
+/// A complete client message
+#[derive(Debug)]
+enum Incoming {
+    Text(String),
+    Ping(Vec<u8>),
+    Close,
+}
+
+/// Read client frames, reassembling fragmented messages, until the
+/// connection closes or `tx` is dropped
+async fn read_messages<R: AsyncRead + Unpin>(mut reader: R, tx: mpsc::Sender<Incoming>) {
+    let mut partial: Option<Vec<u8>> = None;
+    loop {
+        let incoming = match read_frame(&mut reader).await {
+            Ok(frame) => match frame.opcode {
+                OP_PING => Incoming::Ping(frame.payload),
+                OP_PONG => continue,
+                OP_CLOSE => Incoming::Close,
+                OP_TEXT | OP_BINARY | OP_CONTINUATION => {
+                    let text = frame.opcode == OP_TEXT || (frame.opcode == OP_CONTINUATION && partial.is_some());
+                    let buf = partial.get_or_insert_with(Vec::new);
+                    buf.extend_from_slice(&frame.payload);
+                    if buf.len() > MAX_MESSAGE_LEN {
+                        Incoming::Close
+                    } else if !frame.fin {
+                        continue;
+                    } else {
+                        let buf = partial.take().unwrap_or_default();
+                        if !text {
+                            continue;
+                        }
+                        Incoming::Text(String::from_utf8_lossy(&buf).into_owned())
+                    }
+                }
+                _ => Incoming::Close,
+            },
+            Err(e) => {
+                debug!("WebSocket read: {}", e);
+                Incoming::Close
+            }
+        };
+        let close = matches!(incoming, Incoming::Close);
+        if tx.send(incoming).await.is_err() || close {
+            return;
+        }
+    }
+}
+
+/// Which aircraft a client wants
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Filter {
+    /// `[south, west, north, east]`; west > east wraps the antimeridian.
+    /// Aircraft without a position never match.
+    #[serde(default)]
+    pub bbox: Option<[f64; 4]>,
+    /// ICAO addresses, any case
+    #[serde(default)]
+    pub icao: Option<Vec<String>>,
+}
+
+/// Messages clients send
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ClientMessage {
+    Subscribe(Filter),
+}
+
+impl Filter {
+    /// Filters from a `bbox=s,w,n,e&icao=a,b` query string; malformed
+    /// values are ignored
+    pub fn from_query(query: &str) -> Self {
+        let mut filter = Filter::default();
+        for (key, value) in query.split('&').filter_map(|kv| kv.split_once('=')) {
+            match key {
+                "bbox" => {
+                    let v: Vec<f64> = value.split(',').filter_map(|s| s.parse().ok()).collect();
+                    if let [s, w, n, e] = v[..] {
+                        filter.bbox = Some([s, w, n, e]);
+                    }
+                }
+                "icao" => {
+                    filter.icao = Some(value.split(',').filter(|s| !s.is_empty()).map(String::from).collect());
+                }
+                _ => {}
+            }
+        }
+        filter.normalize()
+    }
+
+    fn normalize(mut self) -> Self {
+        if let Some(icao) = &mut self.icao {
+            for hex in icao.iter_mut() {
+                *hex = hex.trim().to_lowercase();
+            }
+        }
+        self
+    }
+
+    /// Whether an `aircraft.json` entry passes
+    fn matches(&self, ac: &Map<String, Value>) -> bool {
+        if let Some(icao) = &self.icao {
+            let hex = ac.get("hex").and_then(Value::as_str).unwrap_or("");
+            if !icao.iter().any(|h| h == hex) {
+                return false;
+            }
+        }
+        if let Some([south, west, north, east]) = self.bbox {
+            let (Some(lat), Some(lon)) = (
+                ac.get("lat").and_then(Value::as_f64),
+                ac.get("lon").and_then(Value::as_f64),
+            ) else {
+                return false;
+            };
+            let lon_ok = if west <= east { (west..=east).contains(&lon) } else { lon >= west || lon <= east };
+            if !(south..=north).contains(&lat) || !lon_ok {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// The aircraft list as pushed, serialized once for all clients
+#[derive(Debug, Default)]
+pub struct Snapshot {
+    now: f64,
+    messages: u64,
+    aircraft: Vec<Map<String, Value>>,
+}
+
+pub type SnapshotReceiver = watch::Receiver<Arc<Snapshot>>;
+
+impl Snapshot {
+    pub fn capture(store: &AircraftStore) -> Self {
+        let file = AircraftFile::from_store(store, stats::unix_now());
+        let aircraft = file
+            .aircraft
+            .iter()
+            .filter_map(|ac| match serde_json::to_value(ac) {
+                Ok(Value::Object(map)) => Some(map),
+                _ => None,
+            })
+            .collect();
+        Self { now: file.now, messages: file.messages, aircraft }
+    }
+
+    /// Aircraft in the `aircraft.json` schema, ordered by ICAO
+    pub fn aircraft(&self) -> &[Map<String, Value>] {
+        &self.aircraft
+    }
+}
+
+/// What one client has been sent, to work out diffs
+#[derive(Debug, Default)]
+struct ClientState {
+    filter: Filter,
+    sent: HashMap<String, Map<String, Value>>,
+}
+
+impl ClientState {
+    fn matching<'a>(&'a self, snap: &'a Snapshot) -> impl Iterator<Item = (&'a str, &'a Map<String, Value>)> {
+        snap.aircraft.iter().filter(|ac| self.filter.matches(ac)).filter_map(|ac| {
+            ac.get("hex").and_then(Value::as_str).map(|hex| (hex, ac))
+        })
+    }
+
+    /// Full state, forgetting what was sent before
+    fn snapshot(&mut self, snap: &Snapshot) -> Value {
+        let sent: HashMap<String, Map<String, Value>> =
+            self.matching(snap).map(|(hex, ac)| (hex.to_string(), ac.clone())).collect();
+        self.sent = sent;
+        let aircraft: Vec<&Map<String, Value>> = self.matching(snap).map(|(_, ac)| ac).collect();
+        json!({"type": "snapshot", "now": snap.now, "messages": snap.messages, "aircraft": aircraft})
+    }
+
+    /// Changed fields per aircraft and removed aircraft; `None` when nothing
+    /// changed. A field that disappeared is sent as `null`.
+    fn update(&mut self, snap: &Snapshot) -> Option<Value> {
+        let mut changed = Vec::new();
+        let mut current = HashSet::new();
+        for (hex, ac) in self.matching(snap) {
+            current.insert(hex);
+            let diff = match self.sent.get(hex) {
+                None => ac.clone(),
+                Some(prev) => {
+                    let mut diff: Map<String, Value> = ac
+                        .iter()
+                        .filter(|(k, v)| !VOLATILE_FIELDS.contains(&k.as_str()) && prev.get(*k) != Some(*v))
+                        .map(|(k, v)| (k.clone(), v.clone()))
+                        .collect();
+                    for key in prev.keys().filter(|k| !ac.contains_key(*k)) {
+                        diff.insert(key.clone(), Value::Null);
+                    }
+                    if diff.is_empty() {
+                        continue;
+                    }
+                    for key in VOLATILE_FIELDS {
+                        if let Some(v) = ac.get(key) {
+                            diff.insert(key.to_string(), v.clone());
+                        }
+                    }
+                    diff.insert("hex".to_string(), Value::from(hex));
+                    diff
+                }
+            };
+            changed.push((hex.to_string(), ac.clone(), diff));
+        }
+
+        let removed: Vec<String> = self.sent.keys().filter(|h| !current.contains(h.as_str())).cloned().collect();
+        for hex in &removed {
+            self.sent.remove(hex);
+        }
+        if changed.is_empty() && removed.is_empty() {
+            return None;
+        }
+        let mut aircraft = Vec::with_capacity(changed.len());
+        for (hex, full, diff) in changed {
+            self.sent.insert(hex, full);
+            aircraft.push(diff);
+        }
+        Some(json!({"type": "update", "now": snap.now, "aircraft": aircraft, "removed": removed}))
+    }
+}
+
+/// Snapshot the store every `PUSH_INTERVAL` while clients are connected
+pub async fn run_publisher(store: Arc<RwLock<AircraftStore>>, tx: watch::Sender<Arc<Snapshot>>) {
+    let mut interval = tokio::time::interval(PUSH_INTERVAL);
+    loop {
+        interval.tick().await;
+        if tx.receiver_count() > 0 {
+            let snap = Snapshot::capture(&store.read());
+            tx.send_replace(Arc::new(snap));
+        }
+    }
+}
+
+/// Serve one upgraded connection until the client leaves
+pub async fn serve(
+    socket: TcpStream,
+    store: Arc<RwLock<AircraftStore>>,
+    mut snapshots: SnapshotReceiver,
+    filter: Filter,
+) -> std::io::Result<()> {
+    let (reader, mut writer) = socket.into_split();
+    let (in_tx, mut in_rx) = mpsc::channel(8);
+    let reader_handle = tokio::spawn(read_messages(reader, in_tx));
+
+    let mut state = ClientState { filter, ..ClientState::default() };
+    let snap = Snapshot::capture(&store.read());
+    writer.write_all(&encode_frame(OP_TEXT, state.snapshot(&snap).to_string().as_bytes())).await?;
+    snapshots.mark_unchanged();
+
+    let result = loop {
+        let out = tokio::select! {
+            changed = snapshots.changed() => {
+                if changed.is_err() {
+                    break Ok(());
+                }
+                let snap = Arc::clone(&snapshots.borrow_and_update());
+                match state.update(&snap) {
+                    Some(msg) => encode_frame(OP_TEXT, msg.to_string().as_bytes()),
+                    None => continue,
+                }
+            }
+            incoming = in_rx.recv() => match incoming {
+                Some(Incoming::Text(text)) => match serde_json::from_str::<ClientMessage>(&text) {
+                    Ok(ClientMessage::Subscribe(filter)) => {
+                        state.filter = filter.normalize();
+                        let snap = Snapshot::capture(&store.read());
+                        encode_frame(OP_TEXT, state.snapshot(&snap).to_string().as_bytes())
+                    }
+                    Err(e) => {
+                        debug!("Ignoring WebSocket message: {}", e);
+                        continue;
+                    }
+                },
+                Some(Incoming::Ping(payload)) => encode_frame(OP_PONG, &payload),
+                Some(Incoming::Close) | None => {
+                    let _ = writer.write_all(&encode_frame(OP_CLOSE, &[])).await;
+                    break Ok(());
+                }
+            },
+        };
+        if let Err(e) = writer.write_all(&out).await {
+            break Err(e);
+        }
+    };
+    reader_handle.abort();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handshake_key() {
+        let hex: String = sha1(b"abc").iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(hex, "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(base64(b"ab"), "YWI=");
+        // Example from RFC 6455 section 1.3
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[tokio::test]
+    async fn test_read_masked_frame() {
+        let payload = b"{\"type\":\"subscribe\"}";
+        let mask = [0x37, 0xFA, 0x21, 0x3D];
+        let mut data = vec![0x81, 0x80 | payload.len() as u8];
+        data.extend_from_slice(&mask);
+        data.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+
+        let frame = read_frame(&mut &data[..]).await.unwrap();
+        assert_eq!(frame, Frame { fin: true, opcode: OP_TEXT, payload: payload.to_vec() });
+
+        // Unmasked client frames are refused
+        assert!(read_frame(&mut &encode_frame(OP_TEXT, b"x")[..]).await.is_err());
+        assert_eq!(encode_frame(OP_TEXT, &[0; 300])[..4], [0x81, 126, 0x01, 0x2C]);
+    }
+
+    #[test]
+    fn test_filter() {
+        let ac = json!({"hex": "4840d6", "lat": 52.0, "lon": 179.5}).as_object().unwrap().clone();
+        let no_pos = json!({"hex": "40621d"}).as_object().unwrap().clone();
+
+        assert!(Filter::from_query("").matches(&no_pos));
+        assert!(Filter::from_query("icao=4840D6,abcdef").matches(&ac));
+        assert!(!Filter::from_query("icao=4840D6").matches(&no_pos));
+        // Box across the antimeridian
+        let bbox = Filter::from_query("bbox=50,170,55,-170");
+        assert!(bbox.matches(&ac));
+        assert!(!bbox.matches(&no_pos));
+        assert!(!Filter::from_query("bbox=50,0,55,10").matches(&ac));
+    }
+
+    #[test]
+    fn test_incremental_updates() {
+        let snap = |aircraft: Vec<Value>| Snapshot {
+            now: 0.0,
+            messages: 0,
+            aircraft: aircraft.iter().map(|ac| ac.as_object().unwrap().clone()).collect(),
+        };
+        let mut state = ClientState::default();
+        let first = state.snapshot(&snap(vec![
+            json!({"hex": "4840d6", "alt_baro": 38000, "squawk": "1000", "seen": 0.1}),
+            json!({"hex": "40621d", "alt_baro": 21000, "seen": 0.2}),
+        ]));
+        assert_eq!(first["aircraft"].as_array().unwrap().len(), 2);
+
+        // Only aging: nothing to send
+        assert_eq!(
+            state.update(&snap(vec![
+                json!({"hex": "4840d6", "alt_baro": 38000, "squawk": "1000", "seen": 0.5}),
+                json!({"hex": "40621d", "alt_baro": 21000, "seen": 0.6}),
+            ])),
+            None
+        );
+
+        let update = state
+            .update(&snap(vec![
+                json!({"hex": "4840d6", "alt_baro": 38025, "seen": 0.0}),
+                json!({"hex": "a1b2c3", "alt_baro": 5000, "seen": 0.0}),
+            ]))
+            .unwrap();
+        assert_eq!(
+            update["aircraft"],
+            json!([
+                {"hex": "4840d6", "alt_baro": 38025, "squawk": null, "seen": 0.0},
+                {"hex": "a1b2c3", "alt_baro": 5000, "seen": 0.0},
+            ])
+        );
+        assert_eq!(update["removed"], json!(["40621d"]));
+    }
+}